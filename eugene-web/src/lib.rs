@@ -1,6 +1,7 @@
 use eugene::lints::lint;
 use eugene::output::LintReport;
 use eugene::parse_scripts;
+use eugene::rule_set::builtin_rules;
 
 pub mod webapp;
 
@@ -8,6 +9,15 @@ pub fn lint_scripts<S: AsRef<str>>(input: S) -> anyhow::Result<Vec<LintReport>>
     let files = parse_scripts::break_into_files(input.as_ref())?;
     files
         .into_iter()
-        .map(|(name, sql)| Ok(lint(name.map(|s| s.to_string()), sql, &[], true, &[])?))
+        .map(|(name, sql)| {
+            Ok(lint(
+                name.map(|s| s.to_string()),
+                sql,
+                &[],
+                true,
+                &[],
+                builtin_rules(),
+            )?)
+        })
         .collect()
 }
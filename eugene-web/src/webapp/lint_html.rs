@@ -49,8 +49,14 @@ pub(crate) async fn lint_html(
         exclamation: EXCLAMATIONS[choice],
     };
     for (name, sql) in scripts {
-        let report: eugene::Result<LintReport> =
-            eugene::lints::lint(name.map(|s| s.to_string()), sql, &[], true, &[]);
+        let report: eugene::Result<LintReport> = eugene::lints::lint(
+            name.map(|s| s.to_string()),
+            sql,
+            &[],
+            true,
+            &[],
+            eugene::rule_set::builtin_rules(),
+        );
         match report {
             Err(eugene::error::Error {
                 inner: eugene::error::InnerError::SqlText(syntax_error),
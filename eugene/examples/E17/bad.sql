@@ -0,0 +1,15 @@
+-- 1.sql
+create table events
+(
+    id         bigint generated always as identity,
+    created_at timestamptz not null
+) partition by range (created_at);
+
+create table events_2024 partition of events
+    for values from ('2024-01-01') to ('2025-01-01');
+
+-- 2.sql
+set local lock_timeout = '2s';
+-- eugene: ignore E6
+-- this index stays invalid until every partition has an attached index
+create index events_created_at_idx on only events (created_at);
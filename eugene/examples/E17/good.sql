@@ -0,0 +1,21 @@
+-- 1.sql
+create table events
+(
+    id         bigint generated always as identity,
+    created_at timestamptz not null
+) partition by range (created_at);
+
+create table events_2024 partition of events
+    for values from ('2024-01-01') to ('2025-01-01');
+
+-- 2.sql
+create index concurrently events_2024_created_at_idx
+    on events_2024 (created_at);
+
+-- 3.sql
+set local lock_timeout = '2s';
+-- eugene: ignore E6
+-- only creates the index on the parent, it is valid once every partition is attached
+create index events_created_at_idx on only events (created_at);
+alter index events_created_at_idx
+    attach partition events_2024_created_at_idx;
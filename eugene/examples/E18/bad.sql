@@ -0,0 +1,13 @@
+-- 1.sql
+create table authors (
+    id integer generated always as identity
+        primary key,
+    name text not null
+);
+
+-- 2.sql
+set local lock_timeout = '2s';
+alter table authors
+    add column email text;
+-- pg_sleep stands in for a slow backfill of the new column
+select pg_sleep(1.1);
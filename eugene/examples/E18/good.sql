@@ -0,0 +1,17 @@
+-- 1.sql
+create table authors (
+    id integer generated always as identity
+        primary key,
+    name text not null
+);
+
+-- 2.sql
+set local lock_timeout = '2s';
+alter table authors
+    add column email text;
+
+-- 3.sql
+-- Backfill in small batches, each in its own transaction
+update authors
+    set email = name || '@example.com'
+    where id <= 1000 and email is null;
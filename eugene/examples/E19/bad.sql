@@ -0,0 +1,13 @@
+-- 1.sql
+create table authors (
+    id integer generated always as identity
+        primary key,
+    name text not null
+);
+
+-- 2.sql
+set local lock_timeout = '2s';
+alter table authors
+    set (autovacuum_vacuum_scale_factor = 0.05);
+alter table authors
+    add column email text;
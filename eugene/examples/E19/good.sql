@@ -0,0 +1,16 @@
+-- 1.sql
+create table authors (
+    id integer generated always as identity
+        primary key,
+    name text not null
+);
+
+-- 2.sql
+set local lock_timeout = '2s';
+alter table authors
+    add column email text;
+
+-- 3.sql
+set local lock_timeout = '2s';
+alter table authors
+    set (autovacuum_vacuum_scale_factor = 0.05);
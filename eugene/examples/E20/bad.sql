@@ -0,0 +1,20 @@
+-- 1.sql
+create table orders (
+    id integer generated always as identity
+        primary key
+);
+create table order_lines (
+    id integer generated always as identity
+        primary key,
+    order_id integer not null
+        constraint order_lines_order_id_fkey references orders (id)
+);
+create index order_lines_order_id_idx on order_lines (order_id);
+
+-- 2.sql
+set local lock_timeout = '2s';
+alter table order_lines
+    add column note text;
+-- eugene: ignore E4
+alter table orders
+    add column note text;
@@ -0,0 +1,12 @@
+-- 1.sql
+create table authors (
+    id serial primary key,
+    name text not null
+);
+
+-- 2.sql
+set local lock_timeout = '2s';
+alter table authors
+    alter column id drop default;
+alter table authors
+    alter column id add generated always as identity;
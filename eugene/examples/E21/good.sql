@@ -0,0 +1,12 @@
+-- 1.sql
+create table authors (
+    id serial primary key,
+    name text not null
+);
+
+-- 2.sql
+create table publishers (
+    id integer generated always as identity
+        primary key,
+    name text not null
+);
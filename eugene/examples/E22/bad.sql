@@ -0,0 +1,12 @@
+-- 1.sql
+create table authors (
+    id integer generated always as identity
+        primary key,
+    name text not null,
+    email text not null default ''
+);
+
+-- 2.sql
+set local lock_timeout = '2s';
+alter table authors
+    alter column email drop default;
@@ -0,0 +1,14 @@
+-- 1.sql
+create table authors (
+    id integer generated always as identity
+        primary key,
+    name text not null
+);
+
+-- 2.sql
+set local lock_timeout = '2s';
+alter table authors
+    add column full_name text;
+
+-- 3.sql
+update authors set full_name = name;
@@ -8,5 +8,5 @@ create table authors (
 -- 2.sql
 set local lock_timeout = '2s';
 alter table authors
-    add column email text not null;
+    add column email text not null default '';
 select count(*) from authors;
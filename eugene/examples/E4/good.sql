@@ -8,7 +8,7 @@ create table authors (
 -- 2.sql
 set local lock_timeout = '2s';
 alter table authors
-    add column email text not null;
+    add column email text not null default '';
 
 -- 3.sql
 select count(*) from authors;
@@ -8,7 +8,7 @@ create table authors(
 
 -- 2.sql
 set lock_timeout = '2s';
--- eugene: ignore E2
+-- eugene: ignore E2, E22
 alter table authors
   alter column name set not null,
   alter column email set not null;
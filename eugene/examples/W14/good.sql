@@ -9,8 +9,8 @@ create unique index concurrently
 
 -- 3.sql
 set local lock_timeout = '2s';
--- eugene: ignore E2
--- This is a demo of W14, so we can ignore E2 instead of the
+-- eugene: ignore E2, E22
+-- This is a demo of W14, so we can ignore E2 and E22 instead of the
 -- multi-step migration to make the column NOT NULL safely
 alter table authors
     alter column name set not null;
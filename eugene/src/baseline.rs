@@ -0,0 +1,188 @@
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::ContextualResult;
+use crate::output::output_format::Hint;
+use crate::output::{FullTraceData, LintReport};
+
+/// A set of accepted findings, so that only new findings make `eugene` fail.
+///
+/// Findings are identified by the script name, a hash of the statement text and the hint ID,
+/// so they still match when statements move to other lines in the script.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Baseline {
+    pub findings: BTreeSet<Finding>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+pub struct Finding {
+    pub file: String,
+    pub statement_hash: String,
+    pub hint_id: String,
+}
+
+impl Finding {
+    pub fn new(file: &str, sql: &str, hint_id: &str) -> Self {
+        Finding {
+            file: file.to_string(),
+            statement_hash: statement_hash(sql),
+            hint_id: hint_id.to_string(),
+        }
+    }
+}
+
+/// Hash of the statement text with whitespace normalized and comment lines removed, so that
+/// reformatting indentation or line breaks, or adding comments does not invalidate the baseline.
+///
+/// This uses 64 bit FNV-1a, which is stable across platforms and rust versions.
+pub fn statement_hash(sql: &str) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let mut first = true;
+    let words = sql
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("--"))
+        .flat_map(|line| line.split_whitespace());
+    for word in words {
+        if !first {
+            hash ^= b' ' as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        first = false;
+        for byte in word.bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    format!("{hash:016x}")
+}
+
+impl Baseline {
+    /// Read a baseline file in JSON format
+    pub fn from_file<P: AsRef<Path>>(path: P) -> crate::Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(format!("Failed to read baseline file {path:?}"))?;
+        serde_json::from_str(&contents).with_context(format!("Invalid baseline file {path:?}"))
+    }
+
+    /// Write the baseline to a file in JSON format
+    pub fn write_to_file<P: AsRef<Path>>(&self, path: P) -> crate::Result<()> {
+        let path = path.as_ref();
+        let contents = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, contents)
+            .with_context(format!("Failed to write baseline file {path:?}"))
+    }
+
+    pub fn contains(&self, file: &str, sql: &str, hint_id: &str) -> bool {
+        self.findings.contains(&Finding::new(file, sql, hint_id))
+    }
+
+    fn add_hints(&mut self, file: &str, sql: &str, hints: &[Hint]) {
+        for hint in hints {
+            self.findings.insert(Finding::new(file, sql, &hint.id));
+        }
+    }
+
+    fn retain_new(&self, file: &str, sql: &str, hints: &mut Vec<Hint>) {
+        hints.retain(|hint| !self.contains(file, sql, &hint.id));
+    }
+
+    /// Add all findings in `report` to the baseline
+    pub fn add_lint_report(&mut self, report: &LintReport) {
+        let name = report.name.clone().unwrap_or_default();
+        for statement in report.statements.iter() {
+            self.add_hints(&name, &statement.sql, &statement.triggered_rules);
+        }
+    }
+
+    /// Add all findings in `trace` to the baseline
+    pub fn add_trace(&mut self, trace: &FullTraceData) {
+        let name = trace.name.clone().unwrap_or_default();
+        for statement in trace.statements.iter() {
+            self.add_hints(&name, &statement.sql, &statement.triggered_rules);
+        }
+    }
+
+    /// Remove findings that are in the baseline from `report`
+    pub fn filter_lint_report(&self, report: &mut LintReport) {
+        let name = report.name.clone().unwrap_or_default();
+        for statement in report.statements.iter_mut() {
+            self.retain_new(&name, &statement.sql, &mut statement.triggered_rules);
+        }
+        report.update_passed_all_checks();
+    }
+
+    /// Remove findings that are in the baseline from `trace`
+    pub fn filter_trace(&self, trace: &mut FullTraceData) {
+        let name = trace.name.clone().unwrap_or_default();
+        for statement in trace.statements.iter_mut() {
+            self.retain_new(&name, &statement.sql, &mut statement.triggered_rules);
+        }
+        trace.update_passed_all_checks();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lints::lint;
+    use crate::rule_set::builtin_rules;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_statement_hash_ignores_whitespace() {
+        assert_eq!(
+            statement_hash("alter table books\n  add column data json"),
+            statement_hash("alter table books add column data json")
+        );
+        assert_eq!(
+            statement_hash("-- eugene: ignore E9\nalter table books add column data json"),
+            statement_hash("alter table books add column data json")
+        );
+        assert_ne!(
+            statement_hash("alter table books add column data json"),
+            statement_hash("alter table books add column meta json")
+        );
+    }
+
+    #[test]
+    fn test_baseline_survives_line_shifts_and_filters_new_findings() {
+        let old = lint(
+            Some("V1__init.sql".to_string()),
+            "alter table books add column data json;",
+            &[],
+            false,
+            &[],
+            builtin_rules(),
+        )
+        .unwrap();
+        let mut baseline = Baseline::default();
+        baseline.add_lint_report(&old);
+        let baseline: Baseline =
+            serde_json::from_str(&serde_json::to_string(&baseline).unwrap()).unwrap();
+
+        let mut report = lint(
+            Some("V1__init.sql".to_string()),
+            "set lock_timeout = '2s';\n\n-- new comment\nalter table books add column data json;\nalter table books add column meta json;",
+            &[],
+            false,
+            &[],
+            builtin_rules(),
+        )
+        .unwrap();
+        baseline.filter_lint_report(&mut report);
+        assert!(!report.passed_all_checks);
+        assert!(report.statements[1].triggered_rules.is_empty());
+        assert_eq!(report.statements[2].triggered_rules[0].id, "E3");
+        assert!(report.has_errors());
+
+        // Findings that are left as warnings do not fail the report
+        for hint in report.statements[2].triggered_rules.iter_mut() {
+            hint.severity = crate::hint_data::Severity::Warning;
+        }
+        baseline.filter_lint_report(&mut report);
+        assert!(report.passed_all_checks);
+    }
+}
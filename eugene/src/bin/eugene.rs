@@ -2,22 +2,32 @@ use anyhow::{anyhow, Context, Result};
 use clap::{CommandFactory, Parser, Subcommand};
 use clap_complete::generate;
 use clap_complete::Shell::{Bash, Elvish, Fish, PowerShell, Zsh};
+use eugene::baseline::Baseline;
+use eugene::config::Config;
 use eugene::git::{GitFilter, GitMode};
-use eugene::output::output_format::GenericHint;
+use eugene::lints::naming::NamingConventions;
+use eugene::lock_budget::LockBudget;
+use eugene::output::output_format::{FullTraceData, GenericHint, VersionDifference};
 use eugene::output::{DetailedLockMode, LockModesWrapper, TerseLockMode};
 use eugene::pg_types::lock_modes;
 use eugene::pgpass::read_pgpass_file;
+use eugene::rule_set::RuleSet;
 use eugene::script_discovery::{script_filters, SortMode};
+use eugene::seed::SeedConfig;
 use eugene::tempserver::TempServer;
+use eugene::tracing::probes::Prober;
+use eugene::tracing::TraceOptions;
 use eugene::{
-    output, parse_placeholders, perform_trace, read_script, script_discovery, ClientSource,
-    WithClient,
+    output, parse_placeholders, perform_trace, read_script, script_discovery, trace_cache,
+    ClientSource, SqlScript, WithClient,
 };
+use eugene::{schema_dump, seed, table_stats};
 use itertools::Itertools;
 use postgres::Client;
 use regex::Regex;
 use serde::Serialize;
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
 #[derive(Parser)]
 #[command(name = "eugene")]
@@ -98,6 +108,29 @@ struct TraceAndLintOptions {
     /// See https://docs.rs/regex/latest/regex/#syntax
     #[arg(long = "skip", default_value = None)]
     skip: Vec<String>,
+
+    /// Path to a JSON config file with ignores and severity overrides for script paths
+    ///
+    /// For example, to ignore E3 and only warn about E9 for legacy scripts:
+    ///
+    /// {"overrides": [{"paths": ["db/legacy/**"], "ignore": ["E3"], "severity": {"E9": "warning"}}]}
+    ///
+    /// Scripts only fail the check if they trigger hints with severity `error`, the default.
+    #[arg(long = "config")]
+    config: Option<String>,
+
+    /// Path to a baseline file with accepted findings, only new findings are reported
+    ///
+    /// Findings are identified by script name, statement text and hint ID, so they
+    /// still match if the statement moves to another line.
+    #[arg(long = "baseline")]
+    baseline: Option<String>,
+
+    /// Write all findings to this baseline file, and exit successfully
+    ///
+    /// Use `--baseline` with the same file later to only fail on new findings.
+    #[arg(long = "write-baseline")]
+    write_baseline: Option<String>,
 }
 
 impl TraceAndLintOptions {
@@ -110,6 +143,24 @@ impl TraceAndLintOptions {
     fn ignored_hints(&self) -> Vec<&str> {
         self.ignored_hints.iter().map(|s| s.as_str()).collect_vec()
     }
+    fn config(&self) -> eugene::Result<Config> {
+        match self.config.as_ref() {
+            Some(path) => Config::from_file(path),
+            None => Ok(Config::default()),
+        }
+    }
+    fn baseline(&self) -> eugene::Result<Baseline> {
+        match self.baseline.as_ref() {
+            Some(path) => Baseline::from_file(path),
+            None => Ok(Baseline::default()),
+        }
+    }
+    fn write_baseline(&self, baseline: &Baseline) -> eugene::Result<()> {
+        match self.write_baseline.as_ref() {
+            Some(path) => baseline.write_to_file(path),
+            None => Ok(()),
+        }
+    }
     fn sort_mode(&self) -> eugene::Result<SortMode> {
         self.sort_mode.as_str().try_into()
     }
@@ -165,6 +216,22 @@ struct Trace {
     /// Supply it more than once to add multiple options.
     #[arg(long = "initdb")]
     initdb_options: Vec<String>,
+    /// Directory with `initdb`, `pg_ctl` and `postgres` for the temporary server
+    ///
+    /// Supply it more than once to trace the scripts on each postgres version, and report the
+    /// statements that trigger different hints on different versions:
+    ///
+    /// `eugene trace --pg-bin /usr/lib/postgresql/14/bin --pg-bin /usr/lib/postgresql/16/bin`
+    #[arg(long = "pg-bin", conflicts_with = "disable_temp_postgres")]
+    pg_bin: Vec<PathBuf>,
+    /// Keep the temporary server in this directory between runs, to skip `initdb` and old scripts
+    ///
+    /// With `--git-diff`, the schema after the scripts that come before the first changed script is
+    /// saved as a template database. Those scripts are not reported on. The template is keyed by a
+    /// hash of those scripts and the initdb options, and later runs with the same scripts start from
+    /// the template instead of tracing them again. Without `--git-diff`, all scripts are traced.
+    #[arg(long = "cache-dir", conflicts_with = "disable_temp_postgres")]
+    cache_dir: Option<PathBuf>,
     #[command(flatten)]
     connection_settings: ProvidedConnectionSettings,
     /// Commit at the end of the transaction.
@@ -175,6 +242,52 @@ struct Trace {
     /// Show locks that are normally not in conflict with application code.
     #[arg(short = 'e', long = "extra", default_value_t = false)]
     extra: bool,
+    /// Run probe queries against locked tables from another connection after each statement
+    ///
+    /// Each table that the migration holds a lock on is probed with `SELECT`, `INSERT`, `UPDATE` and
+    /// `SELECT FOR UPDATE`, and the output shows which of them were blocked.
+    #[arg(long = "probe", default_value_t = false)]
+    probe: bool,
+    /// `lock_timeout` in milliseconds for each probe query, probes that time out are blocked
+    #[arg(long = "probe-lock-timeout", default_value_t = 50)]
+    probe_lock_timeout: u64,
+    /// Record the locks each statement waits for, and which backends block it
+    ///
+    /// A second connection polls `pg_stat_activity` and `pg_blocking_pids` while each statement
+    /// runs. Use it with `--disable-temporary` against a staging database, to see whether a
+    /// migration queues behind long-running transactions.
+    #[arg(long = "monitor-waits", default_value_t = false)]
+    monitor_waits: bool,
+    /// Insert this many synthetic rows into each table before tracing each script
+    ///
+    /// This makes durations of rewrites, index builds and validations realistic. Use `seed` in
+    /// the config file to set the number of rows per table. Requires `--commit` or the
+    /// temporary server, since the rows are committed.
+    #[arg(long = "seed-rows")]
+    seed_rows: Option<u64>,
+    /// Load a `pg_dump --schema-only` file into the temporary server before tracing
+    ///
+    /// Use this to trace only new scripts, instead of replaying all migrations. The dump should be
+    /// made with `--no-owner --no-privileges`, since the temporary server has no other roles.
+    #[arg(long = "schema-dump", conflicts_with = "disable_temp_postgres")]
+    schema_dump: Option<String>,
+    /// Load the schema of the database given by `-U`, `-d`, `-H` and `-p` into the temporary server
+    ///
+    /// This runs `pg_dump --schema-only --no-owner --no-privileges` against the reference database,
+    /// which is never modified, and traces the scripts against a copy of its schema.
+    #[arg(
+        long = "schema-from-db",
+        default_value_t = false,
+        conflicts_with_all = ["disable_temp_postgres", "schema_dump"]
+    )]
+    schema_from_db: bool,
+    /// Read table sizes and activity from the database given by `-U`, `-d`, `-H` and `-p`
+    ///
+    /// The locks in the report show the size and write rate of each table in the reference
+    /// database. Hints for statements that only take dangerous locks on small tables become
+    /// warnings, use `impact` in the config file to set what counts as small.
+    #[arg(long = "stats-from-db", default_value_t = false)]
+    stats_from_db: bool,
 }
 
 #[derive(Subcommand)]
@@ -193,7 +306,7 @@ enum Commands {
     /// Reads $PGPASS for password to postgres, if ~/.pgpass is not found.
     ///
     /// `eugene trace` exits with failure if any problems are detected.
-    Trace(Trace),
+    Trace(Box<Trace>),
     /// List postgres lock modes
     Modes {
         /// Output format, json
@@ -258,6 +371,11 @@ pub struct HintContainer {
     hints: Vec<GenericHint>,
 }
 
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct VersionDifferenceContainer {
+    version_differences: Vec<VersionDifference>,
+}
+
 impl TryFrom<&str> for TraceFormat {
     type Error = anyhow::Error;
 
@@ -280,21 +398,6 @@ enum GetClient {
     Connect(ClientSource),
 }
 
-impl TryFrom<&Trace> for GetClient {
-    type Error = anyhow::Error;
-
-    fn try_from(value: &Trace) -> std::result::Result<Self, Self::Error> {
-        if value.disable_temp_postgres {
-            Ok(GetClient::Connect((&value.connection_settings).try_into()?))
-        } else {
-            Ok(GetClient::TempDb(TempServer::new(
-                &value.postgres_options,
-                &value.initdb_options,
-            )?))
-        }
-    }
-}
-
 impl WithClient for GetClient {
     fn with_client<T>(
         &mut self,
@@ -305,6 +408,228 @@ impl WithClient for GetClient {
             GetClient::Connect(settings) => settings.with_client(f),
         }
     }
+
+    fn new_client(&self) -> eugene::Result<Client> {
+        match self {
+            GetClient::TempDb(temp) => temp.new_client(),
+            GetClient::Connect(settings) => settings.new_client(),
+        }
+    }
+}
+
+/// Trace all scripts with `client_source` and print a report for each of them
+///
+/// Returns the traces of the scripts that the git filter allows, after applying the config
+/// and `baseline`. `server_version` labels the reports when tracing on several postgres versions.
+#[allow(clippy::too_many_arguments)]
+fn trace_scripts(
+    trace_opts: &Trace,
+    mut client_source: GetClient,
+    scripts: &[SqlScript],
+    ends_migration: bool,
+    schema_dump: Option<&str>,
+    server_version: Option<&str>,
+    baseline: &Baseline,
+    new_baseline: &mut Baseline,
+) -> Result<Vec<FullTraceData>> {
+    let commit = trace_opts.commit || !trace_opts.disable_temp_postgres;
+    let format = trace_opts.opts.format()?;
+    if let Some(dump) = schema_dump {
+        schema_dump::restore_schema(&mut client_source, dump)?;
+    }
+    let mut stats_source: Option<ClientSource> = if trace_opts.stats_from_db {
+        Some((&trace_opts.connection_settings).try_into()?)
+    } else {
+        None
+    };
+
+    let skip = trace_opts
+        .opts
+        .skip
+        .iter()
+        .map(|s| Ok(Regex::new(s.as_str())?))
+        .collect::<Result<Vec<_>>>()?;
+    let ignored = trace_opts.opts.ignored_hints();
+    let filter = trace_opts.opts.git_filter()?;
+    let config = trace_opts.opts.config()?;
+    let mut rules = RuleSet::default();
+    if let Some(budget) = config.lock_budget.clone() {
+        rules = rules.replace_trace_hint(budget);
+    }
+    if let Some(order) = config.lock_order.clone() {
+        rules = rules.replace_trace_hint(order);
+    }
+    let seed = seed_config(trace_opts, &config);
+    if !seed.is_empty() && !commit {
+        return Err(anyhow!(
+            "Seeding tables commits rows, use --commit or the temporary server"
+        ));
+    }
+    let mut options = TraceOptions::new(&rules)
+        .with_skip(skip)
+        .with_commit(commit)
+        .with_monitor_waits(trace_opts.monitor_waits)
+        .with_track_writes(!seed.is_empty() || stats_source.is_some());
+    if trace_opts.probe {
+        options = options.with_prober(Prober::new(
+            client_source.new_client()?,
+            trace_opts.probe_lock_timeout,
+        ));
+    }
+    let mut traces = vec![];
+    for (ix, script) in scripts.iter().enumerate() {
+        let name = script.name.as_str();
+        let ignored = config.ignored_hints(name, &ignored);
+        let seeded_tables = if seed.is_empty() {
+            vec![]
+        } else {
+            client_source
+                .in_transaction(true, |tx| seed::seed_tables(tx, &seed))
+                .map_err(|e| anyhow!("Error seeding tables before {name}: {e}"))?
+        };
+        options = options
+            .with_ignored_hints(&ignored)
+            .with_final(ends_migration && ix + 1 == scripts.len());
+        let trace = perform_trace(script, &mut client_source, &options)
+            .map_err(|e| anyhow!("Error tracing {name}: {e}"))?;
+        if filter.allows(name) {
+            let mut full_trace = output::full_trace_data(
+                &trace,
+                output::Settings::new(!trace_opts.extra, trace_opts.opts.skip_summary),
+            );
+            full_trace.seeded_tables = seeded_tables;
+            full_trace.server_version = server_version.map(str::to_string);
+            if let Some(source) = stats_source.as_mut() {
+                let relations = table_stats::relations_in_trace(&full_trace);
+                let stats = source
+                    .with_client(|client| table_stats::fetch_table_stats(client, &relations))
+                    .map_err(|e| anyhow!("Error reading table statistics: {e}"))?;
+                let thresholds = config.impact.clone().unwrap_or_default();
+                table_stats::apply_table_stats(&mut full_trace, &stats, &thresholds);
+            }
+            config.apply_to_trace(&mut full_trace);
+            new_baseline.add_trace(&full_trace);
+            baseline.filter_trace(&mut full_trace);
+            let report = match format {
+                TraceFormat::Json => full_trace.to_pretty_json(),
+                TraceFormat::Plain => full_trace.to_plain_text(),
+                TraceFormat::Markdown => full_trace.to_markdown(),
+            }?;
+            if !report.trim().is_empty() {
+                println!("{}", report);
+            }
+            traces.push(full_trace);
+        }
+    }
+    Ok(traces)
+}
+
+fn seed_config(trace_opts: &Trace, config: &Config) -> SeedConfig {
+    let mut seed = config.seed.clone().unwrap_or_default();
+    if let Some(rows) = trace_opts.seed_rows {
+        seed.rows = rows;
+    }
+    seed
+}
+
+/// The number of scripts before the ones under review, which `--cache-dir` saves a template for
+///
+/// These are the scripts before the first changed one with `--git-diff`, which are traced but not
+/// reported. Without `--git-diff` every script is reported, so none can be skipped. The last
+/// script is always traced.
+fn applied_script_count(trace_opts: &Trace, scripts: &[SqlScript]) -> Result<usize> {
+    if trace_opts.opts.git_diff.is_none() {
+        return Ok(0);
+    }
+    let last = scripts.len().saturating_sub(1);
+    let filter = trace_opts.opts.git_filter()?;
+    Ok(scripts
+        .iter()
+        .position(|script| filter.allows(&script.name))
+        .map_or(last, |first| first.min(last)))
+}
+
+/// Trace `scripts` on a temporary server started from `pg_bin`, and return its version and the traces
+///
+/// With `--cache-dir`, the server is kept between runs, and with `--git-diff`, the unchanged scripts
+/// before the ones under review are restored from a template database instead of traced.
+/// `label_version` adds the server version to the reports.
+fn trace_on_temp_server(
+    trace_opts: &Trace,
+    pg_bin: Option<&Path>,
+    scripts: &[SqlScript],
+    schema_dump: Option<&str>,
+    label_version: bool,
+    baseline: &Baseline,
+    new_baseline: &mut Baseline,
+) -> Result<(String, Vec<FullTraceData>)> {
+    let Some(cache_dir) = trace_opts.cache_dir.as_deref() else {
+        let mut server = TempServer::new(
+            &trace_opts.postgres_options,
+            &trace_opts.initdb_options,
+            pg_bin,
+        )?;
+        let version = server.server_version()?;
+        let traces = trace_scripts(
+            trace_opts,
+            GetClient::TempDb(server),
+            scripts,
+            true,
+            schema_dump,
+            label_version.then_some(version.as_str()),
+            baseline,
+            new_baseline,
+        )?;
+        return Ok((version, traces));
+    };
+    let mut server = TempServer::cached(
+        &trace_cache::cluster_dir(cache_dir, &trace_opts.initdb_options, pg_bin),
+        &trace_opts.postgres_options,
+        &trace_opts.initdb_options,
+        pg_bin,
+    )?;
+    let version = server.server_version()?;
+    let label = label_version.then_some(version.as_str());
+    let applied = applied_script_count(trace_opts, scripts)?;
+    let seed = seed_config(trace_opts, &trace_opts.opts.config()?);
+    let seed_key = format!(
+        "{} {:?}",
+        seed.rows,
+        seed.tables.iter().sorted().collect_vec()
+    );
+    let key = trace_cache::cache_key(
+        trace_opts
+            .initdb_options
+            .iter()
+            .map(String::as_str)
+            .chain([schema_dump.unwrap_or_default(), seed_key.as_str()])
+            .chain(
+                scripts[..applied]
+                    .iter()
+                    .flat_map(|script| [script.name.as_str(), script.sql.as_str()]),
+            ),
+    );
+    let settings = server.connection_to(trace_cache::TRACE_DATABASE);
+    let traces = trace_cache::trace_from_template(
+        &mut server,
+        &settings,
+        &key,
+        scripts,
+        applied,
+        |settings, scripts, applied| {
+            trace_scripts(
+                trace_opts,
+                GetClient::Connect(settings),
+                scripts,
+                !applied,
+                applied.then_some(schema_dump).flatten(),
+                label,
+                baseline,
+                new_baseline,
+            )
+        },
+    )?;
+    Ok((version, traces))
 }
 
 pub fn main() -> Result<()> {
@@ -321,6 +646,14 @@ pub fn main() -> Result<()> {
                 .map(|s| Ok(Regex::new(s.as_str())?))
                 .collect::<Result<Vec<_>>>()?;
             let filter = opts.git_filter()?;
+            let config = opts.config()?;
+            let mut rules = RuleSet::default().with_custom_rules(config.rules.clone());
+            if let Some(naming) = config.naming.clone() {
+                rules = rules.replace_lint_rule(naming);
+            }
+            let baseline = opts.baseline()?;
+            let mut new_baseline = Baseline::default();
+            let ignored = opts.ignored_hints();
             for read_from in script_discovery::discover_all(
                 &opts.paths,
                 script_filters::never,
@@ -330,19 +663,19 @@ pub fn main() -> Result<()> {
             .filter(|r| filter.allows(r.name()))
             {
                 let script = read_script(&read_from, &placeholders)?;
-                let report = eugene::lints::lint(
+                let mut report = eugene::lints::lint(
                     Some(script.name.clone()),
                     script.sql,
-                    &opts.ignored_hints(),
+                    &config.ignored_hints(&script.name, &ignored),
                     opts.skip_summary,
                     &skip,
+                    &rules,
                 )
                 .map_err(|err| anyhow!("Error checking {}: {err}", script.name.as_str()))?;
-                failed = failed
-                    || report
-                        .statements
-                        .iter()
-                        .any(|stmt| !stmt.triggered_rules.is_empty());
+                config.apply_to_lint_report(&mut report);
+                new_baseline.add_lint_report(&report);
+                baseline.filter_lint_report(&mut report);
+                failed = failed || report.has_errors();
                 let out = match format {
                     TraceFormat::Json => Ok(serde_json::to_string_pretty(&report)?),
                     TraceFormat::Plain => output::templates::lint_text(&report),
@@ -353,70 +686,108 @@ pub fn main() -> Result<()> {
                 }
             }
 
-            if failed && !opts.accept_failures {
+            opts.write_baseline(&new_baseline)?;
+            if failed && !opts.accept_failures && opts.write_baseline.is_none() {
                 Err(anyhow!("Some checks failed"))
             } else {
                 Ok(())
             }
         }
         Some(Commands::Trace(trace_opts)) => {
-            let commit = trace_opts.commit || !trace_opts.disable_temp_postgres;
             let format = trace_opts.opts.format()?;
-            let mut client_source: GetClient = (&trace_opts).try_into()?;
-
-            let mut failed = false;
-            let skip = trace_opts
-                .opts
-                .skip
-                .iter()
-                .map(|s| Ok(Regex::new(s.as_str())?))
-                .collect::<Result<Vec<_>>>()?;
+            let schema_dump = if let Some(path) = &trace_opts.schema_dump {
+                Some(schema_dump::read_schema_dump(path)?)
+            } else if trace_opts.schema_from_db {
+                let reference: ClientSource = (&trace_opts.connection_settings).try_into()?;
+                Some(schema_dump::dump_schema(&reference)?)
+            } else {
+                None
+            };
+            let baseline = trace_opts.opts.baseline()?;
+            let mut new_baseline = Baseline::default();
             let placeholders = trace_opts.opts.placeholders()?;
-
-            let script_source = script_discovery::discover_all(
+            let scripts = script_discovery::discover_all(
                 &trace_opts.opts.paths,
                 script_filters::skip_downgrade_and_repeatable,
                 trace_opts.opts.sort_mode()?,
-            )?;
-            if !commit && script_source.len() > 1 {
+            )?
+            .iter()
+            .map(|read_from| read_script(read_from, &placeholders))
+            .collect::<eugene::Result<Vec<_>>>()?;
+            let commit = trace_opts.commit || !trace_opts.disable_temp_postgres;
+            if !commit && scripts.len() > 1 {
                 return Err(anyhow!(
                     "{} scripts detected, use --commit if you want to trace them in sequence",
-                    script_source.len()
+                    scripts.len()
                 ));
             }
-            let last_script = script_source.len() - 1;
-            let ignored = trace_opts.opts.ignored_hints();
-            let filter = trace_opts.opts.git_filter()?;
-            for (ix, read_from) in script_source.into_iter().enumerate() {
-                let script = read_script(&read_from, &placeholders)?;
-                let name = script.name.as_str();
-                let trace = perform_trace(
-                    &script,
-                    &mut client_source,
-                    &ignored,
-                    commit,
-                    &skip,
-                    ix == last_script,
-                )
-                .map_err(|e| anyhow!("Error tracing {name}: {e}"))?;
-                if filter.allows(name) {
-                    let full_trace = output::full_trace_data(
-                        &trace,
-                        output::Settings::new(!trace_opts.extra, trace_opts.opts.skip_summary),
-                    );
-                    failed = failed || !trace.success();
+
+            let traces = if trace_opts.disable_temp_postgres {
+                trace_scripts(
+                    &trace_opts,
+                    GetClient::Connect((&trace_opts.connection_settings).try_into()?),
+                    &scripts,
+                    true,
+                    schema_dump.as_deref(),
+                    None,
+                    &baseline,
+                    &mut new_baseline,
+                )?
+            } else if trace_opts.pg_bin.is_empty() {
+                let (_, traces) = trace_on_temp_server(
+                    &trace_opts,
+                    None,
+                    &scripts,
+                    schema_dump.as_deref(),
+                    false,
+                    &baseline,
+                    &mut new_baseline,
+                )?;
+                traces
+            } else {
+                let mut runs = vec![];
+                for pg_bin in trace_opts.pg_bin.iter() {
+                    let run = trace_on_temp_server(
+                        &trace_opts,
+                        Some(pg_bin),
+                        &scripts,
+                        schema_dump.as_deref(),
+                        true,
+                        &baseline,
+                        &mut new_baseline,
+                    )
+                    .map_err(|e| anyhow!("Error tracing with postgres from {pg_bin:?}: {e}"))?;
+                    runs.push(run);
+                }
+                if runs.len() > 1 {
+                    let differences = output::version_differences(&runs);
+                    let versions = runs.iter().map(|(v, _)| v.clone()).collect_vec();
                     let report = match format {
-                        TraceFormat::Json => full_trace.to_pretty_json(),
-                        TraceFormat::Plain => full_trace.to_plain_text(),
-                        TraceFormat::Markdown => full_trace.to_markdown(),
-                    }?;
+                        TraceFormat::Json => {
+                            serde_json::to_string_pretty(&VersionDifferenceContainer {
+                                version_differences: differences,
+                            })?
+                        }
+                        TraceFormat::Plain => {
+                            output::templates::version_differences_text(&differences)
+                        }
+                        TraceFormat::Markdown => {
+                            output::templates::version_differences_markdown(&versions, &differences)
+                        }
+                    };
                     if !report.trim().is_empty() {
                         println!("{}", report);
                     }
                 }
-            }
+                runs.into_iter().flat_map(|(_, traces)| traces).collect()
+            };
 
-            if failed && !trace_opts.opts.accept_failures {
+            trace_opts.opts.write_baseline(&new_baseline)?;
+            let failed = traces.iter().any(|trace| trace.has_errors());
+            if failed
+                && !trace_opts.opts.accept_failures
+                && trace_opts.opts.write_baseline.is_none()
+            {
                 Err(anyhow!("Some checks failed"))
             } else {
                 Ok(())
@@ -439,12 +810,13 @@ pub fn main() -> Result<()> {
             Ok(())
         }
         Some(Commands::Hints { .. }) => {
-            let hints: Vec<_> = eugene::hint_data::ALL
-                .iter()
-                .copied()
-                .map(GenericHint::from)
-                .collect();
-            let hints = HintContainer { hints };
+            let hints = HintContainer {
+                // W16 and E18 are only checked with `naming` and `lock_budget` config, but are listed anyway
+                hints: RuleSet::default()
+                    .with_lint_rule(NamingConventions::default())
+                    .with_trace_hint(LockBudget::default())
+                    .generic_hints(),
+            };
             println!("{}", serde_json::to_string_pretty(&hints)?);
             Ok(())
         }
@@ -14,6 +14,17 @@ pub enum LintAction<'a> {
     Continue,
 }
 
+impl LintAction<'_> {
+    /// True if the rule with `id` should be checked
+    pub fn allows(&self, id: &str) -> bool {
+        match self {
+            LintAction::SkipAll => false,
+            LintAction::Skip(ids) => !ids.contains(&id),
+            LintAction::Continue => true,
+        }
+    }
+}
+
 static EUGENE_COMMENT_REGEX: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"-- eugene: ([^\n]+)").expect("Failed to compile regex"));
 /// Detect `sql` containing a comment with an instruction for eugene
@@ -41,15 +52,11 @@ pub fn find_comment_action(sql: &str) -> crate::Result<LintAction> {
     }
 }
 
-pub fn filter_rules<'a, T: HintId + 'static>(
+pub fn filter_rules<'a, 'r: 'a, T: HintId + ?Sized + 'r>(
     filter: &'a LintAction<'a>,
-    rules: impl Iterator<Item = &'static T> + 'a,
-) -> impl Iterator<Item = &'static T> + 'a {
-    rules.filter(move |rule| match filter {
-        LintAction::SkipAll => false,
-        LintAction::Skip(ids) => !ids.contains(&rule.id()),
-        LintAction::Continue => true,
-    })
+    rules: impl Iterator<Item = &'r T> + 'a,
+) -> impl Iterator<Item = &'r T> + 'a {
+    rules.filter(move |rule| filter.allows(rule.id()))
 }
 
 #[cfg(test)]
@@ -0,0 +1,309 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::error::{ContextualError, ContextualResult, InnerError};
+use crate::hint_data::{data_by_id, Severity};
+use crate::lints::custom::CustomRule;
+use crate::lints::naming::NamingConventions;
+use crate::lock_budget::LockBudget;
+use crate::lock_order::LockOrder;
+use crate::output::output_format::Hint;
+use crate::output::{FullTraceData, LintReport};
+use crate::seed::SeedConfig;
+use crate::table_stats::ImpactThresholds;
+
+/// Settings for `eugene lint` and `eugene trace` that are read from a JSON file.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Ignores and severities that apply to scripts with paths matching a glob
+    #[serde(default)]
+    pub overrides: Vec<PathOverride>,
+    /// Lint rules that are checked in addition to the built-in rules
+    #[serde(default)]
+    pub rules: Vec<CustomRule>,
+    /// Patterns for names of new constraints and indexes, checked by hint W16
+    #[serde(default)]
+    pub naming: Option<NamingConventions>,
+    /// Synthetic rows to insert into tables before tracing each script
+    #[serde(default)]
+    pub seed: Option<SeedConfig>,
+    /// Tables below these thresholds in the reference database only get warnings, with `--stats-from-db`
+    #[serde(default)]
+    pub impact: Option<ImpactThresholds>,
+    /// Limits on how long strong locks may be held, checked by hint E18
+    #[serde(default)]
+    pub lock_budget: Option<LockBudget>,
+    /// The order that related tables should be locked in, checked by hint E20
+    #[serde(default)]
+    pub lock_order: Option<LockOrder>,
+}
+
+/// Override the ignored hints or the severity of hints for scripts matching any of `paths`.
+///
+/// `*` matches anything but `/`, `**` matches anything and `?` matches a single character.
+/// Patterns are matched against the end of the script path, so `db/legacy/**` matches
+/// both `db/legacy/V1__init.sql` and `/home/me/repo/db/legacy/V1__init.sql`.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PathOverride {
+    pub paths: Vec<Glob>,
+    #[serde(default)]
+    pub ignore: Vec<String>,
+    #[serde(default)]
+    pub severity: HashMap<String, Severity>,
+}
+
+/// A glob pattern for script paths, compiled to a regex once when it is read
+#[derive(Debug, Clone)]
+pub struct Glob {
+    pattern: String,
+    regex: Regex,
+}
+
+impl Glob {
+    pub fn new(pattern: &str) -> crate::Result<Self> {
+        Ok(Glob {
+            pattern: pattern.to_string(),
+            regex: glob_to_regex(pattern)?,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// True if the end of `path` matches the pattern
+    pub fn matches(&self, path: &str) -> bool {
+        self.regex.is_match(&path.replace('\\', "/"))
+    }
+}
+
+/// Globs are equal if they have the same pattern
+impl PartialEq for Glob {
+    fn eq(&self, other: &Self) -> bool {
+        self.pattern == other.pattern
+    }
+}
+
+impl Eq for Glob {}
+
+impl<'de> Deserialize<'de> for Glob {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        Glob::new(&pattern).map_err(serde::de::Error::custom)
+    }
+}
+
+impl Config {
+    /// Read a configuration file in JSON format
+    pub fn from_file<P: AsRef<Path>>(path: P) -> crate::Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(format!("Failed to read config file {path:?}"))?;
+        Self::from_json(&contents).with_context(format!("Invalid config file {path:?}"))
+    }
+
+    /// Parse a configuration from a JSON string, checking that all globs, rules and patterns are valid
+    pub fn from_json(json: &str) -> crate::Result<Self> {
+        let config: Config = serde_json::from_str(json)?;
+        if let Some(naming) = &config.naming {
+            naming.validate()?;
+        }
+        if let Some(budget) = &config.lock_budget {
+            budget.validate()?;
+        }
+        for (ix, rule) in config.rules.iter().enumerate() {
+            rule.validate()?;
+            if data_by_id(&rule.id).is_some() || config.rules[..ix].iter().any(|r| r.id == rule.id)
+            {
+                return Err(InnerError::InvalidCustomRule(rule.id.clone())
+                    .with_context(format!("Rule ID {} is already in use", rule.id)));
+            }
+        }
+        Ok(config)
+    }
+
+    fn overrides_for(&self, script: &str) -> Vec<&PathOverride> {
+        self.overrides
+            .iter()
+            .filter(|o| o.paths.iter().any(|p| p.matches(script)))
+            .collect()
+    }
+
+    /// Hint IDs to ignore for `script`, including the ones in `ignored_hints`
+    pub fn ignored_hints<'a>(&'a self, script: &str, ignored_hints: &[&'a str]) -> Vec<&'a str> {
+        let mut out = ignored_hints.to_vec();
+        for id in self
+            .overrides_for(script)
+            .into_iter()
+            .flat_map(|o| o.ignore.iter().map(|id| id.as_str()))
+        {
+            if !out.contains(&id) {
+                out.push(id);
+            }
+        }
+        out
+    }
+
+    /// The severity of `hint_id` in `script`, if it is overridden. The last matching override wins.
+    pub fn severity(&self, script: &str, hint_id: &str) -> Option<Severity> {
+        self.overrides_for(script)
+            .into_iter()
+            .rev()
+            .find_map(|o| o.severity.get(hint_id).copied())
+    }
+
+    fn apply_severity(&self, script: &str, hints: &mut [Hint]) {
+        for hint in hints.iter_mut() {
+            if let Some(severity) = self.severity(script, &hint.id) {
+                hint.severity = severity;
+            }
+        }
+    }
+
+    /// Set the severity of all triggered rules in `report` according to the overrides
+    pub fn apply_to_lint_report(&self, report: &mut LintReport) {
+        let name = report.name.clone().unwrap_or_default();
+        for statement in report.statements.iter_mut() {
+            self.apply_severity(&name, &mut statement.triggered_rules);
+        }
+        report.update_passed_all_checks();
+    }
+
+    /// Set the severity of all triggered rules in `trace` according to the overrides
+    pub fn apply_to_trace(&self, trace: &mut FullTraceData) {
+        let name = trace.name.clone().unwrap_or_default();
+        for statement in trace.statements.iter_mut() {
+            self.apply_severity(&name, &mut statement.triggered_rules);
+        }
+        trace.update_passed_all_checks();
+    }
+}
+
+/// Translate a glob pattern to a regex that matches the end of a path
+fn glob_to_regex(pattern: &str) -> crate::Result<Regex> {
+    let pattern = pattern.trim_start_matches("./");
+    let mut re = String::from("(?:^|/)");
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                // `**/` may also match no directories at all
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    re.push_str("(?:.*/)?");
+                } else {
+                    re.push_str(".*");
+                }
+            }
+            '*' => re.push_str("[^/]*"),
+            '?' => re.push_str("[^/]"),
+            c => re.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    re.push('$');
+    Regex::new(&re)
+        .map_err(|e| InnerError::InvalidGlob(pattern.to_string()).with_context(format!("{e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn glob_matches(pattern: &str, path: &str) -> bool {
+        Glob::new(pattern).unwrap().matches(path)
+    }
+
+    #[test]
+    fn test_glob_matching() {
+        assert!(glob_matches("db/legacy/**", "db/legacy/V1__init.sql"));
+        assert!(glob_matches(
+            "db/legacy/**",
+            "/repo/db/legacy/old/V1__init.sql"
+        ));
+        assert!(glob_matches("./db/legacy/*.sql", "db/legacy/V1__init.sql"));
+        assert!(!glob_matches(
+            "db/legacy/*.sql",
+            "db/legacy/old/V1__init.sql"
+        ));
+        assert!(!glob_matches("db/legacy/**", "db/current/V1__init.sql"));
+        assert!(!glob_matches("legacy/**", "db/notlegacy/V1__init.sql"));
+        assert!(glob_matches("**/seed_*.sql", "seed_books.sql"));
+        assert!(glob_matches("V?__*.sql", "migrations/V1__init.sql"));
+    }
+
+    #[test]
+    fn test_overrides_ignore_and_severity() {
+        let config = Config::from_json(
+            r#"{
+              "overrides": [
+                {"paths": ["db/legacy/**"], "ignore": ["E3"], "severity": {"E9": "warning"}},
+                {"paths": ["db/**/V2__*.sql"], "severity": {"E9": "error"}}
+              ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.ignored_hints("db/legacy/V1__init.sql", &["E4"]),
+            vec!["E4", "E3"]
+        );
+        assert_eq!(config.ignored_hints("db/V1__init.sql", &["E4"]), vec!["E4"]);
+        assert_eq!(
+            config.severity("db/legacy/V1__init.sql", "E9"),
+            Some(Severity::Warning)
+        );
+        assert_eq!(
+            config.severity("db/legacy/V2__next.sql", "E9"),
+            Some(Severity::Error)
+        );
+        assert_eq!(config.severity("db/legacy/V1__init.sql", "E3"), None);
+    }
+
+    #[test]
+    fn test_severity_overrides_decide_whether_report_passed() {
+        let mut report = crate::lints::lint(
+            Some("db/legacy/V1__init.sql".to_string()),
+            "create index books_title_idx on books(title);",
+            &[],
+            false,
+            &[],
+            crate::rule_set::builtin_rules(),
+        )
+        .unwrap();
+        assert!(!report.passed_all_checks);
+        let severity = report.statements[0]
+            .triggered_rules
+            .iter()
+            .map(|hint| format!(r#""{}": "warning""#, hint.id))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let config = Config::from_json(&format!(
+            r#"{{"overrides": [{{"paths": ["db/legacy/**"], "severity": {{{severity}}}}}]}}"#
+        ))
+        .unwrap();
+        config.apply_to_lint_report(&mut report);
+        assert!(report.passed_all_checks);
+        assert!(!report.statements[0].triggered_rules.is_empty());
+    }
+
+    #[test]
+    fn test_rejects_custom_rule_with_builtin_id() {
+        let rule =
+            r#"{"id": "E3", "name": "json", "message": "json", "when": {"column_type": "json"}}"#;
+        assert!(Config::from_json(&format!(r#"{{"rules": [{rule}]}}"#)).is_err());
+        let rule = rule.replace("E3", "C3");
+        assert!(Config::from_json(&format!(r#"{{"rules": [{rule}]}}"#)).is_ok());
+        assert!(Config::from_json(&format!(r#"{{"rules": [{rule}, {rule}]}}"#)).is_err());
+    }
+
+    #[test]
+    fn test_rejects_unknown_fields() {
+        assert!(Config::from_json(r#"{"overides": []}"#).is_err());
+    }
+}
@@ -91,11 +91,14 @@ pub enum InnerError {
     InvalidSortMode,
     UnresolvedPlaceHolder,
     UnableToInitDb,
+    UnableToDumpSchema,
+    TransactionsRequireCommit,
     AstInterpretationError(AstError),
     Template(RenderError),
     BadCommentInstruction(String),
     ScriptParsingError(String),
     InvalidContype(char),
+    UnknownSchemaObjectKind(String),
     InvalidLock(InvalidLockError),
     PostgresError(postgres::Error),
     MissingRequiredCommand(String),
@@ -116,6 +119,10 @@ pub enum InnerError {
     GitExecutionError,
     GitError,
     InvalidPath,
+    InvalidGlob(String),
+    InvalidCustomRule(String),
+    InvalidNamingConvention(String),
+    InvalidLockBudget(String),
 }
 
 impl From<serde_json::Error> for InnerError {
@@ -1,5 +1,11 @@
+use serde::{Deserialize, Serialize};
+
+/// Link to the documentation of a built-in hint, or an empty string for other IDs
 pub fn hint_url<S: AsRef<str>>(id: S) -> String {
-    format!("https://kaveland.no/eugene/hints/{}/", id.as_ref())
+    match data_by_id(id.as_ref()) {
+        Some(hint) => format!("https://kaveland.no/eugene/hints/{}/", hint.id),
+        None => String::new(),
+    }
 }
 
 pub trait HintId {
@@ -9,6 +15,15 @@ pub trait HintId {
     }
 }
 
+/// How a triggered hint should be treated, `Error` fails the command while `Warning` is only reported
+#[derive(Debug, Default, Eq, PartialEq, Clone, Copy, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    #[default]
+    Error,
+    Warning,
+}
+
 pub struct StaticHintData {
     pub id: &'static str,
     pub name: &'static str,
@@ -162,6 +177,85 @@ pub const FOREIGN_KEY_NOT_BACKED_BY_INDEX: StaticHintData = StaticHintData {
     bad_example: include_str!("../examples/E15/bad.sql"),
     good_example: Some(include_str!("../examples/E15/good.sql")),
 };
+pub const UNNAMED_CONSTRAINT_OR_INDEX: StaticHintData = StaticHintData {
+    id: "W16",
+    name: "Creating a constraint or index without an explicit name",
+    condition: "A constraint or index was added to an existing table without a name, or with a name that does not match the configured naming convention",
+    effect: "Postgres generates names that may differ between environments, which can break later migrations that refer to the constraint or index by name",
+    workaround: "Name the constraint or index explicitly, for example `ALTER TABLE ... ADD CONSTRAINT name ...` or `CREATE INDEX name ON ...`",
+    bad_example: include_str!("../examples/W16/bad.sql"),
+    good_example: Some(include_str!("../examples/W16/good.sql")),
+};
+
+pub const INVALID_INDEX_LEFT_BEHIND: StaticHintData = StaticHintData {
+    id: "E17",
+    name: "Invalid index",
+    condition: "An index is not valid at the end of the migration, for example after a failed `CREATE INDEX CONCURRENTLY`",
+    effect: "Postgres keeps the invalid index up to date on every write, but queries can not use it",
+    workaround: "Drop the index with `DROP INDEX CONCURRENTLY` and create it again, or attach an index for every partition to an index on a partitioned table",
+    bad_example: include_str!("../examples/E17/bad.sql"),
+    good_example: Some(include_str!("../examples/E17/good.sql")),
+};
+
+pub const LOCK_BUDGET_EXCEEDED: StaticHintData = StaticHintData {
+    id: "E18",
+    name: "Lock budget exceeded",
+    condition: "A strong lock was held for longer than the lock budget, or a statement ran for too long while holding one",
+    effect: "Queries that need the locked tables queue up for as long as the lock is held, which can make the application unavailable",
+    workaround: "Split the migration into smaller transactions, or move slow statements out of the transaction that holds the lock",
+    bad_example: include_str!("../examples/E18/bad.sql"),
+    good_example: Some(include_str!("../examples/E18/good.sql")),
+};
+
+pub const LOCK_UPGRADE_HAZARD: StaticHintData = StaticHintData {
+    id: "E19",
+    name: "Upgrading a lock",
+    condition: "A statement took a stronger lock on a table that the transaction already held in `ShareUpdateExclusiveLock` or a stronger mode",
+    effect: "Another transaction that holds the weaker lock and wants the stronger one can deadlock with the migration",
+    workaround: "Take the strongest lock first, with `LOCK TABLE` at the start of the transaction, or run the statements in separate transactions",
+    bad_example: include_str!("../examples/E19/bad.sql"),
+    good_example: Some(include_str!("../examples/E19/good.sql")),
+};
+
+pub const LOCKING_TABLES_OUT_OF_ORDER: StaticHintData = StaticHintData {
+    id: "E20",
+    name: "Locking tables out of order",
+    condition: "A dangerous lock was taken on a table that should be locked before another table that the transaction already holds a dangerous lock on",
+    effect: "Application transactions that lock the tables in the usual order can deadlock with the migration",
+    workaround: "Lock the tables in the canonical order, with `LOCK TABLE` at the start of the transaction",
+    bad_example: include_str!("../examples/E20/bad.sql"),
+    good_example: Some(include_str!("../examples/E20/good.sql")),
+};
+
+pub const COLUMN_VALUE_GENERATION_CHANGED: StaticHintData = StaticHintData {
+    id: "E21",
+    name: "Changing how a column generates values",
+    condition: "An existing column was made an identity column, stopped being one, or had a `nextval` default added, changed or removed",
+    effect: "Running inserts that rely on the old default can fail, and a new sequence can hand out keys that are already in use",
+    workaround: "Keep using the existing sequence, or move the new sequence past the largest value in the column before inserts use it, and stop relying on a default in the application before removing it",
+    bad_example: include_str!("../examples/E21/bad.sql"),
+    good_example: Some(include_str!("../examples/E21/good.sql")),
+};
+
+pub const COLUMN_REQUIRED_WITHOUT_DEFAULT: StaticHintData = StaticHintData {
+    id: "E22",
+    name: "Requiring a value that old application versions do not set",
+    condition: "A `NOT NULL` column without a default was added, a column without a default was changed to `NOT NULL`, or the default was dropped from a `NOT NULL` column",
+    effect: "Inserts from application versions that do not know about the column start failing, for example while a rolling deploy is in progress",
+    workaround: "Keep a default on the column until no running application version leaves out the column",
+    bad_example: include_str!("../examples/E22/bad.sql"),
+    good_example: Some(include_str!("../examples/E22/good.sql")),
+};
+
+pub const COLUMN_RENAMED: StaticHintData = StaticHintData {
+    id: "E23",
+    name: "Renaming a column",
+    condition: "A column in an existing table was renamed",
+    effect: "Queries from application versions that use the old name start failing, for example while a rolling deploy is in progress",
+    workaround: "Add a new column and copy the data over, then drop the old column after no running application version uses it",
+    bad_example: include_str!("../examples/E23/bad.sql"),
+    good_example: Some(include_str!("../examples/E23/good.sql")),
+};
 
 pub const ALL: &[&StaticHintData] = &[
     &VALIDATE_CONSTRAINT_WITH_LOCK,
@@ -179,6 +273,14 @@ pub const ALL: &[&StaticHintData] = &[
     &CREATING_ENUM,
     &ADD_PRIMARY_KEY_USING_INDEX,
     &FOREIGN_KEY_NOT_BACKED_BY_INDEX,
+    &UNNAMED_CONSTRAINT_OR_INDEX,
+    &INVALID_INDEX_LEFT_BEHIND,
+    &LOCK_BUDGET_EXCEEDED,
+    &LOCK_UPGRADE_HAZARD,
+    &LOCKING_TABLES_OUT_OF_ORDER,
+    &COLUMN_VALUE_GENERATION_CHANGED,
+    &COLUMN_REQUIRED_WITHOUT_DEFAULT,
+    &COLUMN_RENAMED,
 ];
 
 pub fn data_by_id<S: AsRef<str>>(id: S) -> Option<&'static StaticHintData> {
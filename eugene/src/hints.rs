@@ -1,20 +1,24 @@
 use crate::hint_data;
 use crate::hint_data::{HintId, StaticHintData};
+use crate::lock_order::LockOrder;
 use crate::output::output_format::Hint;
+use crate::pg_types::locks::LockableTarget;
 use itertools::Itertools;
+use postgres::types::Oid;
 use std::cmp::Reverse;
 
 use crate::pg_types::contype::Contype;
 use crate::pg_types::lock_modes::LockMode;
 use crate::pg_types::relkinds::RelKind;
-use crate::tracing::queries::ForeignKeyReference;
-use crate::tracing::tracer::StatementCtx;
+use crate::tracing::queries::{ColumnMetadata, ForeignKeyReference, InvalidIndex};
+use crate::tracing::tracer::{ModifiedColumn, StatementCtx};
 
-type HintFn = fn(&StatementCtx) -> Option<String>;
+pub type HintFn = fn(&StatementCtx) -> Option<String>;
 
+#[derive(Clone, Copy)]
 pub struct HintInfo {
-    meta: &'static StaticHintData,
-    render_help: HintFn,
+    pub(crate) meta: &'static StaticHintData,
+    pub(crate) render_help: HintFn,
 }
 
 impl HintId for HintInfo {
@@ -24,6 +28,10 @@ impl HintId for HintInfo {
 }
 
 impl HintInfo {
+    /// A hint with static metadata and a function that returns help text when it triggers
+    pub const fn new(meta: &'static StaticHintData, render_help: HintFn) -> Self {
+        HintInfo { meta, render_help }
+    }
     pub fn code(&self) -> &'static str {
         self.meta.id
     }
@@ -78,29 +86,29 @@ fn add_new_valid_constraint_help(sql_statement_trace: &StatementCtx) -> Option<S
     Some(help)
 }
 
-fn make_column_not_nullable_help(sql_statement_trace: &StatementCtx) -> Option<String> {
-    let (id, column) = sql_statement_trace
-        .altered_columns()
-        .find(|(_, column)| !column.new.nullable && column.old.nullable)?;
-
-    let already_constrained = sql_statement_trace
-        .constraints_on(id.oid)
+/// True if a valid `CHECK (column IS NOT NULL)` constraint on `oid` already requires a value
+fn has_valid_not_null_check(ctx: &StatementCtx, oid: Oid, column_name: &str) -> bool {
+    ctx.constraints_on(oid)
         .filter(|c| c.constraint_type == Contype::Check && c.valid)
         .any(|c| {
             c.expression
                 .as_ref()
                 .map(|e| {
-                    e.to_lowercase().contains(&format!(
-                        "{} is not null",
-                        column.old.column_name.to_lowercase()
-                    ))
+                    e.to_lowercase()
+                        .contains(&format!("{} is not null", column_name.to_lowercase()))
                 })
                 .unwrap_or(false)
-        });
+        })
+}
+
+fn make_column_not_nullable_help(sql_statement_trace: &StatementCtx) -> Option<String> {
+    let (id, column) = sql_statement_trace
+        .altered_columns()
+        .find(|(_, column)| !column.new.nullable && column.old.nullable)?;
 
     // postgres knows that the column is not null, so it doesn't need to check,
     // making this a safe alter column
-    if already_constrained {
+    if has_valid_not_null_check(sql_statement_trace, id.oid, &column.old.column_name) {
         return None;
     }
 
@@ -136,14 +144,15 @@ fn add_json_column(sql_statement_trace: &StatementCtx) -> Option<String> {
 fn running_statement_while_holding_access_exclusive(
     sql_statement_trace: &StatementCtx,
 ) -> Option<String> {
-    let lock = sql_statement_trace
+    let target = sql_statement_trace
         .locks_at_start()
-        .find(|lock| matches!(lock.mode, LockMode::AccessExclusive))?;
+        .filter(|lock| matches!(lock.mode, LockMode::AccessExclusive))
+        .find_map(|lock| lock.relation())?;
 
     let help = format!(
         "The statement is running while holding an `AccessExclusiveLock` on the {} `{}.{}`, \
                 blocking all other transactions from accessing it.",
-        lock.target.rel_kind, lock.target.schema, lock.target.object_name,
+        target.rel_kind, target.schema, target.object_name,
     );
     Some(help)
 }
@@ -172,9 +181,10 @@ fn type_change_requires_table_rewrite(sql_statement_trace: &StatementCtx) -> Opt
 fn new_index_on_existing_table_is_nonconcurrent(
     sql_statement_trace: &StatementCtx,
 ) -> Option<String> {
-    let lock = sql_statement_trace
+    let target = sql_statement_trace
         .new_locks_taken()
-        .find(|lock| matches!(lock.mode, LockMode::Share))?;
+        .filter(|lock| matches!(lock.mode, LockMode::Share))
+        .find_map(|lock| lock.relation())?;
     let index = sql_statement_trace
         .new_objects()
         .find(|obj| matches!(obj.rel_kind, RelKind::Index));
@@ -183,8 +193,8 @@ fn new_index_on_existing_table_is_nonconcurrent(
         "A new index was created on the table `{}.{}`. \
                 The index {}was created non-concurrently, which blocks all writes to the table. \
                 Use `CREATE INDEX CONCURRENTLY` to avoid blocking writes.",
-        lock.target.schema,
-        lock.target.object_name,
+        target.schema,
+        target.object_name,
         index
             .map(|obj| format!("`{}.{}` ", obj.schema, obj.object_name))
             .unwrap_or(String::new())
@@ -233,9 +243,10 @@ fn took_dangerous_lock_without_timeout(sql_statement_trace: &StatementCtx) -> Op
     } else {
         let lock = sql_statement_trace
             .new_locks_taken()
-            .filter(|lock| lock.mode.dangerous())
+            .filter(|lock| lock.maybe_dangerous())
             .sorted_by_key(|lock| lock.mode)
             .next_back()?;
+        let target = lock.relation()?;
         let blocked_queries = lock
             .mode
             .blocked_queries()
@@ -245,7 +256,7 @@ fn took_dangerous_lock_without_timeout(sql_statement_trace: &StatementCtx) -> Op
 
         let help = format!(
                     "The statement took `{}` on the {} `{}.{}` without a timeout. It blocks {} while waiting to acquire the lock.",
-                    lock.mode, lock.target.rel_kind, lock.target.schema, lock.target.object_name, blocked_queries.join(", "),
+                    lock.mode, target.rel_kind, target.schema, target.object_name, blocked_queries.join(", "),
                 );
         Some(help)
     }
@@ -256,17 +267,19 @@ fn rewrote_table_or_index(ctx: &StatementCtx) -> Option<String> {
         .rewritten_objects()
         .sorted_by_key(|obj| obj.rel_kind) // prioritize tables
         .find(|obj| matches!(obj.rel_kind, RelKind::Index | RelKind::Table))?;
-    let lock = ctx
+    let (lock, target) = ctx
         .locks_at_start()
-        .sorted_by_key(|lock| (Reverse(lock.mode), lock.target.rel_kind))
-        .find(|lock| lock.mode.dangerous())
+        .filter_map(|lock| Some((lock, lock.relation()?)))
+        .sorted_by_key(|(lock, target)| (Reverse(lock.mode), target.rel_kind))
+        .find(|(lock, _)| lock.maybe_dangerous())
         .or_else(|| {
             ctx.new_locks_taken()
-                .sorted_by_key(|lock| (Reverse(lock.mode), lock.target.rel_kind))
-                .find(|lock| lock.mode.dangerous())
+                .filter_map(|lock| Some((lock, lock.relation()?)))
+                .sorted_by_key(|(lock, target)| (Reverse(lock.mode), target.rel_kind))
+                .find(|(lock, _)| lock.maybe_dangerous())
         })?;
     let relkind_rewritten = rewritten.rel_kind.as_str();
-    let relkind_locked = lock.target.rel_kind.as_str();
+    let relkind_locked = target.rel_kind.as_str();
     let blocked_q = lock
         .mode
         .blocked_queries()
@@ -274,7 +287,7 @@ fn rewrote_table_or_index(ctx: &StatementCtx) -> Option<String> {
         .map(|q| format!("`{}`", q))
         .collect_vec()
         .join(", ");
-    let locked_obj = format!("{}.{}", lock.target.schema, lock.target.object_name);
+    let locked_obj = format!("{}.{}", target.schema, target.object_name);
     let rewritten_obj = format!("{}.{}", rewritten.schema_name, rewritten.object_name);
     let mode = lock.mode.to_db_str();
     let help = format!(
@@ -301,11 +314,255 @@ fn foreign_key_missing_index(ctx: &StatementCtx) -> Option<String> {
     }
 }
 
-/// All the hints eugene can check statement traces against
+fn invalid_index_left_behind(ctx: &StatementCtx) -> Option<String> {
+    let invalid = ctx.sql_statement_trace.invalid_indexes.iter().map(|index| {
+        let InvalidIndex {
+            schema_name, index_name, table_name, partitioned
+        } = index;
+        if *partitioned {
+            format!("The index `{schema_name}.{index_name}` on the partitioned table `{schema_name}.{table_name}` is invalid, \
+            because some partitions have no attached index. Create the missing indexes concurrently on the partitions, \
+            and attach them with `ALTER INDEX \"{schema_name}\".\"{index_name}\" ATTACH PARTITION ...`.")
+        } else {
+            let drop = format!("drop index concurrently if exists \"{schema_name}\".\"{index_name}\";");
+            format!("The index `{schema_name}.{index_name}` on `{schema_name}.{table_name}` is invalid, \
+            probably from a failed `CREATE INDEX CONCURRENTLY`. Fix the cause of the failure, then drop it and retry:\n```sql\n{drop}\n```\n")
+        }
+    }).join("\n\n");
+
+    if invalid.trim().is_empty() {
+        None
+    } else {
+        Some(invalid)
+    }
+}
+
+fn lock_upgrade_hazard(ctx: &StatementCtx) -> Option<String> {
+    let upgrades = ctx
+        .new_locks_taken()
+        .filter(|lock| lock.mode.dangerous())
+        .filter_map(|lock| Some((lock.mode, lock.relation()?)))
+        .filter(|(_, target)| matches!(target.rel_kind, RelKind::Table | RelKind::PartitionedTable))
+        .sorted_by_key(|(mode, _)| Reverse(*mode))
+        .unique_by(|(_, target)| target.oid)
+        .filter_map(|(mode, target)| {
+            let held = ctx
+                .locks_at_start()
+                .filter(|held| held.relation().is_some_and(|t| t.oid == target.oid))
+                .map(|held| held.mode)
+                .max()
+                // Application traffic holds the weaker modes all the time, so upgrading from
+                // them does not add to the risk of taking the stronger lock
+                .filter(|held| held >= &LockMode::ShareUpdateExclusive && held < &mode)?;
+            Some((mode, held, target))
+        })
+        .sorted_by_key(|(_, _, target)| (&target.schema, &target.object_name))
+        .map(|(mode, held, target)| {
+            let LockableTarget { schema, object_name, .. } = target;
+            format!(
+                "The statement took `{}` on the table `{schema}.{object_name}`, which the transaction already held in `{}`. \
+                Other transactions can lock the table in modes that do not conflict with `{}` in the meantime, and if one of them \
+                then waits for this transaction, the two will deadlock. \
+                Take the strongest lock first, at the start of the transaction:\n```sql\nlock table \"{schema}\".\"{object_name}\" in {} mode;\n```\n",
+                mode.to_db_str(),
+                held.to_db_str(),
+                held.to_db_str(),
+                mode.to_sql_str().to_lowercase(),
+            )
+        })
+        .join("\n\n");
+    if upgrades.is_empty() {
+        None
+    } else {
+        Some(upgrades)
+    }
+}
+
+fn uses_sequence(default: &Option<String>) -> bool {
+    default
+        .as_ref()
+        .is_some_and(|expr| expr.contains("nextval("))
+}
+
+fn identity_change(name: &str, old: &ColumnMetadata, new: &ColumnMetadata) -> Option<String> {
+    match (&old.identity, &new.identity) {
+        (None, Some(mode)) => {
+            let explicit = if mode == "ALWAYS" {
+                " Inserts that set the column explicitly will now fail."
+            } else {
+                ""
+            };
+            Some(format!(
+                "The column {name} was changed to `GENERATED {mode} AS IDENTITY`. The identity sequence \
+                does not continue from the values that are already in the column, so it can hand out keys \
+                that are in use unless it is restarted past the largest one.{explicit}"
+            ))
+        }
+        (Some(_), None) => Some(format!(
+            "The column {name} is no longer an identity column. Running inserts that leave out \
+            the column will now fail or insert `NULL`."
+        )),
+        (Some(old_mode), Some(new_mode)) if old_mode != new_mode && new_mode == "ALWAYS" => {
+            Some(format!(
+                "The column {name} was changed from `GENERATED {old_mode} AS IDENTITY` to \
+                `GENERATED ALWAYS AS IDENTITY`. Inserts that set the column explicitly will now fail."
+            ))
+        }
+        _ => None,
+    }
+}
+
+fn sequence_default_change(
+    name: &str,
+    old: &ColumnMetadata,
+    new: &ColumnMetadata,
+) -> Option<String> {
+    if old.default == new.default || !(uses_sequence(&old.default) || uses_sequence(&new.default)) {
+        return None;
+    }
+    match (&old.default, &new.default) {
+        (Some(old_default), None) => Some(format!(
+            "The default `{old_default}` was removed from the column {name}. Running inserts that \
+            leave out the column will now fail or insert `NULL`."
+        )),
+        (old_default, Some(new_default)) => Some(format!(
+            "The default of the column {name} was changed from `{}` to `{new_default}`. Make sure \
+            that the sequence starts after the largest value in the column, otherwise inserts can \
+            fail with duplicate keys.",
+            old_default.as_deref().unwrap_or("NULL"),
+        )),
+        (None, None) => None,
+    }
+}
+
+fn column_value_generation_changed(ctx: &StatementCtx) -> Option<String> {
+    let changes = ctx
+        .altered_columns()
+        .filter_map(|(_, ModifiedColumn { old, new })| {
+            let name = format!(
+                "`{}` in the table `{}.{}`",
+                new.column_name, new.schema_name, new.table_name
+            );
+            identity_change(&name, old, new).or_else(|| sequence_default_change(&name, old, new))
+        })
+        .join("\n\n");
+    if changes.is_empty() {
+        None
+    } else {
+        Some(changes)
+    }
+}
+
+fn has_generated_value(column: &ColumnMetadata) -> bool {
+    column.default.is_some() || column.identity.is_some() || column.generated.is_some()
+}
+
+fn column_required_without_default(ctx: &StatementCtx) -> Option<String> {
+    // Postgres refuses this on tables with rows, but it works on empty tables
+    let added = ctx
+        .added_columns()
+        .filter(|(id, column)| {
+            ctx.existed_before_transaction(id.oid)
+                && !column.nullable
+                && !has_generated_value(column)
+        })
+        .map(|(_, column)| {
+            format!(
+                "The `NOT NULL` column `{}` was added to the table `{}.{}` without a default. \
+                Inserts from application versions that do not set `{}` will fail.",
+                column.column_name, column.schema_name, column.table_name, column.column_name
+            )
+        });
+    let required = ctx
+        .altered_columns()
+        .filter(|(id, ModifiedColumn { old, new })| {
+            old.nullable
+                && !new.nullable
+                && !has_generated_value(new)
+                && !has_valid_not_null_check(ctx, id.oid, &old.column_name)
+        })
+        .map(|(_, ModifiedColumn { new, .. })| {
+            format!(
+                "The column `{}` in the table `{}.{}` was changed to `NOT NULL`, and has no default. \
+                Inserts from application versions that do not set `{}` will fail.",
+                new.column_name, new.schema_name, new.table_name, new.column_name
+            )
+        });
+    // Dropping a `nextval` default is reported by E21
+    let dropped = ctx
+        .altered_columns()
+        .map(|(_, column)| column)
+        .filter(|ModifiedColumn { old, new }| {
+            !new.nullable
+                && !has_generated_value(new)
+                && old.default.is_some()
+                && !uses_sequence(&old.default)
+        })
+        .map(|ModifiedColumn { old, new }| {
+            format!(
+                "The default `{}` was dropped from the `NOT NULL` column `{}` in the table `{}.{}`. \
+                Inserts from application versions that do not set `{}` will fail.",
+                old.default.as_deref().unwrap_or_default(),
+                new.column_name,
+                new.schema_name,
+                new.table_name,
+                new.column_name
+            )
+        });
+    let problems = added.chain(required).chain(dropped).join("\n\n");
+    if problems.is_empty() {
+        None
+    } else {
+        Some(problems)
+    }
+}
+
+fn column_renamed(ctx: &StatementCtx) -> Option<String> {
+    // Renaming a column to the name of a column that was dropped earlier in the transaction
+    // keeps the name that application versions use, like the documented safe pattern in E5
+    let renames = ctx
+        .altered_columns()
+        .map(|(_, column)| column)
+        .filter(|ModifiedColumn { old, new }| {
+            old.column_name != new.column_name
+                && !ctx.dropped_columns_in_transaction().any(|dropped| {
+                    dropped.schema_name == new.schema_name
+                        && dropped.table_name == new.table_name
+                        && dropped.column_name == new.column_name
+                })
+        })
+        .map(|ModifiedColumn { old, new }| {
+            format!(
+                "The column `{}` in the table `{}.{}` was renamed to `{}`. Queries from application \
+                versions that use `{}` will fail.",
+                old.column_name, new.schema_name, new.table_name, new.column_name, old.column_name
+            )
+        })
+        .join("\n\n");
+    if renames.is_empty() {
+        None
+    } else {
+        Some(renames)
+    }
+}
+
+fn locking_tables_out_of_order(ctx: &StatementCtx) -> Option<String> {
+    LockOrder::default().check_statement(ctx)
+}
+
+/// All the hints eugene checks statement traces against by default
 pub fn all_hints() -> &'static [HintInfo] {
     HINTS
 }
 
+/// Trace hints that are only checked when they are configured, see `LockBudget`
+const OPT_IN_HINTS: &[&StaticHintData] = &[&hint_data::LOCK_BUDGET_EXCEEDED];
+
+/// True if `id` is a hint that eugene can check statement traces against, by default or with config
+pub fn is_trace_hint(id: &str) -> bool {
+    HINTS.iter().any(|hint| hint.code() == id) || OPT_IN_HINTS.iter().any(|hint| hint.id == id)
+}
+
 /// Run all hints against a statement trace and return the ones that apply
 pub fn run_hints<'a>(trace: &'a StatementCtx) -> impl Iterator<Item = Hint> + 'a {
     HINTS.iter().filter_map(|hint| hint.check(trace))
@@ -354,6 +611,30 @@ pub const FK_MISSING_BACKIND_INDEX: HintInfo = HintInfo {
     meta: &hint_data::FOREIGN_KEY_NOT_BACKED_BY_INDEX,
     render_help: foreign_key_missing_index,
 };
+pub const INVALID_INDEX_LEFT_BEHIND: HintInfo = HintInfo {
+    meta: &hint_data::INVALID_INDEX_LEFT_BEHIND,
+    render_help: invalid_index_left_behind,
+};
+pub const LOCK_UPGRADE_HAZARD: HintInfo = HintInfo {
+    meta: &hint_data::LOCK_UPGRADE_HAZARD,
+    render_help: lock_upgrade_hazard,
+};
+pub const LOCKING_TABLES_OUT_OF_ORDER: HintInfo = HintInfo {
+    meta: &hint_data::LOCKING_TABLES_OUT_OF_ORDER,
+    render_help: locking_tables_out_of_order,
+};
+pub const COLUMN_VALUE_GENERATION_CHANGED: HintInfo = HintInfo {
+    meta: &hint_data::COLUMN_VALUE_GENERATION_CHANGED,
+    render_help: column_value_generation_changed,
+};
+pub const COLUMN_REQUIRED_WITHOUT_DEFAULT: HintInfo = HintInfo {
+    meta: &hint_data::COLUMN_REQUIRED_WITHOUT_DEFAULT,
+    render_help: column_required_without_default,
+};
+pub const COLUMN_RENAMED: HintInfo = HintInfo {
+    meta: &hint_data::COLUMN_RENAMED,
+    render_help: column_renamed,
+};
 
 /// All the hints eugene can check statement traces against
 const HINTS: &[HintInfo] = &[
@@ -368,6 +649,12 @@ const HINTS: &[HintInfo] = &[
     TOOK_DANGEROUS_LOCK_WITHOUT_TIMEOUT,
     REWROTE_TABLE_WHILE_HOLDING_DANGEROUS_LOCK,
     FK_MISSING_BACKIND_INDEX,
+    INVALID_INDEX_LEFT_BEHIND,
+    LOCK_UPGRADE_HAZARD,
+    LOCKING_TABLES_OUT_OF_ORDER,
+    COLUMN_VALUE_GENERATION_CHANGED,
+    COLUMN_REQUIRED_WITHOUT_DEFAULT,
+    COLUMN_RENAMED,
 ];
 
 #[cfg(test)]
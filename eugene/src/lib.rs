@@ -10,11 +10,12 @@
 use crate::error::{ContextualError, InnerError};
 use crate::script_discovery::ReadFrom;
 use crate::sqltext::sql_statements_with_line_no;
+use crate::tracing::monitor::LockMonitor;
 use crate::tracing::TxLockTracer;
+use log::warn;
 use postgres::{Client, NoTls, Transaction};
-use regex::Regex;
 use std::collections::HashMap;
-use tracing::trace_transaction;
+use tracing::{trace_concurrently_block, trace_transaction_block, TraceOptions};
 
 /// Static data for hints and lints, used to identify them in output or input.
 pub mod hint_data;
@@ -57,6 +58,33 @@ pub mod error;
 /// Utilities for invoking git
 pub mod git;
 
+/// Configuration file for overriding how hints are handled for some paths
+pub mod config;
+
+/// Sets of lint rules and trace hints, which library users can extend with their own
+pub mod rule_set;
+
+/// Baseline files for accepting existing findings, so only new findings fail the check
+pub mod baseline;
+
+/// Fill tables with synthetic rows before tracing, so statements have realistic cost
+pub mod seed;
+
+/// Bootstrap a database from a `pg_dump --schema-only` dump instead of replaying all migrations
+pub mod schema_dump;
+
+/// Size and activity of tables in a reference database, for estimating the impact of locks
+pub mod table_stats;
+
+/// Limits on how long strong locks may be held, configured in the config file
+pub mod lock_budget;
+
+/// The order that migrations should lock related tables in, to avoid deadlocks
+pub mod lock_order;
+
+/// Template databases in a cached temporary server, so repeated traces can skip replaying scripts
+pub mod trace_cache;
+
 pub mod utils {
     use std::path::Path;
 
@@ -128,11 +156,24 @@ impl ClientSource {
             client: None,
         }
     }
+    /// The same settings, but for connecting to `database`
+    pub fn for_database(&self, database: &str) -> Self {
+        ClientSource::new(
+            self.user.clone(),
+            database.to_string(),
+            self.host.clone(),
+            self.port,
+            self.password.clone(),
+        )
+    }
 }
 
 pub trait WithClient {
     fn with_client<T>(&mut self, f: impl FnOnce(&mut Client) -> Result<T>) -> Result<T>;
 
+    /// Open another connection to the same database, for running queries concurrently
+    fn new_client(&self) -> Result<Client>;
+
     fn in_transaction<T>(
         &mut self,
         commit: bool,
@@ -162,6 +203,10 @@ impl WithClient for ClientSource {
             f(self.client.as_mut().unwrap())
         }
     }
+
+    fn new_client(&self) -> Result<Client> {
+        Ok(Client::connect(self.connection_string().as_str(), NoTls)?)
+    }
 }
 /// Parse placeholders in the form of name=value into a map.
 pub fn parse_placeholders(placeholders: &[String]) -> Result<HashMap<&str, &str>> {
@@ -180,58 +225,100 @@ pub fn parse_placeholders(placeholders: &[String]) -> Result<HashMap<&str, &str>
 }
 
 /// Perform a lock trace of a SQL script and optionally commit the transaction, depending on
-/// trace_settings.
+/// `options`. Statements are checked against the trace hints in `options`.
+///
+/// The script is split into transactions at `BEGIN`, `COMMIT` and `ROLLBACK`, and each transaction
+/// is traced separately. Transactions that the script ends with `ROLLBACK` are rolled back.
+/// Without commit, statements outside of `BEGIN` and `COMMIT` are traced with the transaction
+/// next to them, `CONCURRENTLY` statements are skipped, and scripts with more than one explicit
+/// transaction are an error.
+///
+/// With a prober in `options`, probe queries run against the locked tables after each statement,
+/// from another connection.
 pub fn perform_trace<'a, T: WithClient>(
     script: &SqlScript,
     connection_settings: &mut T,
-    ignored_hints: &'a [&'a str],
-    commit: bool,
-    skip: &[Regex],
-    is_final: bool,
-) -> Result<TxLockTracer<'a>> {
+    options: &'a TraceOptions<'a>,
+) -> Result<Vec<TxLockTracer<'a>>> {
     let sql_statements = sql_statements_with_line_no(script.sql.as_str())?;
-    let all_concurrently = sql_statements
-        .iter()
-        .map(|(_, s)| s)
-        .all(sqltext::is_concurrently);
-    if all_concurrently && commit {
-        connection_settings.with_client(|client| {
-            for (_, s) in sql_statements.iter() {
-                let skip_this = skip.iter().any(|r| r.is_match(s));
-                if !skip_this {
-                    client.execute(*s, &[])?;
+    let mut blocks = sqltext::split_transactions(&sql_statements);
+    let commit = options.commit();
+    if !commit {
+        if blocks.iter().any(|block| block.concurrent) {
+            warn!(
+                "{} has CONCURRENTLY statements, which can not run in a transaction, so they are skipped. Use commit to trace them",
+                script.name
+            );
+        }
+        blocks = sqltext::join_implicit_blocks(blocks);
+    }
+    if blocks.len() > 1 && !commit {
+        return Err(InnerError::TransactionsRequireCommit.with_context(format!(
+            "{} has {} transactions, use commit to trace them in sequence",
+            script.name,
+            blocks.len()
+        )));
+    }
+    let mut monitor = if options.monitor_waits() || blocks.iter().any(|block| block.concurrent) {
+        Some(LockMonitor::new(connection_settings.new_client()?))
+    } else {
+        None
+    };
+    let last_block = blocks.len() - 1;
+    connection_settings.with_client(|client| {
+        let mut traces = vec![];
+        for (ix, block) in blocks.iter().enumerate() {
+            let is_final = options.is_final() && ix == last_block;
+            match monitor.as_mut() {
+                Some(monitor) if block.concurrent => {
+                    traces.push(trace_concurrently_block(
+                        Some(script.name.clone()),
+                        client,
+                        monitor,
+                        block.statements.iter().copied(),
+                        options,
+                        is_final,
+                    )?);
+                }
+                _ => {
+                    let mut tx = client.transaction()?;
+                    let mut trace = trace_transaction_block(
+                        Some(script.name.clone()),
+                        &mut tx,
+                        block.statements.iter().copied(),
+                        options,
+                        monitor.as_mut().filter(|_| options.monitor_waits()),
+                        is_final,
+                    )?;
+                    trace.rolled_back = block.rollback;
+                    if commit && !block.rollback {
+                        tx.commit()?;
+                    } else {
+                        tx.rollback()?;
+                    }
+                    traces.push(trace);
                 }
             }
-            Ok(())
-        })?;
-
-        Ok(TxLockTracer::tracer_for_concurrently(
-            Some(script.name.clone()),
-            sql_statements.iter().copied(),
-            ignored_hints,
-        ))
-    } else {
-        connection_settings.in_transaction(commit, |conn| {
-            trace_transaction(
-                Some(script.name.clone()),
-                conn,
-                sql_statements.iter().copied(),
-                ignored_hints,
-                skip,
-                is_final,
-            )
-        })
-    }
+        }
+        client.execute("RESET ALL", &[])?;
+        Ok(traces)
+    })
 }
 
 #[cfg(test)]
-/// Generate a new copy of the test_db database for testing.
-pub fn generate_new_test_db() -> String {
-    let mut pg_client = Client::connect(
-        "host=localhost dbname=postgres password=postgres user=postgres",
+/// Connect to a database on the local test server, eg. one from `generate_new_test_db`.
+pub fn connect_to_test_db(db_name: &str) -> Client {
+    Client::connect(
+        &format!("host=localhost dbname={db_name} password=postgres user=postgres"),
         NoTls,
     )
-    .unwrap();
+    .unwrap()
+}
+
+#[cfg(test)]
+/// Generate a new copy of the test_db database for testing.
+pub fn generate_new_test_db() -> String {
+    let mut pg_client = connect_to_test_db("postgres");
 
     pg_client
         .execute(
@@ -283,6 +370,95 @@ pub fn generate_new_test_db() -> String {
 
 #[cfg(test)]
 mod tests {
+    use crate::tracing::TraceOptions;
+    use crate::{generate_new_test_db, perform_trace, ClientSource, SqlScript};
+
+    #[test]
+    fn trace_splits_script_into_transactions() {
+        let mut source = ClientSource::new(
+            "postgres".to_string(),
+            generate_new_test_db(),
+            "localhost".to_string(),
+            5432,
+            "postgres".to_string(),
+        );
+        let script = SqlScript {
+            name: "transactions.sql".to_string(),
+            sql: "BEGIN;
+ALTER TABLE books ADD COLUMN meta jsonb;
+COMMIT;
+BEGIN;
+SELECT meta FROM books;
+ROLLBACK;"
+                .to_string(),
+        };
+        let options = TraceOptions::default().with_commit(true).with_final(true);
+        let traces = perform_trace(&script, &mut source, &options).unwrap();
+        assert_eq!(traces.len(), 2);
+        assert!(!traces[0].rolled_back);
+        assert!(traces[1].rolled_back);
+        // The second transaction does not hold the lock from the first one
+        assert!(traces[1]
+            .all_locks
+            .iter()
+            .all(|lock| !lock.maybe_dangerous()));
+
+        let options = TraceOptions::default().with_final(true);
+        let err = perform_trace(&script, &mut source, &options);
+        assert!(err.is_err());
+    }
+
+    #[test]
+    fn trace_without_commit_skips_concurrently_statements() {
+        let mut source = ClientSource::new(
+            "postgres".to_string(),
+            generate_new_test_db(),
+            "localhost".to_string(),
+            5432,
+            "postgres".to_string(),
+        );
+        let script = SqlScript {
+            name: "concurrently.sql".to_string(),
+            sql: "set lock_timeout = '2s';
+create index concurrently books_title_idx on books(title);
+alter table books add column concurrently_updated bool;"
+                .to_string(),
+        };
+        let options = TraceOptions::default().with_final(true);
+        let traces = perform_trace(&script, &mut source, &options).unwrap();
+        assert_eq!(traces.len(), 1);
+        assert_eq!(traces[0].statements.len(), 3);
+        assert!(traces[0].statements[1].locks_taken.is_empty());
+        assert!(!traces[0].statements[2].locks_taken.is_empty());
+    }
+
+    #[test]
+    fn trace_without_commit_joins_statements_before_begin_with_the_transaction() {
+        let mut source = ClientSource::new(
+            "postgres".to_string(),
+            generate_new_test_db(),
+            "localhost".to_string(),
+            5432,
+            "postgres".to_string(),
+        );
+        let script = SqlScript {
+            name: "lock_timeout.sql".to_string(),
+            sql: "set lock_timeout = '2s';
+BEGIN;
+alter table books add column meta jsonb;
+COMMIT;"
+                .to_string(),
+        };
+        let options = TraceOptions::default().with_final(true);
+        let traces = perform_trace(&script, &mut source, &options).unwrap();
+        assert_eq!(traces.len(), 1);
+        assert_eq!(traces[0].statements.len(), 2);
+        assert!(!traces[0].statements[1].locks_taken.is_empty());
+        // The lock timeout is set in the traced transaction, so E9 does not trigger
+        assert!(traces[0].triggered_hints[1]
+            .iter()
+            .all(|hint| hint.id != "E9"));
+    }
 
     #[test]
     fn lint_line_numbers_should_make_sense_ex2() {
@@ -1,6 +1,7 @@
-use crate::comments::filter_rules;
 pub use crate::lints::ast::StatementSummary;
-use crate::output::output_format::{LintReport, LintedStatement};
+use crate::output::output_format::{any_errors, LintReport, LintedStatement};
+use crate::rule_set;
+use crate::rule_set::RuleSet;
 use crate::sqltext;
 use itertools::Itertools;
 use regex::Regex;
@@ -8,6 +9,10 @@ use regex::Regex;
 /// The `ast` module provides a way to describe a parsed SQL statement in a structured way,
 /// using simpler trees than the ones provided by `pg_query`.
 pub mod ast;
+/// The `custom` module contains lint rules that are defined in configuration files
+pub mod custom;
+/// The `naming` module checks names of new constraints and indexes
+pub mod naming;
 /// The `rules` module contains lint rules that can be matched to `LintedStatement`
 pub mod rules;
 
@@ -72,6 +77,10 @@ impl<'a> LintContext<'a> {
     pub fn new(ctx: &'a TransactionState, statement: &'a StatementSummary) -> Self {
         LintContext { ctx, statement }
     }
+    /// The summary of the statement under linting
+    pub fn statement(&self) -> &'a StatementSummary {
+        self.statement
+    }
     /// Locks taken by the statement that were not created in the same transaction.
     pub fn locks_visible_outside_tx(&self) -> Vec<(&str, &str)> {
         self.statement
@@ -108,13 +117,15 @@ impl<'a> LintContext<'a> {
     }
 }
 
-/// Lint a SQL script and return a report with all matched lints for each statement.
+/// Lint a SQL script with the lint rules in `rules` and return a report with all matched lints
+/// for each statement.
 pub fn lint<S: AsRef<str>>(
     name: Option<String>,
     sql: S,
     ignored_lints: &[&str],
     skip_summary: bool,
     skip: &[Regex],
+    rules: &RuleSet,
 ) -> crate::Result<LintReport> {
     let statements = sqltext::sql_statements_with_line_no(sql.as_ref())?;
     let mut ctx = TransactionState::default();
@@ -133,12 +144,9 @@ pub fn lint<S: AsRef<str>>(
                     let matched_lints: Vec<_> = if skip_stmt {
                         vec![]
                     } else {
-                        filter_rules(&action, rules::all_rules())
-                            .filter(|rule| !ignored_lints.contains(&rule.id()))
-                            .filter_map(|rule| rule.check(lint_line))
-                            .collect()
+                        rules.lint_statement(&action, ignored_lints, lint_line)
                     };
-                    passed_all = passed_all && matched_lints.is_empty();
+                    passed_all = passed_all && !any_errors(&matched_lints);
 
                     lints.push(LintedStatement {
                         statement_number: no,
@@ -161,7 +169,7 @@ pub fn lint<S: AsRef<str>>(
 }
 
 pub fn anon_lint<S: AsRef<str>>(sql: S) -> crate::Result<LintReport> {
-    lint(None, sql, &[], false, &[])
+    lint(None, sql, &[], false, &[], rule_set::builtin_rules())
 }
 
 #[cfg(test)]
@@ -335,7 +343,7 @@ mod tests {
 
         let sql = "alter table books add constraint unique_title unique (title);";
 
-        let report = lint(None, sql, &[], false, &[regex]).unwrap();
+        let report = lint(None, sql, &[], false, &[regex], rule_set::builtin_rules()).unwrap();
 
         assert!(!matched_lint_rule(
             &report,
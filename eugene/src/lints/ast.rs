@@ -29,6 +29,7 @@ pub struct ColDefSummary {
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Constraint {
+    pub name: String,
     pub valid: bool,
     pub contype: ConstrType,
 }
@@ -125,10 +126,12 @@ impl AlterTableAction {
         match self {
             AlterTableAction::AddColumn { constraints, .. } => constraints.iter().any(predicate),
             AlterTableAction::AddConstraint {
+                name,
                 valid,
                 constraint_type,
                 ..
             } => predicate(&Constraint {
+                name: name.clone(),
                 valid: *valid,
                 contype: *constraint_type,
             }),
@@ -263,6 +266,7 @@ fn parse_alter_table_action(child: &AlterTableCmd) -> crate::Result<AlterTableAc
                         .map_err(|_| AstError::UnrecognizedConstraintType(cons.contype));
                     let valid = cons.initially_valid;
                     constraint_defs.push(Constraint {
+                        name: cons.conname.clone(),
                         valid,
                         contype: constraint_type?,
                     });
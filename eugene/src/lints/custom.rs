@@ -0,0 +1,350 @@
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::error::{ContextualError, InnerError};
+use crate::hint_data::{HintId, Severity};
+use crate::lints::ast::{AlterTableAction, StatementSummary};
+use crate::lints::LintContext;
+use crate::output::output_format::Hint;
+
+/// A lint rule defined in the configuration file, instead of in code.
+///
+/// The rule triggers for a statement when all of the conditions in `when` match it.
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CustomRule {
+    /// Unique ID for the rule, used for ignoring it and in output
+    pub id: String,
+    /// Short description of the problem
+    pub name: String,
+    /// Message shown for each triggered statement, may contain `{schema}`, `{name}` and `{table}`
+    pub message: String,
+    #[serde(default)]
+    pub condition: String,
+    #[serde(default)]
+    pub effect: String,
+    #[serde(default)]
+    pub workaround: String,
+    /// Link to documentation for the rule
+    #[serde(default)]
+    pub url: String,
+    #[serde(default)]
+    pub severity: Severity,
+    pub when: RuleCondition,
+}
+
+/// Conditions that must all match a statement for a `CustomRule` to trigger.
+///
+/// All patterns are regular expressions. `{table}` in `name_not_matching` is replaced by
+/// the name of the table the statement targets.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuleCondition {
+    /// The kind of statement, eg. `create_table`
+    pub statement: Option<StatementKind>,
+    /// Pattern for the schema, `public` is used when the statement has no schema
+    pub schema: Option<String>,
+    /// Pattern that the name of the created or altered object must match
+    pub name: Option<String>,
+    /// Pattern that the name of the created or altered object must not match
+    pub name_not_matching: Option<String>,
+    /// Pattern for the type of any new column or column type change, without `pg_catalog.`
+    pub column_type: Option<String>,
+    /// Triggers for `create_table` statements that do not define this column
+    pub missing_column: Option<String>,
+    /// Triggers for `alter_table` statements that have this action
+    pub action: Option<ActionKind>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatementKind {
+    CreateTable,
+    CreateTableAs,
+    CreateIndex,
+    AlterTable,
+    CreateEnum,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActionKind {
+    SetType,
+    SetNotNull,
+    AddConstraint,
+    AddColumn,
+}
+
+impl HintId for CustomRule {
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+/// The parts of a statement that custom rules can match on
+struct Target<'a> {
+    kind: StatementKind,
+    schema: &'a str,
+    name: &'a str,
+    table: &'a str,
+}
+
+fn target(statement: &StatementSummary) -> Option<Target> {
+    let (kind, schema, name, table) = match statement {
+        StatementSummary::CreateTable { schema, name, .. } => {
+            (StatementKind::CreateTable, schema.as_str(), name, name)
+        }
+        StatementSummary::CreateTableAs { schema, name } => {
+            (StatementKind::CreateTableAs, schema.as_str(), name, name)
+        }
+        StatementSummary::CreateIndex {
+            schema,
+            idxname,
+            target,
+            ..
+        } => (StatementKind::CreateIndex, schema.as_str(), idxname, target),
+        StatementSummary::AlterTable { schema, name, .. } => {
+            (StatementKind::AlterTable, schema.as_str(), name, name)
+        }
+        StatementSummary::CreateEnum { name } => (StatementKind::CreateEnum, "", name, name),
+        StatementSummary::Ignored | StatementSummary::LockTimeout => return None,
+    };
+    Some(Target {
+        kind,
+        schema: if schema.is_empty() { "public" } else { schema },
+        name,
+        table,
+    })
+}
+
+fn column_types(statement: &StatementSummary) -> Vec<&str> {
+    let types: Vec<&str> = match statement {
+        StatementSummary::CreateTable { columns, .. } => {
+            columns.iter().map(|c| c.type_name.as_str()).collect()
+        }
+        StatementSummary::AlterTable { actions, .. } => actions
+            .iter()
+            .filter_map(|action| match action {
+                AlterTableAction::SetType { type_name, .. }
+                | AlterTableAction::AddColumn { type_name, .. } => Some(type_name.as_str()),
+                _ => None,
+            })
+            .collect(),
+        _ => vec![],
+    };
+    types
+        .into_iter()
+        .map(|t| t.trim_start_matches("pg_catalog."))
+        .collect()
+}
+
+fn has_action(statement: &StatementSummary, kind: ActionKind) -> bool {
+    match statement {
+        StatementSummary::AlterTable { actions, .. } => actions.iter().any(|action| {
+            matches!(
+                (action, kind),
+                (AlterTableAction::SetType { .. }, ActionKind::SetType)
+                    | (AlterTableAction::SetNotNull { .. }, ActionKind::SetNotNull)
+                    | (
+                        AlterTableAction::AddConstraint { .. },
+                        ActionKind::AddConstraint
+                    )
+                    | (AlterTableAction::AddColumn { .. }, ActionKind::AddColumn)
+            )
+        }),
+        _ => false,
+    }
+}
+
+fn is_match(pattern: &str, text: &str) -> bool {
+    Regex::new(pattern)
+        .map(|re| re.is_match(text))
+        .unwrap_or(false)
+}
+
+pub(crate) fn table_pattern(pattern: &str, table: &str) -> String {
+    pattern.replace("{table}", &regex::escape(table))
+}
+
+impl CustomRule {
+    /// Check that all patterns in the rule are valid regular expressions
+    pub fn validate(&self) -> crate::Result<()> {
+        let when = &self.when;
+        let patterns = [
+            when.schema.clone(),
+            when.name.clone(),
+            when.name_not_matching
+                .as_deref()
+                .map(|p| table_pattern(p, "table")),
+            when.column_type.clone(),
+        ];
+        for pattern in patterns.iter().flatten() {
+            Regex::new(pattern).map_err(|e| {
+                InnerError::InvalidCustomRule(self.id.clone())
+                    .with_context(format!("Invalid pattern {pattern}: {e}"))
+            })?;
+        }
+        if when.missing_column.is_some()
+            && when.statement.is_some()
+            && when.statement != Some(StatementKind::CreateTable)
+        {
+            return Err(InnerError::InvalidCustomRule(self.id.clone())
+                .with_context("missing_column can only be used with create_table"));
+        }
+        if when.action.is_some()
+            && when.statement.is_some()
+            && when.statement != Some(StatementKind::AlterTable)
+        {
+            return Err(InnerError::InvalidCustomRule(self.id.clone())
+                .with_context("action can only be used with alter_table"));
+        }
+        Ok(())
+    }
+
+    fn matches<'a>(&self, statement: &'a StatementSummary) -> Option<Target<'a>> {
+        let target = target(statement)?;
+        let when = &self.when;
+        let matched = when.statement.is_none_or(|kind| kind == target.kind)
+            && when
+                .schema
+                .as_ref()
+                .is_none_or(|p| is_match(p, target.schema))
+            && when.name.as_ref().is_none_or(|p| is_match(p, target.name))
+            && when
+                .name_not_matching
+                .as_ref()
+                .is_none_or(|p| !is_match(&table_pattern(p, target.table), target.name))
+            && when
+                .column_type
+                .as_ref()
+                .is_none_or(|p| column_types(statement).iter().any(|t| is_match(p, t)))
+            && when.missing_column.as_ref().is_none_or(|column| {
+                matches!(statement, StatementSummary::CreateTable { columns, .. }
+                    if !columns.iter().any(|c| c.name.eq_ignore_ascii_case(column)))
+            })
+            && when.action.is_none_or(|kind| has_action(statement, kind));
+        matched.then_some(target)
+    }
+
+    /// Check the statement against the rule, returning a `Hint` if it triggers
+    pub fn check(&self, stmt: LintContext) -> Option<Hint> {
+        self.matches(stmt.statement).map(|target| Hint {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            condition: self.condition.clone(),
+            effect: self.effect.clone(),
+            workaround: self.workaround.clone(),
+            help: self
+                .message
+                .replace("{schema}", target.schema)
+                .replace("{name}", target.name)
+                .replace("{table}", target.table),
+            url: self.url.clone(),
+            severity: self.severity,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lints::lint;
+    use crate::rule_set::RuleSet;
+    use pretty_assertions::assert_eq;
+
+    fn rules() -> Vec<CustomRule> {
+        serde_json::from_str(
+            r#"[
+              {
+                "id": "C1",
+                "name": "New table without created_at",
+                "message": "Table `{schema}.{name}` has no `created_at` column",
+                "when": {"statement": "create_table", "missing_column": "created_at"}
+              },
+              {
+                "id": "C2",
+                "name": "Table in public schema",
+                "message": "`{name}` is in the public schema",
+                "severity": "warning",
+                "when": {"statement": "create_table", "schema": "^public$"}
+              },
+              {
+                "id": "C3",
+                "name": "Badly named index",
+                "message": "Index `{name}` on `{table}` should be named like `{table}_<columns>_idx`",
+                "when": {"statement": "create_index", "name_not_matching": "^{table}_.+_idx$"}
+              },
+              {
+                "id": "C4",
+                "name": "Changing column to json",
+                "message": "Use jsonb",
+                "when": {"action": "set_type", "column_type": "^json$"}
+              }
+            ]"#,
+        )
+        .unwrap()
+    }
+
+    fn triggered(sql: &str) -> Vec<(String, Severity)> {
+        let rules = rules();
+        rules.iter().for_each(|rule| rule.validate().unwrap());
+        let rules = RuleSet::default().with_custom_rules(rules);
+        let report = lint(None, sql, &[], false, &[], &rules).unwrap();
+        report
+            .statements
+            .into_iter()
+            .flat_map(|st| st.triggered_rules)
+            .filter(|hint| hint.id.starts_with('C'))
+            .map(|hint| (hint.id, hint.severity))
+            .collect()
+    }
+
+    #[test]
+    fn test_create_table_rules() {
+        assert_eq!(
+            triggered("create table books(id int)"),
+            vec![
+                ("C1".to_string(), Severity::Error),
+                ("C2".to_string(), Severity::Warning)
+            ]
+        );
+        assert!(triggered("create table library.books(id int, created_at timestamptz)").is_empty());
+    }
+
+    #[test]
+    fn test_index_name_rule() {
+        assert_eq!(
+            triggered("create index books_idx on books(title)"),
+            vec![("C3".to_string(), Severity::Error)]
+        );
+        assert!(triggered("create index books_title_idx on books(title)").is_empty());
+    }
+
+    #[test]
+    fn test_action_and_column_type_rule() {
+        assert_eq!(
+            triggered("alter table books alter column data type json"),
+            vec![("C4".to_string(), Severity::Error)]
+        );
+        assert!(triggered("alter table books add column data json").is_empty());
+    }
+
+    #[test]
+    fn test_message_placeholders() {
+        let rules = RuleSet::default().with_custom_rules(rules());
+        let report = lint(None, "create table books(id int)", &[], false, &[], &rules).unwrap();
+        let hint = report.statements[0]
+            .triggered_rules
+            .iter()
+            .find(|hint| hint.id == "C1")
+            .unwrap();
+        assert_eq!(hint.help, "Table `public.books` has no `created_at` column");
+    }
+
+    #[test]
+    fn test_invalid_pattern_is_rejected() {
+        let mut rule = rules().remove(1);
+        rule.when.schema = Some("(unclosed".to_string());
+        assert!(rule.validate().is_err());
+    }
+}
@@ -0,0 +1,224 @@
+use pg_query::protobuf::ConstrType;
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::error::{ContextualError, InnerError};
+use crate::hint_data::StaticHintData;
+use crate::lints::ast::{AlterTableAction, StatementSummary};
+use crate::lints::custom::table_pattern;
+use crate::lints::LintContext;
+use crate::rule_set::LintCheck;
+
+/// Patterns that names of new constraints and indexes must match, by constraint type.
+///
+/// The patterns are regular expressions, and `{table}` is replaced by the name of the table.
+/// Without any patterns, only constraints and indexes without a name are reported.
+/// W16 is not in `RuleSet::default()`, `eugene lint` only checks it when the config file has
+/// a `naming` section.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NamingConventions {
+    pub primary_key: Option<String>,
+    pub foreign_key: Option<String>,
+    pub unique: Option<String>,
+    pub check: Option<String>,
+    pub exclusion: Option<String>,
+    pub index: Option<String>,
+}
+
+/// The name of a constraint type that gets a name in `pg_constraint`, or `None` for
+/// things like `NOT NULL` and `DEFAULT` which are also constraints to the parser.
+fn named_constraint_kind(contype: ConstrType) -> Option<&'static str> {
+    match contype {
+        ConstrType::ConstrPrimary => Some("primary key"),
+        ConstrType::ConstrForeign => Some("foreign key"),
+        ConstrType::ConstrUnique => Some("unique"),
+        ConstrType::ConstrCheck => Some("check"),
+        ConstrType::ConstrExclusion => Some("exclusion"),
+        _ => None,
+    }
+}
+
+impl NamingConventions {
+    fn pattern_for(&self, kind: &str) -> Option<&str> {
+        match kind {
+            "primary key" => self.primary_key.as_deref(),
+            "foreign key" => self.foreign_key.as_deref(),
+            "unique" => self.unique.as_deref(),
+            "check" => self.check.as_deref(),
+            "exclusion" => self.exclusion.as_deref(),
+            "index" => self.index.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Check that all patterns are valid regular expressions
+    pub fn validate(&self) -> crate::Result<()> {
+        let patterns = [
+            &self.primary_key,
+            &self.foreign_key,
+            &self.unique,
+            &self.check,
+            &self.exclusion,
+            &self.index,
+        ];
+        for pattern in patterns.into_iter().flatten() {
+            Regex::new(&table_pattern(pattern, "table")).map_err(|e| {
+                InnerError::InvalidNamingConvention(pattern.clone()).with_context(format!("{e}"))
+            })?;
+        }
+        Ok(())
+    }
+
+    fn check_name(&self, kind: &str, name: &str, schema: &str, table: &str) -> Option<String> {
+        let what = if kind == "index" {
+            "New index".to_string()
+        } else {
+            format!("New `{kind}` constraint")
+        };
+        if name.is_empty() {
+            return Some(format!(
+                "{what} on `{schema}.{table}` has no name, so postgres will generate one"
+            ));
+        }
+        let pattern = self.pattern_for(kind)?;
+        let matches = Regex::new(&table_pattern(pattern, table))
+            .map(|re| re.is_match(name))
+            .unwrap_or(true);
+        (!matches).then(|| {
+            format!(
+                "{what} `{name}` on `{schema}.{table}` does not match the naming convention `{pattern}`"
+            )
+        })
+    }
+
+    /// Report new constraints and indexes on existing tables that have no name, or a name
+    /// that does not match the pattern for its type
+    pub(crate) fn check_statement(&self, stmt: LintContext) -> Option<String> {
+        let mut problems = vec![];
+        match stmt.statement {
+            StatementSummary::CreateIndex {
+                schema,
+                idxname,
+                target,
+                ..
+            } if stmt.is_visible(schema, target) => {
+                let schema = if schema.is_empty() { "public" } else { schema };
+                problems.extend(self.check_name("index", idxname, schema, target));
+            }
+            StatementSummary::AlterTable {
+                schema,
+                name,
+                actions,
+            } if stmt.is_visible(schema, name) => {
+                let schema = if schema.is_empty() { "public" } else { schema };
+                let constraints = actions.iter().flat_map(|action| match action {
+                    AlterTableAction::AddConstraint {
+                        name,
+                        constraint_type,
+                        ..
+                    } => vec![(name.as_str(), *constraint_type)],
+                    AlterTableAction::AddColumn { constraints, .. } => constraints
+                        .iter()
+                        .map(|c| (c.name.as_str(), c.contype))
+                        .collect(),
+                    _ => vec![],
+                });
+                for (conname, contype) in constraints {
+                    if let Some(kind) = named_constraint_kind(contype) {
+                        problems.extend(self.check_name(kind, conname, schema, name));
+                    }
+                }
+            }
+            _ => {}
+        }
+        if problems.is_empty() {
+            None
+        } else {
+            Some(problems.join("\n\n"))
+        }
+    }
+}
+
+impl LintCheck for NamingConventions {
+    fn meta(&self) -> &'static StaticHintData {
+        &crate::hint_data::UNNAMED_CONSTRAINT_OR_INDEX
+    }
+    fn check(&self, stmt: LintContext) -> Option<String> {
+        self.check_statement(stmt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lints::lint;
+    use crate::rule_set::{builtin_rules, RuleSet};
+    use pretty_assertions::assert_eq;
+
+    fn help(sql: &str, rules: &RuleSet) -> Vec<String> {
+        lint(None, sql, &[], false, &[], rules)
+            .unwrap()
+            .statements
+            .into_iter()
+            .flat_map(|st| st.triggered_rules)
+            .filter(|hint| hint.id == "W16")
+            .map(|hint| hint.help)
+            .collect()
+    }
+
+    #[test]
+    fn test_unnamed_constraints_and_indexes() {
+        // W16 is only checked when naming conventions are configured
+        assert!(help("create index concurrently on books(title)", builtin_rules()).is_empty());
+        let rules = RuleSet::default().with_lint_rule(NamingConventions::default());
+        assert_eq!(
+            help("alter table books add check (title <> '') not valid", &rules),
+            vec!["New `check` constraint on `public.books` has no name, so postgres will generate one"]
+        );
+        assert_eq!(
+            help("alter table books add column author_id int references authors(id)", &rules),
+            vec!["New `foreign key` constraint on `public.books` has no name, so postgres will generate one"]
+        );
+        assert_eq!(
+            help("create index concurrently on books(title)", &rules),
+            vec!["New index on `public.books` has no name, so postgres will generate one"]
+        );
+        assert!(help(
+            "alter table books add constraint title_check check (title <> '') not valid; \
+             alter table books add column note text not null default '';",
+            &rules
+        )
+        .is_empty());
+        assert!(help(
+            "create table authors(id int primary key); create index on authors(id);",
+            &rules
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_naming_conventions() {
+        let conventions = NamingConventions {
+            check: Some("^{table}_.+_check$".to_string()),
+            index: Some("^ix_".to_string()),
+            ..Default::default()
+        };
+        conventions.validate().unwrap();
+        let rules = RuleSet::default().replace_lint_rule(conventions);
+        assert_eq!(
+            help("alter table books add constraint title_check check (title <> '') not valid", &rules),
+            vec!["New `check` constraint `title_check` on `public.books` does not match the naming convention `^{table}_.+_check$`"]
+        );
+        assert!(help(
+            "alter table books add constraint books_title_check check (title <> '') not valid",
+            &rules
+        )
+        .is_empty());
+        assert!(help("create index concurrently ix_title on books(title)", &rules).is_empty());
+        assert_eq!(
+            help("create index concurrently on books(title)", &rules).len(),
+            1
+        );
+    }
+}
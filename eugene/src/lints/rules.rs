@@ -1,14 +1,15 @@
 use itertools::Itertools;
 use pg_query::protobuf::ConstrType;
 
-use crate::hint_data::{hint_url, HintId, StaticHintData};
+use crate::hint_data::{hint_url, HintId, Severity, StaticHintData};
 use crate::lints::ast::{AlterTableAction, Constraint};
 use crate::lints::{LintContext, StatementSummary};
 use crate::output::output_format::Hint;
 
+#[derive(Clone, Copy)]
 pub struct LintRule {
-    meta: &'static StaticHintData,
-    check: fn(LintContext) -> Option<String>,
+    pub(crate) meta: &'static StaticHintData,
+    pub(crate) check: fn(LintContext) -> Option<String>,
 }
 
 impl HintId for LintRule {
@@ -18,6 +19,13 @@ impl HintId for LintRule {
 }
 
 impl LintRule {
+    /// A rule with static metadata and a function that returns help text when it triggers
+    pub const fn new(
+        meta: &'static StaticHintData,
+        check: fn(LintContext) -> Option<String>,
+    ) -> Self {
+        LintRule { meta, check }
+    }
     pub fn id(&self) -> &'static str {
         self.meta.id
     }
@@ -42,6 +50,7 @@ impl LintRule {
             condition: self.condition().to_string(),
             help,
             url: hint_url(self.id()),
+            severity: Severity::default(),
         })
     }
 }
@@ -101,7 +110,8 @@ fn adding_valid_constraint(stmt: LintContext) -> Option<String> {
                     valid: true,
                     contype: ConstrType::ConstrCheck
                         | ConstrType::ConstrNotnull
-                        | ConstrType::ConstrForeign
+                        | ConstrType::ConstrForeign,
+                    ..
                 }
             )
         })
@@ -470,11 +480,19 @@ const RULES: &[LintRule] = &[
     ADD_PRIMARY_KEY_USING_INDEX,
 ];
 
-/// Get all available lint rules
+/// Lint rules that are only checked when they are configured, see `NamingConventions`
+const OPT_IN_RULES: &[&StaticHintData] = &[&crate::hint_data::UNNAMED_CONSTRAINT_OR_INDEX];
+
+/// Get all lint rules that are checked by default
 pub fn all_rules() -> impl Iterator<Item = &'static LintRule> {
     RULES.iter().sorted_by_key(|rule| rule.id())
 }
 
+/// True if `id` is a rule that eugene can lint scripts with, by default or with config
+pub fn is_lint_rule(id: &str) -> bool {
+    RULES.iter().any(|rule| rule.id() == id) || OPT_IN_RULES.iter().any(|rule| rule.id == id)
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -0,0 +1,221 @@
+use std::time::Duration;
+
+use itertools::Itertools;
+use serde::Deserialize;
+
+use crate::error::{ContextualError, InnerError};
+use crate::hint_data::StaticHintData;
+use crate::pg_types::lock_modes::LockMode;
+use crate::rule_set::TraceCheck;
+use crate::tracing::tracer::StatementCtx;
+
+/// Limits on how long a migration may hold strong locks, checked by hint E18.
+///
+/// E18 is not in `RuleSet::default()`, since hold times depend on the data in the database.
+/// `eugene trace` only checks it when the config file has a `lock_budget` section.
+///
+/// `max_hold_millis` is the longest time a single object may stay locked in one of `lock_modes`,
+/// summed over the statements in a transaction. `max_statement_millis` is the longest time a
+/// single statement may run while it holds one of `lock_modes`.
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LockBudget {
+    #[serde(default = "default_max_hold_millis")]
+    pub max_hold_millis: u64,
+    #[serde(default = "default_max_statement_millis")]
+    pub max_statement_millis: u64,
+    /// Lock modes as found in `pg_locks.mode`, eg. `AccessExclusiveLock`
+    #[serde(default = "default_lock_modes")]
+    pub lock_modes: Vec<String>,
+}
+
+fn default_max_hold_millis() -> u64 {
+    2000
+}
+
+fn default_max_statement_millis() -> u64 {
+    1000
+}
+
+fn default_lock_modes() -> Vec<String> {
+    vec![LockMode::AccessExclusive.to_db_str().to_string()]
+}
+
+impl Default for LockBudget {
+    fn default() -> Self {
+        LockBudget {
+            max_hold_millis: default_max_hold_millis(),
+            max_statement_millis: default_max_statement_millis(),
+            lock_modes: default_lock_modes(),
+        }
+    }
+}
+
+impl LockBudget {
+    /// Check that all lock modes are valid
+    pub fn validate(&self) -> crate::Result<()> {
+        for mode in self.lock_modes.iter() {
+            if LockMode::from_db_str(mode).is_none() {
+                return Err(
+                    InnerError::InvalidLockBudget(mode.clone()).with_context(format!(
+                        "`{mode}` is not a lock mode, eg. `AccessExclusiveLock`"
+                    )),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn limits(&self, mode: LockMode) -> bool {
+        self.lock_modes
+            .iter()
+            .any(|name| LockMode::from_db_str(name) == Some(mode))
+    }
+
+    /// Report objects that the statement pushed over `max_hold_millis`, and the statement itself
+    /// if it ran for longer than `max_statement_millis` while holding a limited lock
+    pub(crate) fn check_statement(&self, ctx: &StatementCtx) -> Option<String> {
+        let duration = ctx.duration();
+        let limited: Vec<_> = ctx
+            .locks_at_start()
+            .chain(ctx.new_locks_taken())
+            .filter(|lock| self.limits(lock.mode))
+            .filter_map(|lock| Some((lock, lock.relation()?)))
+            .unique_by(|(_, target)| target.oid)
+            .sorted_by_key(|(_, target)| (&target.schema, &target.object_name))
+            .collect();
+        let max_hold = Duration::from_millis(self.max_hold_millis);
+        let mut problems: Vec<_> = limited
+            .iter()
+            .filter_map(|(lock, target)| {
+                let held = ctx.held_for(lock);
+                // Only report the statement that went over the budget, not every statement after it
+                (held > max_hold && held - duration <= max_hold).then(|| {
+                    format!(
+                        "The {} `{}.{}` has been locked in `{}` for {}ms, which is over the budget of {}ms.",
+                        target.rel_kind,
+                        target.schema,
+                        target.object_name,
+                        lock.mode.to_db_str(),
+                        held.as_millis(),
+                        self.max_hold_millis
+                    )
+                })
+            })
+            .collect();
+        if let Some((lock, target)) = limited.first() {
+            if duration > Duration::from_millis(self.max_statement_millis) {
+                problems.push(format!(
+                    "The statement ran for {}ms while holding `{}` on the {} `{}.{}`, which is over the budget of {}ms.",
+                    duration.as_millis(),
+                    lock.mode.to_db_str(),
+                    target.rel_kind,
+                    target.schema,
+                    target.object_name,
+                    self.max_statement_millis
+                ));
+            }
+        }
+        (!problems.is_empty()).then(|| problems.join("\n\n"))
+    }
+}
+
+impl TraceCheck for LockBudget {
+    fn meta(&self) -> &'static StaticHintData {
+        &crate::hint_data::LOCK_BUDGET_EXCEEDED
+    }
+    fn check(&self, stmt: &StatementCtx) -> Option<String> {
+        self.check_statement(stmt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::rule_set::RuleSet;
+    use crate::tracing::{trace_transaction, TraceOptions};
+    use crate::{connect_to_test_db, generate_new_test_db};
+
+    fn help(budget: LockBudget, sql: Vec<&str>) -> Vec<Vec<String>> {
+        let mut client = connect_to_test_db(&generate_new_test_db());
+        let rules = RuleSet::empty().with_trace_hint(budget);
+        let mut tx = client.transaction().unwrap();
+        let options = TraceOptions::new(&rules);
+        let trace =
+            trace_transaction(None, &mut tx, sql.into_iter().enumerate(), &options, None).unwrap();
+        trace
+            .triggered_hints
+            .into_iter()
+            .map(|hints| hints.into_iter().map(|hint| hint.help).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_reports_the_statement_that_exceeds_the_hold_budget() {
+        let budget = LockBudget {
+            max_hold_millis: 150,
+            max_statement_millis: 10_000,
+            ..Default::default()
+        };
+        let help = help(
+            budget,
+            vec![
+                "alter table books add column meta jsonb",
+                "select pg_sleep(0.1)",
+                "select pg_sleep(0.1)",
+                "select pg_sleep(0.1)",
+            ],
+        );
+        assert!(help[0].is_empty());
+        assert!(help[1].is_empty());
+        assert_eq!(help[2].len(), 1);
+        assert!(help[2][0]
+            .starts_with("The Table `public.books` has been locked in `AccessExclusiveLock` for"));
+        assert!(help[3].is_empty());
+    }
+
+    #[test]
+    fn test_reports_slow_statements_that_hold_limited_locks() {
+        let budget = LockBudget {
+            max_hold_millis: 10_000,
+            max_statement_millis: 50,
+            lock_modes: vec!["ShareLock".to_string()],
+        };
+        let help = help(
+            budget,
+            vec![
+                "select pg_sleep(0.1)",
+                "lock table books in share mode",
+                "select pg_sleep(0.1)",
+            ],
+        );
+        assert!(help[0].is_empty());
+        assert!(help[1].is_empty());
+        assert_eq!(help[2].len(), 1);
+        assert!(help[2][0].starts_with("The statement ran for"));
+        assert!(help[2][0].ends_with(
+            "while holding `ShareLock` on the Table `public.books`, which is over the budget of 50ms."
+        ));
+    }
+
+    #[test]
+    fn test_budget_is_only_checked_when_configured() {
+        let id = crate::hint_data::LOCK_BUDGET_EXCEEDED.id;
+        assert!(!RuleSet::default().has_trace(id));
+        assert!(RuleSet::default()
+            .replace_trace_hint(LockBudget::default())
+            .has_trace(id));
+        assert!(crate::hints::is_trace_hint(id));
+    }
+
+    #[test]
+    fn test_rejects_unknown_lock_modes() {
+        let budget = LockBudget {
+            lock_modes: vec!["AccessExclusive".to_string()],
+            ..Default::default()
+        };
+        assert!(budget.validate().is_err());
+        assert!(LockBudget::default().validate().is_ok());
+    }
+}
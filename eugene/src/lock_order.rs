@@ -0,0 +1,180 @@
+use itertools::Itertools;
+use serde::Deserialize;
+
+use crate::hint_data::StaticHintData;
+use crate::pg_types::contype::Contype;
+use crate::pg_types::locks::{Lock, LockableTarget};
+use crate::pg_types::relkinds::RelKind;
+use crate::rule_set::TraceCheck;
+use crate::tracing::tracer::StatementCtx;
+
+/// The order that transactions should lock tables in, checked by hint E20.
+///
+/// Tables are given as `schema.table`, or `table` to match a table in any schema. Tables that
+/// are not in the list can be locked in any order. Without any tables, a table that is referenced
+/// by a foreign key must be locked before the table that references it, which is the order
+/// applications usually insert rows in.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LockOrder {
+    #[serde(default)]
+    pub tables: Vec<String>,
+}
+
+fn dangerous_table_locks<'a>(
+    locks: impl Iterator<Item = &'a Lock>,
+) -> impl Iterator<Item = (&'a Lock, &'a LockableTarget)> {
+    locks
+        .filter(|lock| lock.maybe_dangerous())
+        .filter_map(|lock| Some((lock, lock.relation()?)))
+        .filter(|(_, target)| matches!(target.rel_kind, RelKind::Table | RelKind::PartitionedTable))
+        .sorted_by_key(|(lock, target)| (&target.schema, &target.object_name, lock.mode))
+        .rev()
+        .unique_by(|(_, target)| target.oid)
+}
+
+impl LockOrder {
+    fn position(&self, target: &LockableTarget) -> Option<usize> {
+        let qualified = format!("{}.{}", target.schema, target.object_name);
+        self.tables
+            .iter()
+            .position(|name| name == &qualified || name == &target.object_name)
+    }
+
+    /// Why `first` must be locked before `second`, if it must
+    fn reason(
+        &self,
+        ctx: &StatementCtx,
+        first: &LockableTarget,
+        second: &LockableTarget,
+    ) -> Option<String> {
+        if self.tables.is_empty() {
+            let fk = ctx.constraints_on(second.oid).find(|con| {
+                con.constraint_type == Contype::ForeignKey && con.fk_target == Some(first.oid)
+            })?;
+            Some(format!(
+                "the foreign key `{}` on `{}.{}` references `{}.{}`",
+                fk.name, second.schema, second.object_name, first.schema, first.object_name
+            ))
+        } else {
+            (self.position(first)? < self.position(second)?).then(|| {
+                format!(
+                    "`{}.{}` comes before `{}.{}` in the configured lock order",
+                    first.schema, first.object_name, second.schema, second.object_name
+                )
+            })
+        }
+    }
+
+    /// Report dangerous locks that the statement took on tables that should have been locked
+    /// before tables the transaction already holds dangerous locks on
+    pub(crate) fn check_statement(&self, ctx: &StatementCtx) -> Option<String> {
+        let held: Vec<_> = dangerous_table_locks(ctx.locks_at_start()).collect();
+        let problems = dangerous_table_locks(ctx.new_locks_taken())
+            .filter(|(_, target)| !held.iter().any(|(_, h)| h.oid == target.oid))
+            .flat_map(|(lock, target)| {
+                held.iter().filter_map(move |(held_lock, held_target)| {
+                    let reason = self.reason(ctx, target, held_target)?;
+                    let LockableTarget {
+                        schema,
+                        object_name,
+                        ..
+                    } = target;
+                    Some(format!(
+                        "The statement took `{}` on `{schema}.{object_name}` while the transaction holds `{}` on `{}.{}`, \
+                        but {reason}. Application transactions that lock the tables in the opposite order can deadlock with \
+                        the migration. Lock `{schema}.{object_name}` first, at the start of the transaction:\n\
+                        ```sql\nlock table \"{schema}\".\"{object_name}\" in {} mode;\n```\n",
+                        lock.mode.to_db_str(),
+                        held_lock.mode.to_db_str(),
+                        held_target.schema,
+                        held_target.object_name,
+                        lock.mode.to_sql_str().to_lowercase(),
+                    ))
+                })
+            })
+            .join("\n\n");
+        (!problems.is_empty()).then_some(problems)
+    }
+}
+
+impl TraceCheck for LockOrder {
+    fn meta(&self) -> &'static StaticHintData {
+        &crate::hint_data::LOCKING_TABLES_OUT_OF_ORDER
+    }
+    fn check(&self, stmt: &StatementCtx) -> Option<String> {
+        self.check_statement(stmt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::rule_set::RuleSet;
+    use crate::tracing::{trace_transaction, TraceOptions};
+    use crate::{connect_to_test_db, generate_new_test_db};
+
+    fn help(order: LockOrder, sql: Vec<&str>) -> Vec<Vec<String>> {
+        let mut client = connect_to_test_db(&generate_new_test_db());
+        client
+            .batch_execute(
+                "create table orders (id int primary key); \
+                 create table order_lines (id int primary key, order_id int references orders (id));",
+            )
+            .unwrap();
+        let rules = RuleSet::empty().with_trace_hint(order);
+        let mut tx = client.transaction().unwrap();
+        let options = TraceOptions::new(&rules);
+        let trace =
+            trace_transaction(None, &mut tx, sql.into_iter().enumerate(), &options, None).unwrap();
+        trace
+            .triggered_hints
+            .into_iter()
+            .map(|hints| hints.into_iter().map(|hint| hint.help).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_infers_lock_order_from_foreign_keys() {
+        let help = help(
+            LockOrder::default(),
+            vec![
+                "alter table order_lines add column note text",
+                "alter table orders add column note text",
+            ],
+        );
+        assert!(help[0].is_empty());
+        assert_eq!(help[1].len(), 1);
+        assert!(help[1][0].starts_with(
+            "The statement took `AccessExclusiveLock` on `public.orders` while the transaction holds \
+            `AccessExclusiveLock` on `public.order_lines`, but the foreign key `order_lines_order_id_fkey`"
+        ));
+
+        let help = help_in_order(LockOrder::default());
+        assert!(help.iter().all(|hints| hints.is_empty()));
+    }
+
+    fn help_in_order(order: LockOrder) -> Vec<Vec<String>> {
+        help(
+            order,
+            vec![
+                "alter table orders add column note text",
+                "alter table order_lines add column note text",
+            ],
+        )
+    }
+
+    #[test]
+    fn test_configured_lock_order_replaces_foreign_keys() {
+        let order = LockOrder {
+            tables: vec!["order_lines".to_string(), "public.orders".to_string()],
+        };
+        let help = help_in_order(order);
+        assert!(help[0].is_empty());
+        assert_eq!(help[1].len(), 1);
+        assert!(help[1][0].contains(
+            "but `public.order_lines` comes before `public.orders` in the configured lock order"
+        ));
+    }
+}
@@ -1,12 +1,16 @@
+use chrono::Utc;
 use itertools::Itertools;
 use serde::ser::SerializeStruct;
 use serde::Serialize;
 
 pub use output_format::{
     Column, Constraint, DbObject, FullSqlStatementLockTrace, FullTraceData, GenericHint, Hint,
-    LintReport, LintedStatement, ModifiedColumn, ModifiedConstraint, TracedLock,
+    LintReport, LintedStatement, LockModeChange, LockWait, ModifiedColumn, ModifiedConstraint,
+    ModifiedSchemaObject, ObjectLockTimeline, Probe, SchemaObject, SeededTable, TracedLock,
+    TransactionSection, VersionDifference, VersionFindings,
 };
 
+use crate::output::output_format::any_errors;
 use crate::pg_types::lock_modes::LockMode;
 use crate::pg_types::locks::Lock;
 use crate::tracing::{SqlStatementTrace, TxLockTracer};
@@ -37,23 +41,34 @@ impl Settings {
 #[derive(Debug, Eq, PartialEq)]
 struct OutputContext {
     output_settings: Settings,
+    transaction_number: usize,
     statement_number: usize,
     held_locks_context: Vec<TracedLock>,
+    /// Every lock taken during the trace, including the ones released by `ROLLBACK TO SAVEPOINT`
+    acquired_locks: Vec<TracedLock>,
     duration_millis_so_far: u64,
     duration_millis_total: u64,
 }
 
+/// True if `a` and `b` are the same mode of lock on the same target, regardless of duration
+fn same_lock(a: &TracedLock, b: &TracedLock) -> bool {
+    a.lock_type == b.lock_type && a.target == b.target && a.mode == b.mode
+}
+
 impl OutputContext {
     fn output_lock(&self, lock: &Lock) -> TracedLock {
         TracedLock {
-            schema: lock.target.schema.clone(),
-            object_name: lock.target.object_name.clone(),
-            relkind: lock.target.rel_kind.as_str(),
+            schema: lock.target.schema().to_string(),
+            object_name: lock.target.object_name(),
+            relkind: lock.target.kind(),
+            lock_type: lock.target.lock_type(),
+            target: lock.target.to_string(),
             mode: lock.mode.to_db_str().to_string(),
-            maybe_dangerous: lock.mode.dangerous(),
-            oid: lock.target.oid,
+            maybe_dangerous: lock.maybe_dangerous(),
+            oid: lock.target.oid(),
             blocked_queries: lock.blocked_queries(),
             lock_duration_millis: self.duration_millis_total - self.duration_millis_so_far,
+            table_stats: None,
         }
     }
 
@@ -93,6 +108,7 @@ impl OutputContext {
             .collect();
 
         let result = FullSqlStatementLockTrace {
+            transaction_number: self.transaction_number,
             statement_number_in_transaction: self.statement_number,
             line_number: statement.line_no,
             sql: statement.sql.clone(),
@@ -125,10 +141,60 @@ impl OutputContext {
                 .iter()
                 .map(DbObject::from)
                 .collect(),
+            new_schema_objects: statement
+                .added_schema_objects
+                .iter()
+                .map(SchemaObject::from)
+                .collect(),
+            altered_schema_objects: statement
+                .modified_schema_objects
+                .iter()
+                .map(ModifiedSchemaObject::from)
+                .collect(),
+            dropped_schema_objects: statement
+                .dropped_schema_objects
+                .iter()
+                .map(SchemaObject::from)
+                .collect(),
             lock_timeout_millis: statement.lock_timeout_millis,
+            rows_written: statement.rows_written,
+            bytes_written: statement.bytes_written,
             triggered_rules: hints.to_vec(),
+            probes: statement.probes.iter().map(Probe::from).collect(),
+            released_locks: statement
+                .released_locks
+                .iter()
+                .filter(|lock| !self.hide_lock(lock))
+                .map(|lock| self.output_lock(lock))
+                .sorted_by_key(|lock| {
+                    (
+                        lock.schema.clone(),
+                        lock.object_name.clone(),
+                        lock.relkind,
+                        lock.mode.clone(),
+                    )
+                })
+                .collect(),
+            rolled_back: statement.rolled_back,
+            phases: statement.phases.clone(),
+            lock_waits: statement
+                .lock_waits
+                .iter()
+                .map(|wait| LockWait {
+                    lock: self.output_lock(&wait.lock),
+                    waited_millis: wait.waited.as_millis() as u64,
+                    blocking_pids: wait.blocking_pids.clone(),
+                })
+                .collect(),
         };
         self.statement_number += 1;
+        self.held_locks_context
+            .retain(|lock| !result.released_locks.iter().any(|r| same_lock(r, lock)));
+        for lock in result.new_locks_taken.iter() {
+            if !self.acquired_locks.iter().any(|a| same_lock(a, lock)) {
+                self.acquired_locks.push(lock.clone());
+            }
+        }
         self.held_locks_context
             .extend(result.new_locks_taken.clone());
         self.duration_millis_so_far += result.duration_millis;
@@ -136,32 +202,83 @@ impl OutputContext {
     }
 
     fn hide_lock(&self, lock: &Lock) -> bool {
-        self.output_settings.only_dangerous_locks && !lock.mode.dangerous()
+        self.output_settings.only_dangerous_locks && !lock.maybe_dangerous()
     }
-    pub fn new(output_settings: Settings, duration_millis_total: u64) -> Self {
+    pub fn new(output_settings: Settings) -> Self {
         OutputContext {
             output_settings,
+            transaction_number: 0,
             statement_number: 1,
             held_locks_context: vec![],
+            acquired_locks: vec![],
             duration_millis_so_far: 0,
-            duration_millis_total,
+            duration_millis_total: 0,
         }
     }
+
+    /// Start the next transaction, which runs for `duration_millis` and holds no locks at the start
+    fn start_transaction(&mut self, duration_millis: u64) {
+        self.transaction_number += 1;
+        self.statement_number = 1;
+        self.held_locks_context.clear();
+        self.duration_millis_total = self.duration_millis_so_far + duration_millis;
+    }
 }
 
-pub fn full_trace_data(trace: &TxLockTracer, output_settings: Settings) -> FullTraceData {
-    let total_duration = trace
-        .statements
-        .iter()
-        .map(|st| st.duration.as_millis() as u64)
-        .sum();
-    let mut context = OutputContext::new(output_settings, total_duration);
+/// Output the statements of `traces` with `context`, and a section for each transaction
+fn output_statements(
+    traces: &[TxLockTracer],
+    context: &mut OutputContext,
+) -> (Vec<FullSqlStatementLockTrace>, Vec<TransactionSection>) {
     let mut statements = vec![];
-    for (i, statement) in trace.statements.iter().enumerate() {
-        statements.push(context.output_statement(statement, &trace.triggered_hints[i]));
+    let mut transactions = vec![];
+    for trace in traces {
+        let duration_millis = trace
+            .statements
+            .iter()
+            .map(|st| st.duration.as_millis() as u64)
+            .sum();
+        context.start_transaction(duration_millis);
+        let first = statements.len();
+        for (i, statement) in trace.statements.iter().enumerate() {
+            statements.push(context.output_statement(statement, &trace.triggered_hints[i]));
+            // Concurrent statements run outside of transactions, and release their locks when done
+            if trace.concurrent {
+                context.held_locks_context.clear();
+            }
+        }
+        let dangerous_locks_count = statements[first..]
+            .iter()
+            .flat_map(|st| st.new_locks_taken.iter())
+            .filter(|lock| lock.maybe_dangerous)
+            .count();
+        transactions.push(TransactionSection {
+            transaction_number: context.transaction_number,
+            statement_count: trace.statements.len(),
+            duration_millis,
+            dangerous_locks_count,
+            rolled_back: trace.rolled_back,
+        });
     }
-    let passed_all_checks = statements.iter().all(|st| st.triggered_rules.is_empty());
-    context.held_locks_context.sort_by_key(|lock| {
+    (statements, transactions)
+}
+
+/// Output data for a script traced as one or more transactions, see `perform_trace`
+pub fn full_trace_data(traces: &[TxLockTracer], output_settings: Settings) -> FullTraceData {
+    let mut context = OutputContext::new(output_settings);
+    let (statements, transactions) = output_statements(traces, &mut context);
+    // The timeline has every lock, also the ones that are hidden from the statements
+    let lock_timeline = if output_settings.only_dangerous_locks {
+        let all_locks = Settings {
+            only_dangerous_locks: false,
+            ..output_settings
+        };
+        lock_timeline(&output_statements(traces, &mut OutputContext::new(all_locks)).0)
+    } else {
+        lock_timeline(&statements)
+    };
+    let passed_all_checks = !any_errors(statements.iter().flat_map(|st| st.triggered_rules.iter()));
+    context.acquired_locks.sort_by_key(|lock| {
         (
             lock.schema.clone(),
             lock.object_name.clone(),
@@ -170,21 +287,165 @@ pub fn full_trace_data(trace: &TxLockTracer, output_settings: Settings) -> FullT
         )
     });
     let dangerous_locks_count = context
-        .held_locks_context
+        .acquired_locks
         .iter()
         .filter(|lock| lock.maybe_dangerous)
         .count();
 
     FullTraceData {
-        name: trace.name.clone(),
-        start_time: trace.trace_start,
+        name: traces.first().and_then(|trace| trace.name.clone()),
+        lock_timeline,
+        start_time: traces
+            .first()
+            .map(|trace| trace.trace_start)
+            .unwrap_or_else(Utc::now),
         total_duration_millis: context.duration_millis_so_far,
-        all_locks_acquired: context.held_locks_context,
+        all_locks_acquired: context.acquired_locks,
         statements,
         skip_summary: output_settings.skip_summary_section,
         dangerous_locks_count,
         passed_all_checks,
+        seeded_tables: vec![],
+        transactions,
+        server_version: None,
+    }
+}
+
+/// Statements that triggered different hints depending on the postgres version that traced them
+///
+/// `runs` has the traces from each `server_version`. Scripts are matched by name, and statements by
+/// their position in the script. A script that a version did not trace, eg. because it was restored
+/// from a cache, has `None` findings for that version and is only compared between the versions
+/// that traced it.
+pub fn version_differences(runs: &[(String, Vec<FullTraceData>)]) -> Vec<VersionDifference> {
+    let hint_ids = |statement: &FullSqlStatementLockTrace| {
+        statement
+            .triggered_rules
+            .iter()
+            .map(|hint| hint.id.clone())
+            .sorted()
+            .dedup()
+            .collect_vec()
+    };
+    let scripts = runs
+        .iter()
+        .flat_map(|(_, traces)| traces.iter())
+        .unique_by(|trace| &trace.name)
+        .collect_vec();
+    let mut differences = vec![];
+    for script in scripts {
+        let traced = runs
+            .iter()
+            .map(|(server_version, traces)| {
+                let trace = traces.iter().find(|trace| trace.name == script.name);
+                (server_version, trace)
+            })
+            .collect_vec();
+        for (statement_ix, statement) in script.statements.iter().enumerate() {
+            let findings = traced
+                .iter()
+                .map(|(server_version, trace)| VersionFindings {
+                    server_version: server_version.to_string(),
+                    hint_ids: trace
+                        .and_then(|trace| trace.statements.get(statement_ix))
+                        .map(hint_ids),
+                })
+                .collect_vec();
+            if findings
+                .iter()
+                .filter_map(|f| f.hint_ids.as_ref())
+                .all_equal()
+            {
+                continue;
+            }
+            differences.push(VersionDifference {
+                script: script.name.clone(),
+                line_number: statement.line_number,
+                sql: statement.sql.clone(),
+                findings,
+            });
+        }
+    }
+    differences
+}
+
+/// When each relation was locked, in which modes, and when it was released
+///
+/// Times are offsets from the start of the trace, computed from the durations of `statements`, so
+/// the time eugene spends between statements is not counted. A lock is held from the start of the
+/// statement that took it, to the end of the last statement that held it, which is the end of
+/// the transaction unless `ROLLBACK TO SAVEPOINT` released it, or the statement ran outside of
+/// a transaction.
+pub fn lock_timeline(statements: &[FullSqlStatementLockTrace]) -> Vec<ObjectLockTimeline> {
+    let mut done = vec![];
+    let mut open: Vec<ObjectLockTimeline> = vec![];
+    let mut transaction_number = 0;
+    let mut at_millis = 0;
+    for statement in statements {
+        if statement.transaction_number != transaction_number {
+            done.append(&mut open);
+            transaction_number = statement.transaction_number;
+        }
+        let end_millis = at_millis + statement.duration_millis;
+        let strongest = statement
+            .locks_at_start
+            .iter()
+            .chain(statement.new_locks_taken.iter())
+            .filter(|lock| lock.lock_type == "relation")
+            .into_grouping_map_by(|lock| (&lock.schema, &lock.object_name, lock.relkind))
+            .max_by_key(|_, lock| LockMode::from_db_str(&lock.mode));
+        let (held, released): (Vec<_>, Vec<_>) = open.into_iter().partition(|entry| {
+            strongest.contains_key(&(&entry.schema, &entry.object_name, entry.relkind))
+        });
+        done.extend(released);
+        open = held;
+        for ((schema, object_name, relkind), lock) in strongest
+            .into_iter()
+            .sorted_by_key(|((schema, name, _), _)| (*schema, *name))
+        {
+            let change = LockModeChange {
+                mode: lock.mode.clone(),
+                maybe_dangerous: lock.maybe_dangerous,
+                at_millis,
+                statement_number_in_transaction: statement.statement_number_in_transaction,
+            };
+            let entry = open.iter_mut().find(|entry| {
+                &entry.schema == schema
+                    && &entry.object_name == object_name
+                    && entry.relkind == relkind
+            });
+            match entry {
+                Some(entry) => {
+                    let current = entry.modes.last().map(|m| LockMode::from_db_str(&m.mode));
+                    if current < Some(LockMode::from_db_str(&lock.mode)) {
+                        entry.modes.push(change);
+                    }
+                    entry.released_at_millis = end_millis;
+                    entry.held_millis = end_millis - entry.locked_at_millis;
+                }
+                None => open.push(ObjectLockTimeline {
+                    transaction_number,
+                    schema: schema.clone(),
+                    object_name: object_name.clone(),
+                    relkind,
+                    locked_at_millis: at_millis,
+                    released_at_millis: end_millis,
+                    held_millis: statement.duration_millis,
+                    modes: vec![change],
+                }),
+            }
+        }
+        at_millis = end_millis;
     }
+    done.append(&mut open);
+    done.sort_by(|a, b| {
+        (a.locked_at_millis, &a.schema, &a.object_name).cmp(&(
+            b.locked_at_millis,
+            &b.schema,
+            &b.object_name,
+        ))
+    });
+    done
 }
 
 struct JsonTrace<'a> {
@@ -275,3 +536,172 @@ impl<L> LockModesWrapper<L> {
         LockModesWrapper { lock_modes }
     }
 }
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hint_data::Severity;
+    use crate::output::templates::lock_gantt;
+    use crate::rule_set::builtin_rules;
+    use crate::tracing::{trace_transaction, TraceOptions};
+    use crate::{connect_to_test_db, generate_new_test_db};
+
+    #[test]
+    fn test_lock_timeline_shows_escalation_until_end_of_transaction() {
+        let mut client = connect_to_test_db(&generate_new_test_db());
+        let mut tx = client.transaction().unwrap();
+        let options = TraceOptions::default();
+        let trace = trace_transaction(
+            None,
+            &mut tx,
+            vec![
+                "select pg_sleep(0.02)",
+                "lock table books in access share mode",
+                "select pg_sleep(0.02)",
+                "alter table books add column meta jsonb",
+                "select pg_sleep(0.02)",
+            ]
+            .into_iter()
+            .enumerate(),
+            &options,
+            None,
+        )
+        .unwrap();
+        let data = full_trace_data(std::slice::from_ref(&trace), Settings::new(false, false));
+        let books = data
+            .lock_timeline
+            .iter()
+            .find(|entry| entry.object_name == "books")
+            .unwrap();
+        let modes: Vec<_> = books.modes.iter().map(|m| m.mode.as_str()).collect();
+        assert_eq!(modes, vec!["AccessShareLock", "AccessExclusiveLock"]);
+        assert_eq!(books.locked_at_millis, data.statements[1].start_time_millis);
+        assert_eq!(books.modes[1].statement_number_in_transaction, 4);
+        assert_eq!(books.released_at_millis, data.total_duration_millis);
+        assert_eq!(
+            books.held_millis,
+            books.released_at_millis - books.locked_at_millis
+        );
+
+        let gantt = lock_gantt(&data.lock_timeline, data.total_duration_millis);
+        let line = gantt
+            .lines()
+            .find(|line| line.starts_with("public.books "))
+            .unwrap();
+        let bar = line.split('|').nth(1).unwrap();
+        assert!(bar.starts_with(' '));
+        assert!(bar.trim().starts_with('='));
+        assert!(bar.ends_with('#'));
+
+        // Hiding locks that are not dangerous from the statements keeps them in the timeline
+        let dangerous = full_trace_data(std::slice::from_ref(&trace), Settings::new(true, false));
+        assert_eq!(dangerous.lock_timeline, data.lock_timeline);
+        assert!(!dangerous.to_plain_text().unwrap().contains("public.books "));
+    }
+
+    #[test]
+    fn test_warnings_are_rendered_in_text_and_markdown() {
+        let mut client = connect_to_test_db(&generate_new_test_db());
+        let mut tx = client.transaction().unwrap();
+        let options = TraceOptions::default();
+        let trace = trace_transaction(
+            Some("add_data.sql".to_string()),
+            &mut tx,
+            vec!["alter table books add column data json"]
+                .into_iter()
+                .enumerate(),
+            &options,
+            None,
+        )
+        .unwrap();
+        let mut data = full_trace_data(std::slice::from_ref(&trace), Settings::new(false, false));
+        for statement in data.statements.iter_mut() {
+            for rule in statement.triggered_rules.iter_mut() {
+                rule.severity = Severity::Warning;
+            }
+        }
+        data.update_passed_all_checks();
+        assert!(data.passed_all_checks);
+        let text = data.to_plain_text().unwrap();
+        assert!(text.contains(" W E3 "), "{text}");
+        let markdown = data.to_markdown().unwrap();
+        assert!(markdown.contains("### ⚠️ Statement number 1"), "{markdown}");
+        assert!(markdown.contains("##### ⚠️ `E3`"), "{markdown}");
+        assert!(!markdown.contains("##### ❌"), "{markdown}");
+
+        let mut report = crate::lints::lint(
+            Some("add_data.sql".to_string()),
+            "alter table books add column data json;",
+            &[],
+            false,
+            &[],
+            builtin_rules(),
+        )
+        .unwrap();
+        assert!(templates::lint_text(&report)
+            .unwrap()
+            .contains("add_data.sql:1 E E3 "));
+        for rule in report.statements[0].triggered_rules.iter_mut() {
+            rule.severity = Severity::Warning;
+        }
+        report.update_passed_all_checks();
+        assert!(report.passed_all_checks);
+        let text = templates::lint_text(&report).unwrap();
+        assert!(text.contains("add_data.sql:1 W E3 "), "{text}");
+        let markdown = templates::lint_report_to_markdown(&report).unwrap();
+        assert!(markdown.contains("### ⚠️ Statement number 1"), "{markdown}");
+        assert!(!markdown.contains("❌"), "{markdown}");
+    }
+
+    #[test]
+    fn test_version_differences_only_lists_statements_with_different_hints() {
+        let mut client = connect_to_test_db(&generate_new_test_db());
+        let mut tx = client.transaction().unwrap();
+        let options = TraceOptions::default();
+        let trace = trace_transaction(
+            Some("add_meta.sql".to_string()),
+            &mut tx,
+            vec!["select 1", "alter table books add column meta json"]
+                .into_iter()
+                .enumerate(),
+            &options,
+            None,
+        )
+        .unwrap();
+        let old = full_trace_data(std::slice::from_ref(&trace), Settings::new(false, false));
+        let mut new = old.clone();
+        new.statements[1]
+            .triggered_rules
+            .retain(|hint| hint.id != crate::hint_data::ADD_JSON_COLUMN.id);
+
+        let same = vec![
+            ("15".to_string(), vec![old.clone()]),
+            ("16".to_string(), vec![old.clone()]),
+        ];
+        assert!(version_differences(&same).is_empty());
+
+        let mut other = old.clone();
+        other.name = Some("other.sql".to_string());
+        // The second version only traced `other.sql`, eg. because `add_meta.sql` was cached
+        let missing = vec![
+            ("15".to_string(), vec![old.clone(), other.clone()]),
+            ("16".to_string(), vec![other]),
+        ];
+        assert!(version_differences(&missing).is_empty());
+
+        let runs = vec![
+            ("15".to_string(), vec![old.clone()]),
+            ("16".to_string(), vec![new]),
+            ("17".to_string(), vec![]),
+        ];
+        let differences = version_differences(&runs);
+        assert_eq!(differences.len(), 1);
+        assert_eq!(differences[0].script.as_deref(), Some("add_meta.sql"));
+        assert_eq!(differences[0].sql, "alter table books add column meta json");
+        let hint_ids = |ix: usize| differences[0].findings[ix].hint_ids.clone();
+        assert!(hint_ids(0).unwrap().contains(&"E3".to_string()));
+        assert!(!hint_ids(1).unwrap().contains(&"E3".to_string()));
+        assert_eq!(hint_ids(2), None);
+    }
+}
@@ -1,9 +1,11 @@
 use chrono::{DateTime, Utc};
 use serde::Serialize;
 
-use crate::hint_data::{hint_url, HintId, StaticHintData};
+use crate::hint_data::{hint_url, HintId, Severity, StaticHintData};
 use crate::hints::HintInfo;
 use crate::pg_types::locks::LockableTarget;
+use crate::table_stats::TableStats;
+use crate::tracing::probes::ProbeResult;
 use crate::tracing::queries::ColumnMetadata;
 
 #[derive(Debug, Eq, PartialEq, Clone, Serialize)]
@@ -26,10 +28,8 @@ impl From<&HintInfo> for GenericHint {
             condition: value.condition().to_string(),
             effect: value.effect().to_string(),
             workaround: value.workaround().to_string(),
-            has_lint: crate::lints::rules::all_rules().any(|rule| rule.id() == value.code()),
-            has_trace: crate::hints::all_hints()
-                .iter()
-                .any(|hint| hint.code() == value.code()),
+            has_lint: crate::lints::rules::is_lint_rule(value.code()),
+            has_trace: crate::hints::is_trace_hint(value.code()),
             url: value.url(),
         }
     }
@@ -43,10 +43,8 @@ impl From<&StaticHintData> for GenericHint {
             condition: value.condition.to_string(),
             effect: value.effect.to_string(),
             workaround: value.workaround.to_string(),
-            has_lint: crate::lints::rules::all_rules().any(|rule| rule.id() == value.id),
-            has_trace: crate::hints::all_hints()
-                .iter()
-                .any(|hint| hint.code() == value.id),
+            has_lint: crate::lints::rules::is_lint_rule(value.id),
+            has_trace: crate::hints::is_trace_hint(value.id),
             url: value.url(),
         }
     }
@@ -58,6 +56,9 @@ pub struct DbObject {
     pub object_name: String,
     pub relkind: &'static str,
     pub oid: u32,
+    /// Size and activity of the object in the reference database, with `--stats-from-db`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub table_stats: Option<TableStats>,
 }
 
 impl From<&LockableTarget> for DbObject {
@@ -67,6 +68,7 @@ impl From<&LockableTarget> for DbObject {
             object_name: value.object_name.to_string(),
             relkind: value.rel_kind.as_str(),
             oid: value.oid,
+            table_stats: None,
         }
     }
 }
@@ -76,11 +78,53 @@ pub struct TracedLock {
     pub schema: String,
     pub object_name: String,
     pub mode: String,
+    /// The kind of the locked object, eg. `Table`, `Type` or `Advisory`
     pub relkind: &'static str,
+    /// The `locktype` from `pg_locks`, eg. `relation`, `object` or `advisory`
+    pub lock_type: &'static str,
+    /// Human-readable description of what is locked, eg. `Type public.mood`
+    pub target: String,
     pub oid: u32,
     pub maybe_dangerous: bool,
     pub blocked_queries: Vec<&'static str>,
     pub lock_duration_millis: u64,
+    /// Size and activity of the locked table in the reference database, with `--stats-from-db`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub table_stats: Option<TableStats>,
+}
+
+/// A query that ran against a locked table from another connection while the statement held its locks
+#[derive(Debug, Eq, PartialEq, Clone, Serialize)]
+pub struct Probe {
+    pub schema: String,
+    pub table_name: String,
+    pub query: &'static str,
+    pub blocked: bool,
+    /// Set if the probe failed for a reason other than locks, so it is not known whether it was blocked
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A lock that the statement waited for, seen from a monitoring connection with `--monitor-waits`
+#[derive(Debug, Eq, PartialEq, Clone, Serialize)]
+pub struct LockWait {
+    pub lock: TracedLock,
+    /// Approximate time spent waiting for the lock, at the resolution of the sampling
+    pub waited_millis: u64,
+    /// Backends that held or queued for a conflicting lock, from `pg_blocking_pids`
+    pub blocking_pids: Vec<i32>,
+}
+
+impl From<&ProbeResult> for Probe {
+    fn from(value: &ProbeResult) -> Self {
+        Probe {
+            schema: value.schema.clone(),
+            table_name: value.table_name.clone(),
+            query: value.kind.as_str(),
+            blocked: value.blocked,
+            error: value.error.clone(),
+        }
+    }
 }
 
 #[derive(Debug, Eq, PartialEq, Clone, Serialize)]
@@ -90,6 +134,12 @@ pub struct Column {
     pub column_name: String,
     pub data_type: String,
     pub nullable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub identity: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub generated: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
 }
 
 impl From<&ColumnMetadata> for Column {
@@ -100,6 +150,9 @@ impl From<&ColumnMetadata> for Column {
             column_name: meta.column_name.clone(),
             data_type: meta.typename.clone(),
             nullable: meta.nullable,
+            identity: meta.identity.clone(),
+            generated: meta.generated.clone(),
+            default: meta.default.clone(),
         }
     }
 }
@@ -159,8 +212,46 @@ impl From<&crate::tracing::tracer::ModifiedConstraint> for ModifiedConstraint {
     }
 }
 
+/// A view, function, trigger or sequence, with its definition
+#[derive(Debug, Eq, PartialEq, Clone, Serialize)]
+pub struct SchemaObject {
+    /// `view`, `function`, `trigger` or `sequence`
+    pub kind: &'static str,
+    pub schema_name: String,
+    pub name: String,
+    pub definition: String,
+}
+
+impl From<&crate::tracing::queries::SchemaObject> for SchemaObject {
+    fn from(value: &crate::tracing::queries::SchemaObject) -> Self {
+        SchemaObject {
+            kind: value.kind.as_str(),
+            schema_name: value.schema_name.clone(),
+            name: value.name.clone(),
+            definition: value.definition.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Serialize)]
+pub struct ModifiedSchemaObject {
+    pub old: SchemaObject,
+    pub new: SchemaObject,
+}
+
+impl From<&crate::tracing::tracer::ModifiedSchemaObject> for ModifiedSchemaObject {
+    fn from(meta: &crate::tracing::tracer::ModifiedSchemaObject) -> Self {
+        ModifiedSchemaObject {
+            old: SchemaObject::from(&meta.old),
+            new: SchemaObject::from(&meta.new),
+        }
+    }
+}
+
 #[derive(Debug, Eq, PartialEq, Clone, Serialize)]
 pub struct FullSqlStatementLockTrace {
+    /// The transaction in the script that the statement ran in, starting at 1
+    pub transaction_number: usize,
     pub statement_number_in_transaction: usize,
     pub line_number: usize,
     pub sql: String,
@@ -173,8 +264,102 @@ pub struct FullSqlStatementLockTrace {
     pub new_constraints: Vec<Constraint>,
     pub altered_constraints: Vec<ModifiedConstraint>,
     pub new_objects: Vec<DbObject>,
+    /// Views, functions, triggers and sequences created by the statement
+    pub new_schema_objects: Vec<SchemaObject>,
+    /// Views, functions, triggers and sequences replaced or altered by the statement
+    pub altered_schema_objects: Vec<ModifiedSchemaObject>,
+    /// Views, functions, triggers and sequences dropped by the statement
+    pub dropped_schema_objects: Vec<SchemaObject>,
     pub lock_timeout_millis: u64,
+    /// Rows inserted, updated or deleted by the statement
+    pub rows_written: u64,
+    /// Bytes of write-ahead log written by the statement
+    pub bytes_written: u64,
     pub triggered_rules: Vec<Hint>,
+    /// Probe queries that ran against locked tables after the statement, with `--probe`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub probes: Vec<Probe>,
+    /// Locks that were released by `ROLLBACK TO SAVEPOINT`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub released_locks: Vec<TracedLock>,
+    /// True if a later `ROLLBACK TO SAVEPOINT` undid this statement
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub rolled_back: bool,
+    /// Phases of a `CONCURRENTLY` index build that were seen while the statement ran
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub phases: Vec<String>,
+    /// Locks the statement waited for, with `--monitor-waits`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub lock_waits: Vec<LockWait>,
+}
+
+/// A table that got synthetic rows before the script was traced
+#[derive(Debug, Eq, PartialEq, Clone, Serialize)]
+pub struct SeededTable {
+    pub schema: String,
+    pub table_name: String,
+    pub rows_inserted: u64,
+    pub total_rows: u64,
+    /// Size of the table with indexes and toast after seeding
+    pub bytes: u64,
+    /// Why no rows were inserted, if the table could not be seeded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skipped: Option<String>,
+}
+
+/// The hints that a statement triggered when traced on one postgres version
+#[derive(Debug, Eq, PartialEq, Clone, Serialize)]
+pub struct VersionFindings {
+    pub server_version: String,
+    /// `None` if this version did not trace the script
+    pub hint_ids: Option<Vec<String>>,
+}
+
+/// A statement that triggered different hints on different postgres versions, see `version_differences`
+#[derive(Debug, Eq, PartialEq, Clone, Serialize)]
+pub struct VersionDifference {
+    pub script: Option<String>,
+    pub line_number: usize,
+    pub sql: String,
+    pub findings: Vec<VersionFindings>,
+}
+
+/// A transaction in a script, which is split into transactions at `BEGIN`, `COMMIT` and `ROLLBACK`
+#[derive(Debug, Eq, PartialEq, Clone, Serialize)]
+pub struct TransactionSection {
+    pub transaction_number: usize,
+    pub statement_count: usize,
+    pub duration_millis: u64,
+    pub dangerous_locks_count: usize,
+    /// True if the script ended the transaction with `ROLLBACK`
+    pub rolled_back: bool,
+}
+
+/// The strongest lock mode held on an object, from the statement that took it
+#[derive(Debug, Eq, PartialEq, Clone, Serialize)]
+pub struct LockModeChange {
+    pub mode: String,
+    pub maybe_dangerous: bool,
+    /// Milliseconds from the start of the trace to the start of the statement that took the lock
+    pub at_millis: u64,
+    pub statement_number_in_transaction: usize,
+}
+
+/// When an object was locked in a transaction, how the lock got stronger, and when it was released
+#[derive(Debug, Eq, PartialEq, Clone, Serialize)]
+pub struct ObjectLockTimeline {
+    pub transaction_number: usize,
+    pub schema: String,
+    pub object_name: String,
+    pub relkind: &'static str,
+    /// Milliseconds from the start of the trace to the start of the statement that first locked the object
+    pub locked_at_millis: u64,
+    /// Milliseconds from the start of the trace to the end of the transaction, or the `ROLLBACK TO
+    /// SAVEPOINT` that released the lock
+    pub released_at_millis: u64,
+    pub held_millis: u64,
+    /// The first mode, followed by each mode that was stronger than the ones before it
+    pub modes: Vec<LockModeChange>,
 }
 
 #[derive(Debug, Eq, PartialEq, Clone, Serialize)]
@@ -188,6 +373,39 @@ pub struct FullTraceData {
     pub skip_summary: bool,
     pub dangerous_locks_count: usize,
     pub passed_all_checks: bool,
+    /// Tables that got synthetic rows before tracing, see `seed::seed_tables`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub seeded_tables: Vec<SeededTable>,
+    /// The transactions in the script, statements refer to them by `transaction_number`
+    pub transactions: Vec<TransactionSection>,
+    /// Relations locked by the script, ordered by when they were first locked, see `lock_timeline`
+    pub lock_timeline: Vec<ObjectLockTimeline>,
+    /// The `server_version` of the postgres server that traced the script, with `--pg-bin`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server_version: Option<String>,
+}
+
+/// True if any of `rules` has `Severity::Error`, which decides whether a script passed the checks
+pub fn any_errors<'a>(rules: impl IntoIterator<Item = &'a Hint>) -> bool {
+    rules
+        .into_iter()
+        .any(|hint| hint.severity == Severity::Error)
+}
+
+impl FullTraceData {
+    /// True if any statement triggered a hint with `Severity::Error`
+    pub fn has_errors(&self) -> bool {
+        any_errors(
+            self.statements
+                .iter()
+                .flat_map(|st| st.triggered_rules.iter()),
+        )
+    }
+
+    /// Set `passed_all_checks` after hints were removed or changed severity
+    pub fn update_passed_all_checks(&mut self) {
+        self.passed_all_checks = !self.has_errors();
+    }
 }
 
 mod datefmt {
@@ -210,6 +428,7 @@ pub struct Hint {
     pub workaround: String,
     pub help: String,
     pub url: String,
+    pub severity: Severity,
 }
 
 impl Hint {
@@ -229,6 +448,7 @@ impl Hint {
             workaround: workaround.to_string(),
             help: help.to_string(),
             url: hint_url(code),
+            severity: Severity::default(),
         }
     }
 }
@@ -248,3 +468,19 @@ pub struct LintReport {
     pub passed_all_checks: bool,
     pub skip_summary: bool,
 }
+
+impl LintReport {
+    /// True if any statement triggered a rule with `Severity::Error`
+    pub fn has_errors(&self) -> bool {
+        any_errors(
+            self.statements
+                .iter()
+                .flat_map(|st| st.triggered_rules.iter()),
+        )
+    }
+
+    /// Set `passed_all_checks` after rules were removed or changed severity
+    pub fn update_passed_all_checks(&mut self) {
+        self.passed_all_checks = !self.has_errors();
+    }
+}
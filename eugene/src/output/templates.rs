@@ -1,12 +1,36 @@
-use handlebars::Handlebars;
+use handlebars::{handlebars_helper, Handlebars};
 use once_cell::sync::Lazy;
+use serde::Serialize;
+use serde_json::Value;
 
-use crate::output::{FullTraceData, LintReport};
+use crate::hint_data::Severity;
+use crate::output::output_format::Hint;
+use crate::output::{FullTraceData, LintReport, ObjectLockTimeline, VersionDifference};
+
+fn is_warning(rule: &Value) -> bool {
+    rule.get("severity").and_then(Value::as_str) == Some("warning")
+}
+
+// `❌` for a rule that is an error, `⚠️` for a warning
+handlebars_helper!(severity_icon: |rule: Value| if is_warning(&rule) { "⚠️" } else { "❌" });
+
+// `✅` if no rules triggered, `⚠️` if all triggered rules are warnings, otherwise `❌`
+handlebars_helper!(status_icon: |rules: array| {
+    if rules.is_empty() {
+        "✅"
+    } else if rules.iter().all(is_warning) {
+        "⚠️"
+    } else {
+        "❌"
+    }
+});
 
 pub(crate) static HBARS: Lazy<Handlebars> = Lazy::new(|| {
     let mut hbars = Handlebars::new();
     hbars.set_strict_mode(true);
     hbars.register_escape_fn(handlebars::no_escape);
+    hbars.register_helper("severity_icon", Box::new(severity_icon));
+    hbars.register_helper("status_icon", Box::new(status_icon));
     hbars
         .register_template_string("locks_table_md", include_str!("locks_table.md.hbs"))
         .expect("Failed to register lock_table");
@@ -19,51 +43,173 @@ pub(crate) static HBARS: Lazy<Handlebars> = Lazy::new(|| {
     hbars
 });
 
+/// Columns in the bars of `lock_gantt`
+const GANTT_WIDTH: u64 = 50;
+
+/// Render `timeline` as a text Gantt chart, with one bar for each locked object
+///
+/// `#` marks time spent holding a dangerous lock, and `=` time spent holding other locks.
+pub fn lock_gantt(timeline: &[ObjectLockTimeline], total_millis: u64) -> String {
+    let total_millis = total_millis.max(1);
+    let column = |millis: u64| (millis * GANTT_WIDTH / total_millis).min(GANTT_WIDTH);
+    let labels: Vec<_> = timeline
+        .iter()
+        .map(|entry| format!("{}.{}", entry.schema, entry.object_name))
+        .collect();
+    let label_width = labels.iter().map(|label| label.len()).max().unwrap_or(0);
+    let mut out = String::new();
+    for (entry, label) in timeline.iter().zip(labels) {
+        let start = column(entry.locked_at_millis);
+        let end = column(entry.released_at_millis).max(start + 1);
+        let bar: String = (0..GANTT_WIDTH.max(end))
+            .map(|col| {
+                if col < start || col >= end {
+                    return ' ';
+                }
+                let mode = entry
+                    .modes
+                    .iter()
+                    .rev()
+                    .find(|mode| column(mode.at_millis) <= col)
+                    .unwrap_or(&entry.modes[0]);
+                if mode.maybe_dangerous {
+                    '#'
+                } else {
+                    '='
+                }
+            })
+            .collect();
+        out.push_str(&format!(
+            "{label:<label_width$} |{bar}| {}ms\n",
+            entry.held_millis
+        ));
+    }
+    out
+}
+
+#[derive(Serialize)]
+struct MarkdownTrace<'a> {
+    #[serde(flatten)]
+    trace: &'a FullTraceData,
+    lock_gantt: String,
+}
+
 /// Render a markdown report from a `FullTraceData`
 pub fn to_markdown(trace: &FullTraceData) -> crate::Result<String> {
-    Ok(HBARS.render("trace_report_md", trace)?)
+    let data = MarkdownTrace {
+        trace,
+        lock_gantt: lock_gantt(&trace.lock_timeline, trace.total_duration_millis),
+    };
+    Ok(HBARS.render("trace_report_md", &data)?)
 }
 
 pub fn lint_report_to_markdown(report: &LintReport) -> crate::Result<String> {
     Ok(HBARS.render("lint_report_md", report)?)
 }
 
-pub fn trace_text(trace: &FullTraceData) -> crate::Result<String> {
-    if trace.passed_all_checks {
-        Ok(String::new())
-    } else {
-        let fname = trace.name.as_deref().unwrap_or("unnamed");
-        let mut out = String::new();
-        for statement in &trace.statements {
-            if !statement.triggered_rules.is_empty() {
-                let line = statement.line_number;
-                for rule in &statement.triggered_rules {
-                    let id = rule.id.as_str();
-                    let name = rule.name.as_str();
-                    let url = rule.url.as_str();
-                    out.push_str(&format!("{fname}:{line} {id} {name} {url}\n"));
-                }
-            }
+/// Render the triggered rules as one line each, `file:line severity id name url`, where the
+/// severity is `E` for errors and `W` for warnings
+fn triggered_rules_text<'a>(
+    fname: &str,
+    statements: impl IntoIterator<Item = (usize, &'a [Hint])>,
+) -> String {
+    let mut out = String::new();
+    for (line, rules) in statements {
+        for rule in rules {
+            let severity = match rule.severity {
+                Severity::Error => 'E',
+                Severity::Warning => 'W',
+            };
+            let id = rule.id.as_str();
+            let name = rule.name.as_str();
+            let url = rule.url.as_str();
+            out.push_str(format!("{fname}:{line} {severity} {id} {name} {url}").trim_end());
+            out.push('\n');
         }
-        out.pop();
-        Ok(out)
     }
+    out.pop();
+    out
 }
 
-pub fn lint_text(report: &LintReport) -> crate::Result<String> {
+pub fn trace_text(trace: &FullTraceData) -> crate::Result<String> {
+    let mut statements = trace
+        .statements
+        .iter()
+        .filter(|statement| !statement.triggered_rules.is_empty())
+        .map(|statement| (statement.line_number, statement.triggered_rules.as_slice()))
+        .peekable();
+    if statements.peek().is_none() {
+        return Ok(String::new());
+    }
+    let fname = trace.name.as_deref().unwrap_or("unnamed");
     let mut out = String::new();
-    let fname = report.name.as_deref().unwrap_or("unnamed");
-    for statement in &report.statements {
-        if !statement.triggered_rules.is_empty() {
-            let line = statement.line_number;
-            for rule in &statement.triggered_rules {
-                let id = rule.id.as_str();
-                let name = rule.name.as_str();
-                let url = rule.url.as_str();
-                out.push_str(&format!("{fname}:{line} {id} {name} {url}\n"));
-            }
-        }
+    if let Some(version) = trace.server_version.as_deref() {
+        out.push_str(&format!("Postgres {version}\n"));
     }
-    out.pop();
+    out.push_str(&triggered_rules_text(fname, statements));
     Ok(out)
 }
+
+fn hint_list(hint_ids: &Option<Vec<String>>) -> String {
+    match hint_ids {
+        None => "not traced".to_string(),
+        Some(ids) if ids.is_empty() => "none".to_string(),
+        Some(ids) => ids.join(", "),
+    }
+}
+
+/// Render the statements that triggered different hints on different postgres versions as text
+pub fn version_differences_text(differences: &[VersionDifference]) -> String {
+    differences
+        .iter()
+        .map(|difference| {
+            let fname = difference.script.as_deref().unwrap_or("unnamed");
+            let findings = difference
+                .findings
+                .iter()
+                .map(|f| format!("postgres {}: {}", f.server_version, hint_list(&f.hint_ids)))
+                .collect::<Vec<_>>()
+                .join("; ");
+            format!("{fname}:{} {findings}", difference.line_number)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Render the statements that triggered different hints on different postgres versions as a
+/// markdown table, with one column for each version
+pub fn version_differences_markdown(
+    server_versions: &[String],
+    differences: &[VersionDifference],
+) -> String {
+    let mut out = String::from("## Findings that differ between postgres versions\n\n");
+    if differences.is_empty() {
+        out.push_str("All postgres versions triggered the same hints.\n");
+        return out;
+    }
+    out.push_str("| Script | Line |");
+    for version in server_versions {
+        out.push_str(&format!(" {version} |"));
+    }
+    out.push_str("\n|--------|------|");
+    out.push_str(&"-----|".repeat(server_versions.len()));
+    out.push('\n');
+    for difference in differences {
+        let fname = difference.script.as_deref().unwrap_or("unnamed");
+        out.push_str(&format!("| `{fname}` | {} |", difference.line_number));
+        for findings in &difference.findings {
+            out.push_str(&format!(" {} |", hint_list(&findings.hint_ids)));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+pub fn lint_text(report: &LintReport) -> crate::Result<String> {
+    let fname = report.name.as_deref().unwrap_or("unnamed");
+    let statements = report
+        .statements
+        .iter()
+        .map(|statement| (statement.line_number, statement.triggered_rules.as_slice()));
+    Ok(triggered_rules_text(fname, statements))
+}
@@ -112,6 +112,19 @@ impl LockMode {
             AccessExclusive => "AccessExclusiveLock",
         }
     }
+    /// Convert to the mode in `LOCK TABLE ... IN <mode> MODE`
+    pub fn to_sql_str(&self) -> &'static str {
+        match self {
+            AccessShare => "ACCESS SHARE",
+            RowShare => "ROW SHARE",
+            RowExclusive => "ROW EXCLUSIVE",
+            ShareUpdateExclusive => "SHARE UPDATE EXCLUSIVE",
+            Share => "SHARE",
+            ShareRowExclusive => "SHARE ROW EXCLUSIVE",
+            Exclusive => "EXCLUSIVE",
+            AccessExclusive => "ACCESS EXCLUSIVE",
+        }
+    }
     /// What lock modes this lock mode conflicts with.
     pub fn conflicts_with(&self) -> &[LockMode] {
         match self {
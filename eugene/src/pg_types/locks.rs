@@ -26,19 +26,169 @@ impl LockableTarget {
     }
 }
 
-/// A lock targets a target object with a specific mode.
+/// What a lock is held on, corresponding to the `locktype` column in `pg_locks`.
 #[derive(Debug, Eq, PartialEq, Clone, Hash)]
-pub struct Lock {
-    pub(crate) mode: LockMode,
-    pub(crate) target: LockableTarget,
+pub enum LockTarget {
+    /// A relation in `pg_class`, such as a table, index or sequence
+    Relation(LockableTarget),
+    /// Another database object, such as a type, schema, function or database
+    Object {
+        kind: &'static str,
+        schema: String,
+        object_name: String,
+        oid: Oid,
+    },
+    /// An application defined lock, taken by eg. `pg_advisory_xact_lock`
+    Advisory { key: String },
+    /// The ID of another transaction, which a transaction waits for to lock rows it has changed
+    TransactionId,
+    /// A single row in a relation
+    Tuple {
+        relation: LockableTarget,
+        page: i32,
+        tuple: i16,
+    },
 }
 
-impl Lock {
-    pub fn target_oid(&self) -> Oid {
-        self.target.oid
+/// Map the `type` column of `pg_identify_object` to a human-readable kind
+pub fn object_kind(object_type: &str) -> &'static str {
+    match object_type {
+        "type" => "Type",
+        "schema" => "Schema",
+        "function" => "Function",
+        "procedure" => "Procedure",
+        "aggregate" => "Aggregate",
+        "database" => "Database",
+        "role" => "Role",
+        "extension" => "Extension",
+        "tablespace" => "Tablespace",
+        "collation" => "Collation",
+        "publication" => "Publication",
+        "subscription" => "Subscription",
+        "foreign-data wrapper" => "ForeignDataWrapper",
+        "server" => "Server",
+        _ => "Object",
     }
 }
 
+impl LockTarget {
+    /// The `locktype` in `pg_locks` for this target
+    pub fn lock_type(&self) -> &'static str {
+        match self {
+            LockTarget::Relation(_) => "relation",
+            LockTarget::Object { .. } => "object",
+            LockTarget::Advisory { .. } => "advisory",
+            LockTarget::TransactionId => "transactionid",
+            LockTarget::Tuple { .. } => "tuple",
+        }
+    }
+
+    /// The kind of the target, the relation kind for relations and rows
+    pub fn kind(&self) -> &'static str {
+        match self {
+            LockTarget::Relation(target) => target.rel_kind.as_str(),
+            LockTarget::Object { kind, .. } => kind,
+            LockTarget::Advisory { .. } => "Advisory",
+            LockTarget::TransactionId => "TransactionId",
+            LockTarget::Tuple { .. } => "Row",
+        }
+    }
+
+    /// The schema of the target, or an empty string if it does not belong to a schema
+    pub fn schema(&self) -> &str {
+        match self {
+            LockTarget::Relation(target)
+            | LockTarget::Tuple {
+                relation: target, ..
+            } => &target.schema,
+            LockTarget::Object { schema, .. } => schema,
+            LockTarget::Advisory { .. } | LockTarget::TransactionId => "",
+        }
+    }
+
+    /// The name of the target within its schema
+    pub fn object_name(&self) -> String {
+        match self {
+            LockTarget::Relation(target) => target.object_name.clone(),
+            LockTarget::Object { object_name, .. } => object_name.clone(),
+            LockTarget::Advisory { key } => key.clone(),
+            LockTarget::TransactionId => "transaction".to_string(),
+            LockTarget::Tuple {
+                relation,
+                page,
+                tuple,
+            } => format!("{} ({page},{tuple})", relation.object_name),
+        }
+    }
+
+    /// The `oid` of the target, or 0 if it has none
+    pub fn oid(&self) -> Oid {
+        match self {
+            LockTarget::Relation(target)
+            | LockTarget::Tuple {
+                relation: target, ..
+            } => target.oid,
+            LockTarget::Object { oid, .. } => *oid,
+            LockTarget::Advisory { .. } | LockTarget::TransactionId => 0,
+        }
+    }
+
+    /// The `oid` of the relation the target is in, for relations and rows
+    pub fn relation_oid(&self) -> Option<Oid> {
+        match self {
+            LockTarget::Relation(target)
+            | LockTarget::Tuple {
+                relation: target, ..
+            } => Some(target.oid),
+            _ => None,
+        }
+    }
+}
+
+impl Display for LockTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LockTarget::Relation(target) => {
+                write!(
+                    f,
+                    "{} {}.{}",
+                    target.rel_kind, target.schema, target.object_name
+                )
+            }
+            LockTarget::Object {
+                kind,
+                schema,
+                object_name,
+                ..
+            } if schema.is_empty() => write!(f, "{kind} {object_name}"),
+            LockTarget::Object {
+                kind,
+                schema,
+                object_name,
+                ..
+            } => write!(f, "{kind} {schema}.{object_name}"),
+            LockTarget::Advisory { key } => write!(f, "advisory lock {key}"),
+            LockTarget::TransactionId => write!(f, "transaction ID"),
+            LockTarget::Tuple {
+                relation,
+                page,
+                tuple,
+            } => write!(
+                f,
+                "row ({page},{tuple}) in {} {}.{}",
+                relation.rel_kind, relation.schema, relation.object_name
+            ),
+        }
+    }
+}
+
+/// A lock targets a target object with a specific mode.
+#[derive(Debug, Eq, PartialEq, Clone, Hash)]
+pub struct Lock {
+    pub(crate) mode: LockMode,
+    pub(crate) target: LockTarget,
+}
+
 /// Errors that can occur when creating a `Lock`
 #[derive(Debug, Eq, PartialEq)]
 pub enum InvalidLockError {
@@ -56,6 +206,7 @@ impl Display for InvalidLockError {
 }
 
 impl Lock {
+    /// A lock on a relation
     pub fn new<S: AsRef<str> + Into<String>>(
         schema: S,
         table_name: S,
@@ -63,20 +214,129 @@ impl Lock {
         rel_kind: char,
         oid: Oid,
     ) -> Result<Self, InvalidLockError> {
-        let mode = LockMode::from_db_str(mode.as_ref())
-            .ok_or_else(|| InvalidLockError::InvalidMode(mode.into()))?;
         let target = LockableTarget::new(schema, table_name, rel_kind, oid)
             .ok_or(InvalidLockError::InvalidRelKind(rel_kind))?;
+        Self::on(mode, LockTarget::Relation(target))
+    }
+
+    /// A lock on any kind of target
+    pub fn on<S: AsRef<str> + Into<String>>(
+        mode: S,
+        target: LockTarget,
+    ) -> Result<Self, InvalidLockError> {
+        let mode = LockMode::from_db_str(mode.as_ref())
+            .ok_or_else(|| InvalidLockError::InvalidMode(mode.into()))?;
         Ok(Self { mode, target })
     }
 
-    pub fn target(&self) -> &LockableTarget {
+    /// The relation that is locked, or the relation of the locked row
+    ///
+    /// Returns `None` for locks on other kinds of targets, see [`Lock::lock_target`].
+    pub fn target(&self) -> Option<&LockableTarget> {
+        match &self.target {
+            LockTarget::Relation(target)
+            | LockTarget::Tuple {
+                relation: target, ..
+            } => Some(target),
+            _ => None,
+        }
+    }
+    /// The mode the lock is held in
+    pub fn mode(&self) -> LockMode {
+        self.mode
+    }
+    pub fn target_oid(&self) -> Oid {
+        self.target.oid()
+    }
+    /// What the lock is held on, for locks on any kind of target
+    pub fn lock_target(&self) -> &LockTarget {
         &self.target
     }
+    /// The relation that is locked, if this is a lock on a relation
+    pub fn relation(&self) -> Option<&LockableTarget> {
+        match &self.target {
+            LockTarget::Relation(target) => Some(target),
+            _ => None,
+        }
+    }
+    /// Locks on relations are dangerous if they block queries. Locks on other targets only conflict
+    /// with DDL or explicit lock requests for the same target, so they are not considered dangerous.
+    pub fn maybe_dangerous(&self) -> bool {
+        self.relation().is_some() && self.mode.dangerous()
+    }
     pub fn blocked_queries(&self) -> Vec<&'static str> {
-        self.mode.blocked_queries()
+        if self.relation().is_some() {
+            self.mode.blocked_queries()
+        } else {
+            vec![]
+        }
     }
     pub fn blocked_ddl(&self) -> Vec<&'static str> {
         self.mode.blocked_ddl()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_human_readable_targets() {
+        let table = LockableTarget::new("public", "books", 'r', 1).unwrap();
+        let targets = [
+            LockTarget::Relation(table.clone()),
+            LockTarget::Object {
+                kind: object_kind("type"),
+                schema: "public".to_string(),
+                object_name: "mood".to_string(),
+                oid: 2,
+            },
+            LockTarget::Object {
+                kind: object_kind("schema"),
+                schema: String::new(),
+                object_name: "public".to_string(),
+                oid: 3,
+            },
+            LockTarget::Advisory {
+                key: "42".to_string(),
+            },
+            LockTarget::TransactionId,
+            LockTarget::Tuple {
+                relation: table,
+                page: 0,
+                tuple: 1,
+            },
+        ];
+        let described: Vec<_> = targets.iter().map(|t| t.to_string()).collect();
+        assert_eq!(
+            described,
+            vec![
+                "Table public.books",
+                "Type public.mood",
+                "Schema public",
+                "advisory lock 42",
+                "transaction ID",
+                "row (0,1) in Table public.books",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_only_relation_locks_are_dangerous() {
+        let table = Lock::new("public", "books", "AccessExclusiveLock", 'r', 1).unwrap();
+        assert!(table.maybe_dangerous());
+        let advisory = Lock::on(
+            "ExclusiveLock",
+            LockTarget::Advisory {
+                key: "42".to_string(),
+            },
+        )
+        .unwrap();
+        assert!(!advisory.maybe_dangerous());
+        assert!(advisory.blocked_queries().is_empty());
+        assert_eq!(table.target().unwrap().object_name, "books");
+        assert_eq!(advisory.target(), None);
+        assert_eq!(table.target_oid(), 1);
+        assert_eq!(advisory.lock_target().oid(), 0);
+    }
+}
@@ -7,16 +7,22 @@ use itertools::Itertools;
 use once_cell::sync::Lazy;
 use pretty_assertions::assert_eq;
 use rayon::prelude::*;
+use regex::Regex;
 use serde::Serialize;
 
 use crate::error::{ContextualError, InnerError};
 use crate::hint_data::{data_by_id, HintId};
 use crate::lints::lint;
+use crate::lints::naming::NamingConventions;
+use crate::lock_budget::LockBudget;
 use crate::output::{full_trace_data, GenericHint, Settings};
 use crate::parse_scripts::break_into_files;
+use crate::rule_set::{builtin_rules, RuleSet};
+use crate::tracing::TraceOptions;
 use crate::{generate_new_test_db, hint_data, output, perform_trace, ClientSource, SqlScript};
 
 static DEFAULT_SETTINGS: Lazy<Settings> = Lazy::new(|| Settings::new(true, true));
+static MEASURED_MILLIS: Lazy<Regex> = Lazy::new(|| Regex::new(r"for \d+ms").unwrap());
 static HBARS: Lazy<Handlebars> = Lazy::new(|| {
     let mut hbars = Handlebars::new();
     hbars.set_strict_mode(true);
@@ -75,6 +81,14 @@ fn every_lint_has_an_example_migration() -> crate::Result<()> {
 
 fn snapshot_lint(id: &str, kind: &str, script: &str) -> crate::Result<String> {
     let mut reports = vec![];
+    // W16 is only checked when the config has naming conventions
+    let naming_rules;
+    let rules = if id == "W16" {
+        naming_rules = RuleSet::default().with_lint_rule(NamingConventions::default());
+        &naming_rules
+    } else {
+        builtin_rules()
+    };
     for (name, sql) in break_into_files(script)? {
         let report = lint(
             name.map(|n| format!("examples/{id}/{kind}/{n}")),
@@ -82,6 +96,7 @@ fn snapshot_lint(id: &str, kind: &str, script: &str) -> crate::Result<String> {
             &[],
             false,
             &[],
+            rules,
         )?;
         reports.push(output::templates::lint_report_to_markdown(&report)?);
     }
@@ -110,20 +125,25 @@ fn snapshot_trace(
     } else {
         [].as_slice()
     };
+    // E18 is only checked when the config has a lock budget
+    let budget_rules;
+    let rules = if id == "E18" {
+        budget_rules = RuleSet::default().with_trace_hint(LockBudget::default());
+        &budget_rules
+    } else {
+        builtin_rules()
+    };
 
     for (ix, (name, script)) in scripts.into_iter().enumerate() {
         let path = format!("examples/{}/{kind}/{}", id, name.unwrap());
         let sql = script.into();
         let sql_script = SqlScript { name: path, sql };
 
-        let trace = perform_trace(
-            &sql_script,
-            &mut connection_settings,
-            ignored,
-            true,
-            &[],
-            ix == last,
-        )?;
+        let options = TraceOptions::new(rules)
+            .with_ignored_hints(ignored)
+            .with_commit(true)
+            .with_final(ix == last);
+        let trace = perform_trace(&sql_script, &mut connection_settings, &options)?;
         let mut report = full_trace_data(&trace, *output_settings);
 
         // Try to make the report deterministic
@@ -134,9 +154,18 @@ fn snapshot_trace(
             lock.oid = 1;
             lock.lock_duration_millis = 10;
         });
-        report.total_duration_millis = 10;
+        report.total_duration_millis = 10 * report.statements.len() as u64;
         for statement_trace in report.statements.iter_mut() {
             statement_trace.duration_millis = 10;
+            statement_trace.bytes_written = 0;
+            // Phases of concurrent index builds depend on when the monitor sampled
+            statement_trace.phases.clear();
+            // Lock budget hints report measured durations
+            for hint in statement_trace.triggered_rules.iter_mut() {
+                hint.help = MEASURED_MILLIS
+                    .replace_all(&hint.help, "for 1100ms")
+                    .to_string();
+            }
             statement_trace.new_locks_taken.iter_mut().for_each(|lock| {
                 lock.oid = 1;
                 lock.lock_duration_millis = 10;
@@ -147,6 +176,8 @@ fn snapshot_trace(
             });
         }
 
+        report.lock_timeline = output::lock_timeline(&report.statements);
+
         let md = report.to_markdown()?;
         reports.push(md);
     }
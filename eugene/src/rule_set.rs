@@ -0,0 +1,458 @@
+use std::fmt::{Debug, Formatter};
+
+use once_cell::sync::Lazy;
+
+use crate::comments::{filter_rules, LintAction};
+use crate::hint_data::{hint_url, HintId, StaticHintData, ALL};
+use crate::hints::HintInfo;
+use crate::lints::custom::CustomRule;
+use crate::lints::rules::LintRule;
+use crate::lints::LintContext;
+use crate::output::output_format::{GenericHint, Hint};
+use crate::tracing::tracer::StatementCtx;
+
+/// A rule that checks a statement by looking at its syntax tree, used by `eugene lint`.
+pub trait LintCheck: Send + Sync {
+    /// Static data describing the rule, used in output and hint listings
+    fn meta(&self) -> &'static StaticHintData;
+    /// Returns a help message if the statement triggers the rule
+    fn check(&self, stmt: LintContext) -> Option<String>;
+    /// Link to documentation for the rule, built-in rules link to the eugene documentation
+    fn doc_url(&self) -> String {
+        hint_url(self.meta().id)
+    }
+}
+
+/// A hint that checks a statement by looking at the trace of running it, used by `eugene trace`.
+pub trait TraceCheck: Send + Sync {
+    /// Static data describing the hint, used in output and hint listings
+    fn meta(&self) -> &'static StaticHintData;
+    /// Returns a help message if the statement trace triggers the hint
+    fn check(&self, stmt: &StatementCtx) -> Option<String>;
+    /// Link to documentation for the hint, built-in hints link to the eugene documentation
+    fn doc_url(&self) -> String {
+        hint_url(self.meta().id)
+    }
+}
+
+impl LintCheck for LintRule {
+    fn meta(&self) -> &'static StaticHintData {
+        self.meta
+    }
+    fn check(&self, stmt: LintContext) -> Option<String> {
+        (self.check)(stmt)
+    }
+}
+
+impl TraceCheck for HintInfo {
+    fn meta(&self) -> &'static StaticHintData {
+        self.meta
+    }
+    fn check(&self, stmt: &StatementCtx) -> Option<String> {
+        (self.render_help)(stmt)
+    }
+}
+
+impl HintId for dyn LintCheck {
+    fn id(&self) -> &str {
+        self.meta().id
+    }
+}
+
+impl HintId for dyn TraceCheck {
+    fn id(&self) -> &str {
+        self.meta().id
+    }
+}
+
+fn to_hint(meta: &StaticHintData, url: String, help: String) -> Hint {
+    Hint {
+        url,
+        ..Hint::new(
+            meta.id,
+            meta.name,
+            meta.condition,
+            meta.effect,
+            meta.workaround,
+            help,
+        )
+    }
+}
+
+/// The lint rules and trace hints that `lints::lint` and `perform_trace` check statements against.
+///
+/// `RuleSet::default()` contains all the built-in rules and hints. Library users can add
+/// their own rules by implementing `LintCheck` or `TraceCheck`:
+///
+/// ```
+/// use eugene::hint_data::StaticHintData;
+/// use eugene::lints::{LintContext, StatementSummary};
+/// use eugene::rule_set::{LintCheck, RuleSet};
+///
+/// static NO_ENUMS: StaticHintData = StaticHintData {
+///     id: "X1",
+///     name: "Creating an enum",
+///     condition: "A new enum type is created",
+///     effect: "We prefer lookup tables",
+///     workaround: "Create a lookup table",
+///     bad_example: "CREATE TYPE color AS ENUM ('red');",
+///     good_example: None,
+/// };
+///
+/// struct NoEnums;
+///
+/// impl LintCheck for NoEnums {
+///     fn meta(&self) -> &'static StaticHintData {
+///         &NO_ENUMS
+///     }
+///     fn check(&self, stmt: LintContext) -> Option<String> {
+///         match stmt.statement() {
+///             StatementSummary::CreateEnum { name } => Some(format!("Enum `{name}` created")),
+///             _ => None,
+///         }
+///     }
+/// }
+///
+/// let rules = RuleSet::default().with_lint_rule(NoEnums);
+/// let report = eugene::lints::lint(
+///     None,
+///     "CREATE TYPE color AS ENUM ('red');",
+///     &[],
+///     false,
+///     &[],
+///     &rules,
+/// )
+/// .unwrap();
+/// assert!(report.statements[0]
+///     .triggered_rules
+///     .iter()
+///     .any(|hint| hint.id == "X1"));
+/// ```
+pub struct RuleSet {
+    lint_rules: Vec<Box<dyn LintCheck>>,
+    trace_hints: Vec<Box<dyn TraceCheck>>,
+    custom_rules: Vec<CustomRule>,
+}
+
+static BUILTIN_RULES: Lazy<RuleSet> = Lazy::new(RuleSet::builtin);
+
+/// A shared rule set with all the built-in rules and hints
+pub fn builtin_rules() -> &'static RuleSet {
+    &BUILTIN_RULES
+}
+
+impl Default for RuleSet {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl Debug for RuleSet {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RuleSet")
+            .field(
+                "lint_rules",
+                &self.lint_rules().map(|r| r.meta().id).collect::<Vec<_>>(),
+            )
+            .field(
+                "trace_hints",
+                &self.trace_hints().map(|h| h.meta().id).collect::<Vec<_>>(),
+            )
+            .field("custom_rules", &self.custom_rules)
+            .finish()
+    }
+}
+
+/// Rule sets are considered equal if they have rules with the same IDs
+impl PartialEq for RuleSet {
+    fn eq(&self, other: &Self) -> bool {
+        self.lint_rules()
+            .map(|r| r.meta().id)
+            .eq(other.lint_rules().map(|r| r.meta().id))
+            && self
+                .trace_hints()
+                .map(|h| h.meta().id)
+                .eq(other.trace_hints().map(|h| h.meta().id))
+            && self.custom_rules == other.custom_rules
+    }
+}
+
+impl Eq for RuleSet {}
+
+impl RuleSet {
+    /// A rule set without any rules or hints
+    pub fn empty() -> Self {
+        RuleSet {
+            lint_rules: vec![],
+            trace_hints: vec![],
+            custom_rules: vec![],
+        }
+    }
+
+    /// A rule set with all the built-in rules and hints
+    pub fn builtin() -> Self {
+        let mut rules = Self::empty();
+        for rule in crate::lints::rules::all_rules() {
+            rules.lint_rules.push(Box::new(*rule));
+        }
+        for hint in crate::hints::all_hints() {
+            rules.trace_hints.push(Box::new(*hint));
+        }
+        rules
+    }
+
+    /// Add a rule that is checked by `lints::lint`
+    pub fn with_lint_rule<R: LintCheck + 'static>(mut self, rule: R) -> Self {
+        self.lint_rules.push(Box::new(rule));
+        self
+    }
+
+    /// Add a rule that is checked by `lints::lint`, replacing any rule with the same ID
+    pub fn replace_lint_rule<R: LintCheck + 'static>(mut self, rule: R) -> Self {
+        let id = rule.meta().id;
+        self.lint_rules.retain(|existing| existing.meta().id != id);
+        self.with_lint_rule(rule)
+    }
+
+    /// Add a hint that is checked by `perform_trace`
+    pub fn with_trace_hint<H: TraceCheck + 'static>(mut self, hint: H) -> Self {
+        self.trace_hints.push(Box::new(hint));
+        self
+    }
+
+    /// Add a hint that is checked by `perform_trace`, replacing any hint with the same ID
+    pub fn replace_trace_hint<H: TraceCheck + 'static>(mut self, hint: H) -> Self {
+        let id = hint.meta().id;
+        self.trace_hints.retain(|existing| existing.meta().id != id);
+        self.with_trace_hint(hint)
+    }
+
+    /// Add rules from a configuration file, that are checked by `lints::lint`
+    pub fn with_custom_rules(mut self, rules: impl IntoIterator<Item = CustomRule>) -> Self {
+        self.custom_rules.extend(rules);
+        self
+    }
+
+    pub fn lint_rules(&self) -> impl Iterator<Item = &(dyn LintCheck + 'static)> {
+        self.lint_rules.iter().map(|rule| rule.as_ref())
+    }
+
+    pub fn trace_hints(&self) -> impl Iterator<Item = &(dyn TraceCheck + 'static)> {
+        self.trace_hints.iter().map(|hint| hint.as_ref())
+    }
+
+    pub fn custom_rules(&self) -> &[CustomRule] {
+        &self.custom_rules
+    }
+
+    /// True if the rule set has a lint rule with this ID
+    pub fn has_lint(&self, id: &str) -> bool {
+        self.lint_rules().any(|rule| rule.meta().id == id)
+            || self.custom_rules.iter().any(|rule| rule.id == id)
+    }
+
+    /// True if the rule set has a trace hint with this ID
+    pub fn has_trace(&self, id: &str) -> bool {
+        self.trace_hints().any(|hint| hint.meta().id == id)
+    }
+
+    /// Link to documentation for the lint rule or trace hint with this ID
+    fn doc_url(&self, id: &str) -> String {
+        self.lint_rules()
+            .find(|rule| rule.meta().id == id)
+            .map(|rule| rule.doc_url())
+            .or_else(|| {
+                self.trace_hints()
+                    .find(|hint| hint.meta().id == id)
+                    .map(|hint| hint.doc_url())
+            })
+            .unwrap_or_else(|| hint_url(id))
+    }
+
+    /// Static data for all rules and hints in the set, built-in ones first, in ID order
+    pub fn hint_data(&self) -> Vec<&'static StaticHintData> {
+        let mut out: Vec<&'static StaticHintData> = vec![];
+        let metas = self
+            .lint_rules()
+            .map(|rule| rule.meta())
+            .chain(self.trace_hints().map(|hint| hint.meta()));
+        for meta in metas {
+            if !out.iter().any(|m| m.id == meta.id) {
+                out.push(meta);
+            }
+        }
+        out.sort_by_key(|meta| {
+            ALL.iter()
+                .position(|builtin| builtin.id == meta.id)
+                .unwrap_or(ALL.len())
+        });
+        out
+    }
+
+    /// Describe all rules and hints in the set, like `eugene hints` does
+    pub fn generic_hints(&self) -> Vec<GenericHint> {
+        let builtin = self.hint_data().into_iter().map(|meta| GenericHint {
+            has_lint: self.has_lint(meta.id),
+            has_trace: self.has_trace(meta.id),
+            url: self.doc_url(meta.id),
+            ..GenericHint::from(meta)
+        });
+        let custom = self.custom_rules.iter().map(|rule| GenericHint {
+            id: rule.id.clone(),
+            name: rule.name.clone(),
+            condition: rule.condition.clone(),
+            effect: rule.effect.clone(),
+            workaround: rule.workaround.clone(),
+            has_lint: true,
+            has_trace: false,
+            url: rule.url.clone(),
+        });
+        builtin.chain(custom).collect()
+    }
+
+    /// Check all lint rules against a statement, skipping those that are ignored
+    pub(crate) fn lint_statement(
+        &self,
+        action: &LintAction,
+        ignored_hints: &[&str],
+        stmt: LintContext,
+    ) -> Vec<Hint> {
+        let builtin = filter_rules(action, self.lint_rules())
+            .filter(|rule| !ignored_hints.contains(&rule.id()))
+            .filter_map(|rule| {
+                rule.check(stmt)
+                    .map(|help| to_hint(rule.meta(), rule.doc_url(), help))
+            });
+        let custom = filter_rules(action, self.custom_rules.iter())
+            .filter(|rule| !ignored_hints.contains(&rule.id()))
+            .filter_map(|rule| rule.check(stmt));
+        builtin.chain(custom).collect()
+    }
+
+    /// Check all trace hints against a statement trace, skipping those that are ignored
+    pub(crate) fn trace_statement(
+        &self,
+        action: &LintAction,
+        ignored_hints: &[&str],
+        stmt: &StatementCtx,
+    ) -> Vec<Hint> {
+        filter_rules(action, self.trace_hints())
+            .filter(|hint| !ignored_hints.contains(&hint.id()))
+            .filter_map(|hint| {
+                hint.check(stmt)
+                    .map(|help| to_hint(hint.meta(), hint.doc_url(), help))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hint_data::CREATING_ENUM;
+    use crate::lints::StatementSummary;
+    use pretty_assertions::assert_eq;
+
+    struct EnumsAreFine;
+
+    impl LintCheck for EnumsAreFine {
+        fn meta(&self) -> &'static StaticHintData {
+            &CREATING_ENUM
+        }
+        fn check(&self, stmt: LintContext) -> Option<String> {
+            matches!(stmt.statement(), StatementSummary::CreateEnum { .. })
+                .then(|| "Enum created".to_string())
+        }
+    }
+
+    #[test]
+    fn test_builtin_rule_set_lists_all_hints() {
+        // W16 and E18 are only checked when naming conventions and a lock budget are configured
+        let rules = RuleSet::default()
+            .with_lint_rule(crate::lints::naming::NamingConventions::default())
+            .with_trace_hint(crate::lock_budget::LockBudget::default());
+        let listed: Vec<_> = rules.hint_data().iter().map(|meta| meta.id).collect();
+        let all: Vec<_> = ALL.iter().map(|meta| meta.id).collect();
+        assert_eq!(listed, all);
+    }
+
+    #[test]
+    fn test_custom_rule_set_only_runs_its_rules() {
+        let rules = RuleSet::empty().with_lint_rule(EnumsAreFine);
+        assert!(rules.has_lint(CREATING_ENUM.id));
+        assert!(!rules.has_trace(CREATING_ENUM.id));
+        let report = crate::lints::lint(
+            None,
+            "create type color as enum ('red'); alter table books add column data json;",
+            &[],
+            false,
+            &[],
+            &rules,
+        )
+        .unwrap();
+        assert_eq!(report.statements[0].triggered_rules[0].help, "Enum created");
+        assert!(report.statements[1].triggered_rules.is_empty());
+    }
+
+    static NO_ENUMS: StaticHintData = StaticHintData {
+        id: "X1",
+        name: "Creating an enum",
+        condition: "A new enum type is created",
+        effect: "We prefer lookup tables",
+        workaround: "Create a lookup table",
+        bad_example: "CREATE TYPE color AS ENUM ('red');",
+        good_example: None,
+    };
+
+    struct NoEnums(Option<&'static str>);
+
+    impl LintCheck for NoEnums {
+        fn meta(&self) -> &'static StaticHintData {
+            &NO_ENUMS
+        }
+        fn check(&self, stmt: LintContext) -> Option<String> {
+            matches!(stmt.statement(), StatementSummary::CreateEnum { .. })
+                .then(|| "Enum created".to_string())
+        }
+        fn doc_url(&self) -> String {
+            self.0.map(String::from).unwrap_or_default()
+        }
+    }
+
+    #[test]
+    fn test_only_builtin_rules_link_to_eugene_documentation() {
+        let url_of = |rules: &RuleSet| {
+            let report = crate::lints::lint(
+                None,
+                "create type color as enum ('red');",
+                &[],
+                false,
+                &[],
+                rules,
+            )
+            .unwrap();
+            report.statements[0]
+                .triggered_rules
+                .iter()
+                .map(|hint| (hint.id.clone(), hint.url.clone()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            url_of(&RuleSet::empty().with_lint_rule(EnumsAreFine)),
+            vec![(
+                "W13".to_string(),
+                "https://kaveland.no/eugene/hints/W13/".to_string()
+            )]
+        );
+        assert_eq!(
+            url_of(&RuleSet::empty().with_lint_rule(NoEnums(None))),
+            vec![("X1".to_string(), String::new())]
+        );
+        let documented = RuleSet::empty().with_lint_rule(NoEnums(Some("https://example.com/X1")));
+        assert_eq!(
+            url_of(&documented),
+            vec![("X1".to_string(), "https://example.com/X1".to_string())]
+        );
+        assert_eq!(documented.generic_hints()[0].url, "https://example.com/X1");
+    }
+}
@@ -0,0 +1,150 @@
+use std::path::Path;
+use std::process::Command;
+
+use crate::error::{ContextualError, ContextualResult, InnerError};
+use crate::{ClientSource, WithClient};
+
+/// Read a schema dump made with `pg_dump --schema-only` from a file
+pub fn read_schema_dump<P: AsRef<Path>>(path: P) -> crate::Result<String> {
+    let path = path.as_ref();
+    std::fs::read_to_string(path).with_context(format!("Unable to read schema dump {path:?}"))
+}
+
+/// Dump the schema of the database at `source` with `pg_dump --schema-only`
+///
+/// Ownership and privileges are left out of the dump, so it can be loaded into a server that
+/// does not have the same roles.
+pub fn dump_schema(source: &ClientSource) -> crate::Result<String> {
+    let output = Command::new("pg_dump")
+        .arg("--schema-only")
+        .arg("--no-owner")
+        .arg("--no-privileges")
+        .arg("--host")
+        .arg(&source.host)
+        .arg("--port")
+        .arg(source.port.to_string())
+        .arg("--username")
+        .arg(&source.user)
+        .arg("--dbname")
+        .arg(&source.database)
+        .env("PGPASSWORD", &source.password)
+        .output()
+        .map_err(|e| {
+            InnerError::MissingRequiredCommand("pg_dump".to_string())
+                .with_context(format!("Unable to run pg_dump: {e}"))
+        })?;
+    if !output.status.success() {
+        return Err(InnerError::UnableToDumpSchema.with_context(format!(
+            "pg_dump of {} failed: {}",
+            source.database,
+            String::from_utf8_lossy(&output.stderr)
+        )));
+    }
+    String::from_utf8(output.stdout).map_err(|_| {
+        InnerError::NotValidUtf8.with_context(format!("pg_dump of {}", source.database))
+    })
+}
+
+/// Load a plain text schema dump into the database of `target` and commit it
+///
+/// `psql` meta-commands in the dump, such as `\restrict`, are skipped. The dump must not
+/// contain data, since `COPY ... FROM stdin` needs `psql`.
+pub fn restore_schema<T: WithClient>(target: &mut T, dump: &str) -> crate::Result<()> {
+    let sql = dump
+        .lines()
+        .filter(|line| !line.starts_with('\\'))
+        .collect::<Vec<_>>()
+        .join("\n");
+    target
+        .in_transaction(true, |tx| Ok(tx.batch_execute(&sql)?))
+        .map_err(|e| {
+            InnerError::UnableToDumpSchema.with_context(format!(
+                "Unable to load schema dump, was it made with --schema-only --no-owner --no-privileges? {e}"
+            ))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generate_new_test_db;
+
+    fn source(database: &str) -> ClientSource {
+        ClientSource::new(
+            "postgres".to_string(),
+            database.to_string(),
+            "localhost".to_string(),
+            5432,
+            "postgres".to_string(),
+        )
+    }
+
+    fn empty_db() -> ClientSource {
+        let name = format!(
+            "eugene_dump_{}",
+            uuid::Uuid::new_v4().to_string().replace('-', "_")
+        );
+        source("postgres")
+            .with_client(|client| {
+                client.execute(&format!("CREATE DATABASE {name} TEMPLATE template0"), &[])?;
+                Ok(())
+            })
+            .unwrap();
+        source(&name)
+    }
+
+    #[test]
+    fn test_restores_dump_of_reference_database() {
+        let mut reference = source(&generate_new_test_db());
+        reference
+            .in_transaction(true, |tx| {
+                tx.batch_execute(
+                    "CREATE TYPE mood AS ENUM ('happy', 'sad'); \
+                     CREATE TABLE authors (id serial PRIMARY KEY, name text NOT NULL, mood mood); \
+                     CREATE FUNCTION one() RETURNS int AS $$ SELECT 1 $$ LANGUAGE sql; \
+                     INSERT INTO authors (name) VALUES ('Ursula');",
+                )?;
+                Ok(())
+            })
+            .unwrap();
+
+        let dump = dump_schema(&reference).unwrap();
+        let mut target = empty_db();
+        restore_schema(&mut target, &dump).unwrap();
+
+        let (tables, authors, search_path) = target
+            .with_client(|client| {
+                let tables: Vec<String> = client
+                    .query(
+                        "SELECT tablename::text FROM pg_tables WHERE schemaname = 'public' ORDER BY 1",
+                        &[],
+                    )?
+                    .into_iter()
+                    .map(|row| row.get(0))
+                    .collect();
+                let authors: i64 = client
+                    .query_one("SELECT count(*) FROM authors", &[])?
+                    .get(0);
+                let search_path: String = client.query_one("SHOW search_path", &[])?.get(0);
+                Ok((tables, authors, search_path))
+            })
+            .unwrap();
+        assert_eq!(
+            tables,
+            vec!["authors", "books", "for_checking_modified_constraints"]
+        );
+        assert_eq!(authors, 0);
+        assert_eq!(search_path, "\"$user\", public");
+    }
+
+    #[test]
+    fn test_restore_skips_psql_meta_commands() {
+        let dump = "\\restrict abc\nCREATE TABLE public.t (id int);\n\\unrestrict abc\n";
+        let mut target = empty_db();
+        restore_schema(&mut target, dump).unwrap();
+        let count: i64 = target
+            .with_client(|client| Ok(client.query_one("SELECT count(*) FROM t", &[])?.get(0)))
+            .unwrap();
+        assert_eq!(count, 0);
+    }
+}
@@ -0,0 +1,414 @@
+use std::collections::HashMap;
+
+use fxhash::{FxHashMap, FxHashSet};
+use postgres::types::Oid;
+use postgres::Transaction;
+use serde::Deserialize;
+
+use crate::error::ContextualResult;
+use crate::output::output_format::SeededTable;
+use crate::sqltext::quote_ident;
+
+/// How many synthetic rows to put in tables before tracing a script.
+///
+/// Tables that already have enough rows are left alone, so seeding before each script in a
+/// sequence only tops up tables with rows for the new columns.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SeedConfig {
+    /// Number of rows for tables that are not in `tables`
+    #[serde(default)]
+    pub rows: u64,
+    /// Number of rows for specific tables, by `schema.table`, or `table` for the `public` schema
+    #[serde(default)]
+    pub tables: HashMap<String, u64>,
+}
+
+impl SeedConfig {
+    /// The number of rows `schema.table` should have
+    pub fn rows_for(&self, schema: &str, table: &str) -> u64 {
+        self.tables
+            .get(&format!("{schema}.{table}"))
+            .or_else(|| (schema == "public").then(|| self.tables.get(table))?)
+            .copied()
+            .unwrap_or(self.rows)
+    }
+
+    /// True if no table should get any rows
+    pub fn is_empty(&self) -> bool {
+        self.rows == 0 && self.tables.values().all(|rows| *rows == 0)
+    }
+}
+
+struct SeedTable {
+    oid: Oid,
+    schema: String,
+    name: String,
+}
+
+struct SeedColumn {
+    name: String,
+    type_name: String,
+    base_type: String,
+    type_type: String,
+    category: String,
+    typmod: i32,
+    skip: bool,
+    fk_value: Option<String>,
+    not_null: bool,
+    /// True if the column is in a unique index with only foreign key columns
+    unique_fk: bool,
+}
+
+/// Why rows can not be generated for a table with `columns`, if they can not
+fn unseedable(columns: &[SeedColumn]) -> Option<String> {
+    for column in columns {
+        if column.fk_value.is_some() && column.unique_fk {
+            return Some(format!(
+                "foreign key column {} is unique, so it can not reference the same row from every row",
+                column.name
+            ));
+        }
+        if column.not_null && value_expression(column).is_none() && !column.skip {
+            return Some(format!(
+                "no values can be generated for column {} of type {}",
+                column.name, column.type_name
+            ));
+        }
+    }
+    None
+}
+
+/// An expression that generates a value for `column` from the row number `g`, or `None`
+/// to leave the column to its default value
+fn value_expression(column: &SeedColumn) -> Option<String> {
+    if let Some(fk_value) = &column.fk_value {
+        return Some(fk_value.clone());
+    }
+    if column.skip {
+        return None;
+    }
+    let ty = column.type_name.as_str();
+    let expression = match column.base_type.as_str() {
+        "int2" => format!("(g % 32767)::{ty}"),
+        "int4" | "int8" | "numeric" | "float4" | "float8" | "oid" => format!("g::{ty}"),
+        "text" | "varchar" | "bpchar" | "name" | "citext" if column.typmod > 4 => {
+            format!("left(md5(g::text), {})::{ty}", column.typmod - 4)
+        }
+        "text" | "varchar" | "bpchar" | "name" | "citext" => format!("md5(g::text)::{ty}"),
+        "bool" => format!("(g % 2 = 0)::{ty}"),
+        "timestamp" | "timestamptz" => format!("(now() - g * interval '1 second')::{ty}"),
+        "date" => format!("(current_date - (g % 36500)::int)::{ty}"),
+        "time" | "timetz" => format!("(time '00:00' + g * interval '1 second')::{ty}"),
+        "interval" => format!("(g * interval '1 second')::{ty}"),
+        "uuid" => format!("md5(g::text)::uuid::{ty}"),
+        "json" | "jsonb" => format!("json_build_object('n', g)::{ty}"),
+        "bytea" => format!("decode(md5(g::text), 'hex')::{ty}"),
+        _ if column.type_type == "e" => format!("enum_first(null::{ty})"),
+        _ if column.category == "A" => format!("'{{}}'::{ty}"),
+        _ => return None,
+    };
+    Some(expression)
+}
+
+fn fetch_tables(tx: &mut Transaction) -> crate::Result<Vec<SeedTable>> {
+    let sql = "SELECT c.oid, n.nspname::text, c.relname::text
+         FROM pg_catalog.pg_class c
+           JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+         WHERE c.relkind = 'r' AND NOT c.relispartition
+           AND n.nspname NOT IN ('pg_catalog', 'information_schema')
+           AND n.nspname NOT LIKE 'pg_toast%' AND n.nspname NOT LIKE 'pg_temp%'
+         ORDER BY n.nspname, c.relname";
+    let rows = tx
+        .query(sql, &[])
+        .with_context("failed to fetch tables to seed")?;
+    rows.into_iter()
+        .map(|row| {
+            Ok(SeedTable {
+                oid: row.try_get(0)?,
+                schema: row.try_get(1)?,
+                name: row.try_get(2)?,
+            })
+        })
+        .collect()
+}
+
+/// Sort tables so that tables referenced by foreign keys come before the tables referencing them
+fn in_dependency_order(
+    tx: &mut Transaction,
+    tables: Vec<SeedTable>,
+) -> crate::Result<Vec<SeedTable>> {
+    let sql = "SELECT conrelid, confrelid FROM pg_catalog.pg_constraint
+         WHERE contype = 'f' AND conrelid <> confrelid";
+    let mut parents: FxHashMap<Oid, FxHashSet<Oid>> = FxHashMap::default();
+    for row in tx
+        .query(sql, &[])
+        .with_context("failed to fetch foreign keys")?
+    {
+        parents
+            .entry(row.try_get(0)?)
+            .or_default()
+            .insert(row.try_get(1)?);
+    }
+    let seeded: FxHashSet<Oid> = tables.iter().map(|table| table.oid).collect();
+    let mut done = FxHashSet::default();
+    let mut remaining = tables;
+    let mut ordered = vec![];
+    while !remaining.is_empty() {
+        let (ready, waiting): (Vec<_>, Vec<_>) = remaining.into_iter().partition(|table| {
+            parents.get(&table.oid).is_none_or(|p| {
+                p.iter()
+                    .all(|parent| done.contains(parent) || !seeded.contains(parent))
+            })
+        });
+        if ready.is_empty() {
+            // Foreign keys form a cycle, seed the rest in name order
+            ordered.extend(waiting);
+            break;
+        }
+        done.extend(ready.iter().map(|table| table.oid));
+        ordered.extend(ready);
+        remaining = waiting;
+    }
+    Ok(ordered)
+}
+
+fn fetch_columns(tx: &mut Transaction, table: Oid) -> crate::Result<Vec<SeedColumn>> {
+    let sql = "SELECT
+           a.attname::text,
+           format_type(a.atttypid, a.atttypmod),
+           bt.typname::text,
+           bt.typtype::text,
+           bt.typcategory::text,
+           a.atttypmod,
+           a.atthasdef OR a.attidentity <> '' OR a.attgenerated <> '',
+           (SELECT format('(SELECT %I FROM %I.%I ORDER BY ctid LIMIT 1)', fa.attname, fn.nspname, fc.relname)
+              FROM pg_catalog.pg_constraint con
+                JOIN pg_catalog.pg_class fc ON fc.oid = con.confrelid
+                JOIN pg_catalog.pg_namespace fn ON fn.oid = fc.relnamespace
+                JOIN pg_catalog.pg_attribute fa ON fa.attrelid = con.confrelid
+                  AND fa.attnum = con.confkey[array_position(con.conkey, a.attnum)]
+              WHERE con.contype = 'f' AND con.conrelid = a.attrelid AND a.attnum = ANY(con.conkey)
+              LIMIT 1),
+           a.attnotnull,
+           EXISTS (SELECT FROM pg_catalog.pg_index i
+              WHERE i.indrelid = a.attrelid AND i.indisunique AND a.attnum = ANY(i.indkey::int2[])
+                AND NOT EXISTS (SELECT FROM unnest(i.indkey::int2[]) k
+                  WHERE NOT EXISTS (SELECT FROM pg_catalog.pg_constraint fk
+                    WHERE fk.contype = 'f' AND fk.conrelid = a.attrelid AND k = ANY(fk.conkey))))
+         FROM pg_catalog.pg_attribute a
+           JOIN pg_catalog.pg_type t ON t.oid = a.atttypid
+           JOIN pg_catalog.pg_type bt
+             ON bt.oid = CASE WHEN t.typtype = 'd' THEN t.typbasetype ELSE t.oid END
+         WHERE a.attrelid = $1 AND a.attnum > 0 AND NOT a.attisdropped
+         ORDER BY a.attnum";
+    let rows = tx
+        .query(sql, &[&table])
+        .with_context("failed to fetch columns to seed")?;
+    rows.into_iter()
+        .map(|row| {
+            Ok(SeedColumn {
+                name: row.try_get(0)?,
+                type_name: row.try_get(1)?,
+                base_type: row.try_get(2)?,
+                type_type: row.try_get(3)?,
+                category: row.try_get(4)?,
+                typmod: row.try_get(5)?,
+                skip: row.try_get(6)?,
+                fk_value: row.try_get(7)?,
+                not_null: row.try_get(8)?,
+                unique_fk: row.try_get(9)?,
+            })
+        })
+        .collect()
+}
+
+fn seed_table(tx: &mut Transaction, table: &SeedTable, rows: u64) -> crate::Result<SeededTable> {
+    let name = format!(
+        "{}.{}",
+        quote_ident(&table.schema),
+        quote_ident(&table.name)
+    );
+    let existing: i64 = tx
+        .query_one(&format!("SELECT count(*) FROM {name}"), &[])?
+        .try_get(0)?;
+    let existing = existing as u64;
+    let mut rows_inserted = rows.saturating_sub(existing);
+    let mut skipped = None;
+    if rows_inserted > 0 {
+        let columns = fetch_columns(tx, table.oid)?;
+        skipped = unseedable(&columns);
+        if skipped.is_none() {
+            let (columns, values): (Vec<_>, Vec<_>) = columns
+                .iter()
+                .filter_map(|column| Some((quote_ident(&column.name), value_expression(column)?)))
+                .unzip();
+            // Columns that are left out get their defaults, even when all of them are left out
+            let columns = if columns.is_empty() {
+                String::new()
+            } else {
+                format!("({})", columns.join(", "))
+            };
+            let sql = format!(
+                "INSERT INTO {name} {columns} SELECT {} FROM generate_series({}::int8, {}::int8) AS s(g)",
+                values.join(", "),
+                existing + 1,
+                rows
+            );
+            // Constraints that the generated values break skip the table, instead of the trace
+            let mut savepoint = tx.savepoint("eugene_seed")?;
+            match savepoint.batch_execute(&sql) {
+                Ok(()) => savepoint.commit()?,
+                Err(err) => match err.as_db_error() {
+                    Some(db_error) => {
+                        skipped = Some(db_error.message().to_string());
+                        savepoint.rollback()?;
+                    }
+                    None => return Err(err).with_context(format!("failed to seed {name}")),
+                },
+            }
+        }
+        if skipped.is_some() {
+            rows_inserted = 0;
+        } else {
+            tx.batch_execute(&format!("ANALYZE {name}"))?;
+        }
+    }
+    let bytes: i64 = tx
+        .query_one("SELECT pg_total_relation_size($1::oid)", &[&table.oid])?
+        .try_get(0)?;
+    Ok(SeededTable {
+        schema: table.schema.clone(),
+        table_name: table.name.clone(),
+        rows_inserted,
+        total_rows: existing + rows_inserted,
+        bytes: bytes as u64,
+        skipped,
+    })
+}
+
+/// Insert synthetic rows into all tables, so that rewrites, index builds and validations in the
+/// traced script take realistic time.
+///
+/// Values are generated from the column types, foreign keys reference the first row in the
+/// referenced table, and columns with defaults are left to their defaults. Partitions are skipped.
+/// Tables that rows can not be generated for, like tables with a unique foreign key or a required
+/// column of an unknown type, are reported as skipped, with the reason.
+pub fn seed_tables(tx: &mut Transaction, seed: &SeedConfig) -> crate::Result<Vec<SeededTable>> {
+    let tables = fetch_tables(tx)?;
+    let tables = in_dependency_order(tx, tables)?;
+    let mut seeded = vec![];
+    for table in tables {
+        let rows = seed.rows_for(&table.schema, &table.name);
+        if rows > 0 {
+            seeded.push(seed_table(tx, &table, rows)?);
+        }
+    }
+    Ok(seeded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{connect_to_test_db, generate_new_test_db};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_rows_for_table() {
+        let seed: SeedConfig =
+            serde_json::from_str(r#"{"rows": 10, "tables": {"books": 5, "library.authors": 7}}"#)
+                .unwrap();
+        assert_eq!(seed.rows_for("public", "books"), 5);
+        assert_eq!(seed.rows_for("library", "books"), 10);
+        assert_eq!(seed.rows_for("library", "authors"), 7);
+    }
+
+    #[test]
+    fn test_seeding_fills_tables_with_foreign_keys_and_tops_up() {
+        let mut client = connect_to_test_db(&generate_new_test_db());
+        client
+            .batch_execute(
+                "create type mood as enum ('happy', 'sad');
+                 create table seed_authors(id int generated always as identity primary key, name varchar(8) not null unique);
+                 create table seed_posts(
+                   id bigserial primary key,
+                   author int not null references seed_authors(id),
+                   title text not null,
+                   mood mood not null,
+                   published timestamptz not null,
+                   data jsonb,
+                   public bool not null
+                 );",
+            )
+            .unwrap();
+        let seed = SeedConfig {
+            rows: 0,
+            tables: [
+                ("seed_posts".to_string(), 100),
+                ("seed_authors".to_string(), 3),
+            ]
+            .into_iter()
+            .collect(),
+        };
+        let mut tx = client.transaction().unwrap();
+        let seeded = seed_tables(&mut tx, &seed).unwrap();
+        assert_eq!(
+            seeded
+                .iter()
+                .map(|t| (t.table_name.as_str(), t.rows_inserted))
+                .collect::<Vec<_>>(),
+            vec![("seed_authors", 3), ("seed_posts", 100)]
+        );
+        assert!(seeded.iter().all(|t| t.bytes > 0));
+        let more = SeedConfig {
+            rows: 0,
+            tables: [("seed_posts".to_string(), 150)].into_iter().collect(),
+        };
+        let seeded = seed_tables(&mut tx, &more).unwrap();
+        assert_eq!(seeded[0].rows_inserted, 50);
+        assert_eq!(seeded[0].total_rows, 150);
+        let count: i64 = tx
+            .query_one("select count(*) from seed_posts", &[])
+            .unwrap()
+            .get(0);
+        assert_eq!(count, 150);
+    }
+
+    #[test]
+    fn test_tables_that_can_not_be_seeded_are_skipped() {
+        let mut client = connect_to_test_db(&generate_new_test_db());
+        client
+            .batch_execute(
+                "create table seed_authors(id int generated always as identity primary key);
+                 create table seed_profiles(author int not null unique references seed_authors(id));
+                 create table seed_places(location point not null);
+                 create table seed_codes(code text not null check (char_length(code) < 3));",
+            )
+            .unwrap();
+        let seed = SeedConfig {
+            rows: 10,
+            tables: [("books".to_string(), 0)].into_iter().collect(),
+        };
+        let mut tx = client.transaction().unwrap();
+        let seeded = seed_tables(&mut tx, &seed).unwrap();
+        let skipped: Vec<_> = seeded
+            .iter()
+            .filter(|t| t.table_name.starts_with("seed_"))
+            .map(|t| (t.table_name.as_str(), t.rows_inserted, t.skipped.is_some()))
+            .collect();
+        assert_eq!(
+            skipped,
+            vec![
+                ("seed_authors", 10, false),
+                ("seed_codes", 0, true),
+                ("seed_places", 0, true),
+                ("seed_profiles", 0, true),
+            ]
+        );
+        let count: i64 = tx
+            .query_one("select count(*) from seed_authors", &[])
+            .unwrap()
+            .get(0);
+        assert_eq!(count, 10);
+    }
+}
@@ -8,6 +8,8 @@ use nom::combinator::recognize;
 use nom::multi::{many0, many_till};
 use nom::sequence::pair;
 use nom::IResult;
+use pg_query::protobuf::TransactionStmtKind;
+use pg_query::NodeEnum;
 
 use crate::error::InnerError::UnresolvedPlaceHolder;
 use crate::error::{ContextualError, ContextualResult};
@@ -84,11 +86,168 @@ pub fn read_sql_statements(path: &str) -> crate::Result<String> {
     }
 }
 
-/// Check if a SQL statement is a CREATE INDEX CONCURRENTLY statement or similar, which
-/// must run outside of a transaction.
+/// Quote an identifier, like `quote_ident` in postgres
+pub fn quote_ident(ident: &str) -> String {
+    format!("\"{}\"", ident.replace('"', "\"\""))
+}
+
+/// A statement that starts, ends or partially rolls back a transaction
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum TransactionControl {
+    Begin,
+    Commit,
+    Rollback,
+    Savepoint(String),
+    Release(String),
+    RollbackTo(String),
+}
+
+/// Check if a SQL statement is `BEGIN`, `COMMIT`, `SAVEPOINT` or another transaction control statement
+pub fn transaction_control<S: AsRef<str>>(sql: S) -> Option<TransactionControl> {
+    let parsed = pg_query::parse(sql.as_ref()).ok()?;
+    let stmt = parsed
+        .protobuf
+        .stmts
+        .first()?
+        .stmt
+        .as_ref()?
+        .node
+        .as_ref()?;
+    let NodeEnum::TransactionStmt(stmt) = stmt else {
+        return None;
+    };
+    let name = stmt.savepoint_name.clone();
+    match TransactionStmtKind::try_from(stmt.kind).ok()? {
+        TransactionStmtKind::TransStmtBegin | TransactionStmtKind::TransStmtStart => {
+            Some(TransactionControl::Begin)
+        }
+        TransactionStmtKind::TransStmtCommit => Some(TransactionControl::Commit),
+        TransactionStmtKind::TransStmtRollback => Some(TransactionControl::Rollback),
+        TransactionStmtKind::TransStmtSavepoint => Some(TransactionControl::Savepoint(name)),
+        TransactionStmtKind::TransStmtRelease => Some(TransactionControl::Release(name)),
+        TransactionStmtKind::TransStmtRollbackTo => Some(TransactionControl::RollbackTo(name)),
+        _ => None,
+    }
+}
+
+/// Statements from a script that run in the same transaction
+#[derive(Debug, Eq, PartialEq, Clone, Default)]
+pub struct TransactionBlock<'a> {
+    pub statements: Vec<(usize, &'a str)>,
+    /// True if the script ends the transaction with `ROLLBACK`
+    pub rollback: bool,
+    /// True if the statements are `CONCURRENTLY` statements that must run outside of a transaction
+    pub concurrent: bool,
+    /// True if the script starts the transaction with `BEGIN`
+    pub explicit: bool,
+}
+
+/// Split statements into transactions at `BEGIN`, `COMMIT` and `ROLLBACK`, leaving those statements out
+///
+/// Statements outside of explicit transactions are grouped with their neighbours, so a script
+/// without transaction control statements is a single transaction. `CONCURRENTLY` statements
+/// outside of explicit transactions are grouped separately, since they must run outside of a
+/// transaction.
+pub fn split_transactions<'a>(statements: &[(usize, &'a str)]) -> Vec<TransactionBlock<'a>> {
+    let mut blocks = vec![];
+    let mut current = TransactionBlock::default();
+    let mut explicit = false;
+    let mut finish = |current: &mut TransactionBlock<'a>, rollback: bool| {
+        if !current.statements.is_empty() {
+            current.rollback = rollback;
+            blocks.push(std::mem::take(current));
+        }
+    };
+    for &(line, sql) in statements {
+        match transaction_control(sql) {
+            Some(TransactionControl::Begin) => {
+                finish(&mut current, false);
+                explicit = true;
+            }
+            Some(TransactionControl::Commit) => {
+                finish(&mut current, false);
+                explicit = false;
+            }
+            Some(TransactionControl::Rollback) => {
+                finish(&mut current, true);
+                explicit = false;
+            }
+            _ => {
+                let concurrent = !explicit && is_concurrently(sql);
+                if concurrent != current.concurrent {
+                    finish(&mut current, false);
+                }
+                current.concurrent = concurrent;
+                current.explicit = explicit;
+                current.statements.push((line, sql));
+            }
+        }
+    }
+    finish(&mut current, false);
+    if blocks.is_empty() {
+        blocks.push(TransactionBlock::default());
+    }
+    blocks
+}
+
+/// Join statements outside of explicit transactions with the transaction after them, or the one
+/// before them at the end of the script, for tracing a script without committing anything
+///
+/// Scripts often set `lock_timeout` before `BEGIN`, and those statements belong with the transaction.
+/// The result only has more than one block if the script has more than one explicit transaction.
+pub fn join_implicit_blocks(blocks: Vec<TransactionBlock>) -> Vec<TransactionBlock> {
+    let mut joined: Vec<TransactionBlock> = vec![];
+    let mut pending = vec![];
+    for block in blocks {
+        if block.explicit {
+            pending.extend(block.statements);
+            joined.push(TransactionBlock {
+                statements: std::mem::take(&mut pending),
+                ..block
+            });
+        } else {
+            pending.extend(block.statements);
+        }
+    }
+    match joined.last_mut() {
+        Some(last) => last.statements.extend(pending),
+        None => joined.push(TransactionBlock {
+            statements: pending,
+            ..TransactionBlock::default()
+        }),
+    }
+    joined
+}
+
+/// Check if a SQL statement is `CREATE INDEX CONCURRENTLY` or another statement that must run
+/// outside of a transaction, like `DROP INDEX CONCURRENTLY`, `REINDEX ... CONCURRENTLY` or
+/// `ALTER TABLE ... DETACH PARTITION ... CONCURRENTLY`.
 pub fn is_concurrently<S: AsRef<str>>(sql: S) -> bool {
-    let sql = sql.as_ref();
-    sql.to_lowercase().contains("concurrently")
+    let Ok(parsed) = pg_query::parse(sql.as_ref()) else {
+        return false;
+    };
+    parsed
+        .protobuf
+        .stmts
+        .iter()
+        .filter_map(|stmt| stmt.stmt.as_ref()?.node.as_ref())
+        .any(|node| match node {
+            NodeEnum::IndexStmt(stmt) => stmt.concurrent,
+            NodeEnum::DropStmt(stmt) => stmt.concurrent,
+            NodeEnum::ReindexStmt(stmt) => stmt.params.iter().any(|param| {
+                matches!(&param.node, Some(NodeEnum::DefElem(option)) if option.defname == "concurrently")
+            }),
+            NodeEnum::AlterTableStmt(stmt) => stmt.cmds.iter().any(|cmd| {
+                let Some(NodeEnum::AlterTableCmd(cmd)) = &cmd.node else {
+                    return false;
+                };
+                matches!(
+                    cmd.def.as_ref().and_then(|def| def.node.as_ref()),
+                    Some(NodeEnum::PartitionCmd(partition)) if partition.concurrent
+                )
+            }),
+            _ => false,
+        })
 }
 
 #[cfg(test)]
@@ -217,4 +376,112 @@ $body$ LANGUAGE plpgsql;"; // generated example by ai
         let result = super::parse_blanks_and_comments(s);
         assert_eq!(result.unwrap(), ("sqltext", "  /*comment\n\n*/"));
     }
+
+    #[test]
+    fn test_split_transactions_at_begin_commit_and_rollback() -> crate::Result<()> {
+        let sql = "set lock_timeout = '2s';
+BEGIN;
+alter table a add column b int;
+COMMIT;
+start transaction;
+alter table c add column d int;
+savepoint s;
+rollback;
+create table e (id int);";
+        let statements = super::sql_statements_with_line_no(sql)?;
+        let blocks = super::split_transactions(&statements);
+        let lines: Vec<Vec<_>> = blocks
+            .iter()
+            .map(|b| b.statements.iter().map(|(line, _)| *line).collect())
+            .collect();
+        assert_eq!(lines, vec![vec![1], vec![3], vec![6, 7], vec![9]]);
+        let rollback: Vec<_> = blocks.iter().map(|b| b.rollback).collect();
+        assert_eq!(rollback, vec![false, false, true, false]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_script_without_transaction_control_is_one_block() -> crate::Result<()> {
+        let statements = super::sql_statements_with_line_no("select 1; select 2")?;
+        assert_eq!(super::split_transactions(&statements).len(), 1);
+        assert_eq!(super::split_transactions(&[]).len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_concurrently_statements_run_outside_of_transactions() -> crate::Result<()> {
+        let sql = "set lock_timeout = '2s';
+create index concurrently a_idx on a(b);
+create index concurrently c_idx on c(d);
+alter table a add column e int;";
+        let statements = super::sql_statements_with_line_no(sql)?;
+        let blocks = super::split_transactions(&statements);
+        let concurrent: Vec<_> = blocks
+            .iter()
+            .map(|b| (b.statements.len(), b.concurrent))
+            .collect();
+        assert_eq!(concurrent, vec![(1, false), (2, true), (1, false)]);
+
+        let joined = super::join_implicit_blocks(blocks);
+        assert_eq!(joined.len(), 1);
+        assert_eq!(joined[0].statements, statements);
+        assert!(!joined[0].concurrent);
+        Ok(())
+    }
+
+    #[test]
+    fn test_concurrently_is_found_by_parsing_statements() {
+        for sql in [
+            "create index concurrently a_idx on a(b)",
+            "create unique index concurrently if not exists a_idx on a(b)",
+            "drop index concurrently a_idx",
+            "reindex index concurrently a_idx",
+            "alter table a detach partition a_1 concurrently",
+        ] {
+            assert!(super::is_concurrently(sql), "{sql}");
+        }
+        for sql in [
+            "create index a_idx on a(b)",
+            "alter table a add column concurrently_updated bool",
+            "comment on table a is 'updated concurrently'",
+            "alter table a detach partition a_1",
+            "reindex index a_idx",
+            "not sql at all concurrently",
+        ] {
+            assert!(!super::is_concurrently(sql), "{sql}");
+        }
+    }
+
+    #[test]
+    fn test_statements_outside_of_transactions_join_the_transaction_next_to_them(
+    ) -> crate::Result<()> {
+        let sql = "set lock_timeout = '2s';
+BEGIN;
+alter table a add column b int;
+COMMIT;
+select 1;";
+        let statements = super::sql_statements_with_line_no(sql)?;
+        let joined = super::join_implicit_blocks(super::split_transactions(&statements));
+        let lines: Vec<Vec<_>> = joined
+            .iter()
+            .map(|b| b.statements.iter().map(|(line, _)| *line).collect())
+            .collect();
+        assert_eq!(lines, vec![vec![1, 3, 5]]);
+
+        let sql = "set lock_timeout = '2s';
+BEGIN;
+alter table a add column b int;
+COMMIT;
+BEGIN;
+alter table c add column d int;
+ROLLBACK;";
+        let statements = super::sql_statements_with_line_no(sql)?;
+        let joined = super::join_implicit_blocks(super::split_transactions(&statements));
+        let blocks: Vec<_> = joined
+            .iter()
+            .map(|b| (b.statements.len(), b.rollback))
+            .collect();
+        assert_eq!(blocks, vec![(2, false), (1, true)]);
+        Ok(())
+    }
 }
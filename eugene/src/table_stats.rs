@@ -0,0 +1,323 @@
+use std::collections::HashMap;
+
+use postgres::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::hint_data::Severity;
+use crate::output::output_format::TracedLock;
+use crate::output::FullTraceData;
+
+/// Size and activity of a relation in a reference database
+#[derive(Debug, Eq, PartialEq, Clone, Serialize)]
+pub struct TableStats {
+    /// `pg_class.reltuples`, or 0 if the relation was never analyzed
+    pub estimated_rows: u64,
+    /// `pg_total_relation_size`, including indexes and toast
+    pub total_bytes: u64,
+    /// Rows inserted, updated and deleted per minute since statistics were reset
+    pub writes_per_minute: u64,
+    /// Sequential and index scans per minute since statistics were reset
+    pub scans_per_minute: u64,
+    /// Human-readable summary, eg. `48 GB, 3k writes/s`
+    pub summary: String,
+}
+
+impl TableStats {
+    fn new(
+        estimated_rows: u64,
+        total_bytes: u64,
+        writes_per_minute: u64,
+        scans_per_minute: u64,
+    ) -> Self {
+        let writes = if writes_per_minute >= 60 {
+            format!("{} writes/s", human_count(writes_per_minute / 60))
+        } else {
+            format!("{writes_per_minute} writes/min")
+        };
+        TableStats {
+            estimated_rows,
+            total_bytes,
+            writes_per_minute,
+            scans_per_minute,
+            summary: format!(
+                "{}, {} rows, {writes}",
+                human_bytes(total_bytes),
+                human_count(estimated_rows)
+            ),
+        }
+    }
+}
+
+fn human_bytes(bytes: u64) -> String {
+    let units = ["bytes", "kB", "MB", "GB", "TB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024 && unit < units.len() - 1 {
+        value /= 1024;
+        unit += 1;
+    }
+    format!("{value} {}", units[unit])
+}
+
+fn human_count(count: u64) -> String {
+    match count {
+        0..=999 => count.to_string(),
+        1_000..=999_999 => format!("{}k", count / 1_000),
+        1_000_000..=999_999_999 => format!("{}M", count / 1_000_000),
+        _ => format!("{}G", count / 1_000_000_000),
+    }
+}
+
+/// Fetch statistics for relations by `(schema, name)` from a reference database
+///
+/// Relations that do not exist in the reference database are left out.
+pub fn fetch_table_stats(
+    client: &mut Client,
+    relations: &[(String, String)],
+) -> crate::Result<HashMap<(String, String), TableStats>> {
+    let schemas: Vec<_> = relations.iter().map(|(s, _)| s.as_str()).collect();
+    let names: Vec<_> = relations.iter().map(|(_, n)| n.as_str()).collect();
+    let query = "
+    WITH since AS (
+        SELECT greatest(extract(epoch FROM now() - coalesce(
+            (SELECT stats_reset FROM pg_stat_database WHERE datname = current_database()),
+            pg_postmaster_start_time()
+        )) / 60.0, 1.0) AS minutes
+    )
+    SELECT
+        n.nspname::text,
+        c.relname::text,
+        greatest(c.reltuples, 0)::bigint,
+        pg_total_relation_size(c.oid),
+        (coalesce(s.n_tup_ins + s.n_tup_upd + s.n_tup_del, 0) / since.minutes)::bigint,
+        (coalesce(s.seq_scan + coalesce(s.idx_scan, 0), i.idx_scan, 0) / since.minutes)::bigint
+    FROM unnest($1::text[], $2::text[]) AS r(schema_name, relname)
+      JOIN pg_namespace n ON n.nspname = r.schema_name
+      JOIN pg_class c ON c.relnamespace = n.oid AND c.relname = r.relname
+      LEFT JOIN pg_stat_all_tables s ON s.relid = c.oid
+      LEFT JOIN pg_stat_all_indexes i ON i.indexrelid = c.oid
+      CROSS JOIN since
+    ";
+    let rows = client.query(query, &[&schemas, &names])?;
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let schema: String = row.get(0);
+            let name: String = row.get(1);
+            let rows: i64 = row.get(2);
+            let bytes: i64 = row.get(3);
+            let writes: i64 = row.get(4);
+            let scans: i64 = row.get(5);
+            (
+                (schema, name),
+                TableStats::new(rows as u64, bytes as u64, writes as u64, scans as u64),
+            )
+        })
+        .collect())
+}
+
+/// Relations that are locked or created in `trace`, by `(schema, name)`
+pub fn relations_in_trace(trace: &FullTraceData) -> Vec<(String, String)> {
+    let mut out: Vec<_> = all_locks(trace)
+        .filter(|lock| lock.lock_type == "relation")
+        .map(|lock| (lock.schema.clone(), lock.object_name.clone()))
+        .chain(trace.statements.iter().flat_map(|st| {
+            st.new_objects
+                .iter()
+                .map(|obj| (obj.schema.clone(), obj.object_name.clone()))
+        }))
+        .collect();
+    out.sort();
+    out.dedup();
+    out
+}
+
+fn all_locks(trace: &FullTraceData) -> impl Iterator<Item = &TracedLock> {
+    trace.all_locks_acquired.iter().chain(
+        trace
+            .statements
+            .iter()
+            .flat_map(|st| st.locks_at_start.iter().chain(st.new_locks_taken.iter())),
+    )
+}
+
+/// Thresholds for tables that are small and quiet enough that dangerous locks on them are harmless
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ImpactThresholds {
+    #[serde(default = "default_max_rows")]
+    pub max_rows: u64,
+    #[serde(default = "default_max_bytes")]
+    pub max_bytes: u64,
+    #[serde(default = "default_max_writes_per_minute")]
+    pub max_writes_per_minute: u64,
+}
+
+fn default_max_rows() -> u64 {
+    10_000
+}
+
+fn default_max_bytes() -> u64 {
+    64 * 1024 * 1024
+}
+
+fn default_max_writes_per_minute() -> u64 {
+    600
+}
+
+impl Default for ImpactThresholds {
+    fn default() -> Self {
+        ImpactThresholds {
+            max_rows: default_max_rows(),
+            max_bytes: default_max_bytes(),
+            max_writes_per_minute: default_max_writes_per_minute(),
+        }
+    }
+}
+
+impl ImpactThresholds {
+    /// True if `stats` is below all the thresholds
+    pub fn is_small(&self, stats: &TableStats) -> bool {
+        stats.estimated_rows <= self.max_rows
+            && stats.total_bytes <= self.max_bytes
+            && stats.writes_per_minute <= self.max_writes_per_minute
+    }
+}
+
+/// Attach `stats` to the locks and objects in `trace`, and scale the severity of hints
+///
+/// Hints for statements that only hold dangerous locks on small tables in the reference database
+/// are downgraded to `Severity::Warning`. Statements that hold a dangerous lock on a table that is
+/// not in the reference database, or is too large, keep their severity.
+pub fn apply_table_stats(
+    trace: &mut FullTraceData,
+    stats: &HashMap<(String, String), TableStats>,
+    thresholds: &ImpactThresholds,
+) {
+    let lookup = |schema: &str, name: &str| stats.get(&(schema.to_string(), name.to_string()));
+    let attach = |lock: &mut TracedLock| {
+        if lock.lock_type == "relation" {
+            lock.table_stats = lookup(&lock.schema, &lock.object_name).cloned();
+        }
+    };
+    trace.all_locks_acquired.iter_mut().for_each(attach);
+    for statement in trace.statements.iter_mut() {
+        statement.locks_at_start.iter_mut().for_each(attach);
+        statement.new_locks_taken.iter_mut().for_each(attach);
+        for obj in statement.new_objects.iter_mut() {
+            obj.table_stats = lookup(&obj.schema, &obj.object_name).cloned();
+        }
+        let mut dangerous = statement
+            .locks_at_start
+            .iter()
+            .chain(statement.new_locks_taken.iter())
+            .filter(|lock| lock.maybe_dangerous)
+            .peekable();
+        let only_small_tables = dangerous.peek().is_some()
+            && dangerous.all(|lock| {
+                lock.table_stats
+                    .as_ref()
+                    .is_some_and(|stats| thresholds.is_small(stats))
+            });
+        if only_small_tables {
+            for hint in statement.triggered_rules.iter_mut() {
+                hint.severity = Severity::Warning;
+            }
+        }
+    }
+    trace.update_passed_all_checks();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::{full_trace_data, Settings};
+    use crate::rule_set::RuleSet;
+    use crate::tracing::{trace_transaction, TraceOptions};
+    use crate::{generate_new_test_db, ClientSource, WithClient};
+
+    fn test_source() -> ClientSource {
+        ClientSource::new(
+            "postgres".to_string(),
+            generate_new_test_db(),
+            "localhost".to_string(),
+            5432,
+            "postgres".to_string(),
+        )
+    }
+
+    #[test]
+    fn test_summary_is_human_readable() {
+        let stats = TableStats::new(1_200_000, 48 * 1024 * 1024 * 1024, 180_000, 0);
+        assert_eq!(stats.summary, "48 GB, 1M rows, 3k writes/s");
+        let stats = TableStats::new(10, 8192, 5, 0);
+        assert_eq!(stats.summary, "8 kB, 10 rows, 5 writes/min");
+    }
+
+    #[test]
+    fn test_fetches_stats_for_existing_relations() {
+        let stats = test_source()
+            .with_client(|client| {
+                client.batch_execute(
+                    "INSERT INTO books (title) SELECT 'title' FROM generate_series(1, 100); \
+                     ANALYZE books;",
+                )?;
+                fetch_table_stats(
+                    client,
+                    &[
+                        ("public".to_string(), "books".to_string()),
+                        ("public".to_string(), "books_pkey".to_string()),
+                        ("public".to_string(), "missing".to_string()),
+                    ],
+                )
+            })
+            .unwrap();
+        assert_eq!(stats.len(), 2);
+        let books = &stats[&("public".to_string(), "books".to_string())];
+        assert_eq!(books.estimated_rows, 100);
+        assert!(books.total_bytes > 0);
+        assert!(stats.contains_key(&("public".to_string(), "books_pkey".to_string())));
+    }
+
+    #[test]
+    fn test_hints_on_small_tables_are_downgraded_to_warnings() {
+        let rules = RuleSet::default();
+        let options = TraceOptions::new(&rules).with_final(true);
+        let trace = test_source()
+            .in_transaction(false, |tx| {
+                trace_transaction(
+                    None,
+                    tx,
+                    vec![(1, "ALTER TABLE books ALTER COLUMN title SET NOT NULL")].into_iter(),
+                    &options,
+                    None,
+                )
+            })
+            .unwrap();
+        let trace = full_trace_data(std::slice::from_ref(&trace), Settings::new(true, false));
+        let books = ("public".to_string(), "books".to_string());
+        assert_eq!(relations_in_trace(&trace), vec![books.clone()]);
+        let thresholds = ImpactThresholds::default();
+
+        let mut small = trace.clone();
+        let stats = HashMap::from([(books.clone(), TableStats::new(10, 8192, 0, 0))]);
+        apply_table_stats(&mut small, &stats, &thresholds);
+        assert!(small.all_locks_acquired[0].table_stats.is_some());
+        assert!(!small.statements[0].triggered_rules.is_empty());
+        assert!(!small.has_errors());
+        // Downgraded hints are still shown, as warnings
+        let text = small.to_plain_text().unwrap();
+        assert!(text.contains(":1 W E2 "), "{text}");
+        let markdown = small.to_markdown().unwrap();
+        assert!(markdown.contains("##### ⚠️ `E2`"), "{markdown}");
+
+        let mut large = trace.clone();
+        let stats = HashMap::from([(books, TableStats::new(10_000_000, 1 << 34, 6000, 0))]);
+        apply_table_stats(&mut large, &stats, &thresholds);
+        assert!(large.has_errors());
+
+        let mut unknown = trace;
+        apply_table_stats(&mut unknown, &HashMap::new(), &thresholds);
+        assert!(unknown.has_errors());
+    }
+}
@@ -1,58 +1,157 @@
 use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
 use std::process::{Child, Command};
 use std::sync::mpsc::channel;
 use std::thread::{spawn, JoinHandle};
 
 use crate::error::InnerError::UnableToInitDb;
-use crate::error::{ContextualError, InnerError};
+use crate::error::{ContextualError, ContextualResult, InnerError};
 use crate::utils::FsyncDir;
 use crate::{ClientSource, WithClient};
 use log::{debug, error, info, warn};
 use postgres::Client;
 use tempfile::{Builder, TempDir};
 
+/// Where a [`TempServer`] keeps its data
+#[derive(Debug)]
+enum DataDir {
+    /// Deleted when the server stops
+    Temporary(TempDir),
+    /// Kept when the server stops, so the next server can start from it
+    Cached(PathBuf),
+}
+
+impl DataDir {
+    fn path(&self) -> &Path {
+        match self {
+            DataDir::Temporary(dir) => dir.path(),
+            DataDir::Cached(path) => path,
+        }
+    }
+}
+
 pub struct TempServer {
-    dbpath: Option<TempDir>,
+    dbpath: Option<DataDir>,
     child: Child,
     reader: Option<JoinHandle<()>>,
     logger: Option<JoinHandle<()>>,
     connection_settings: ClientSource,
+    pg_bin: Option<PathBuf>,
+}
+
+/// Run `name` from `pg_bin`, or from `PATH` if `pg_bin` is `None`
+fn postgres_command(pg_bin: Option<&Path>, name: &str) -> Command {
+    match pg_bin {
+        Some(dir) => Command::new(dir.join(name)),
+        None => Command::new(name),
+    }
+}
+
+fn random_password() -> String {
+    let mut superuser_password = String::new();
+    while superuser_password.len() < 20 {
+        let rand_byte: u8 = rand::random();
+        if rand_byte.is_ascii_alphanumeric() {
+            superuser_password.push(rand_byte as char);
+        }
+    }
+    superuser_password
+}
+
+fn init_db(
+    dbpath: &Path,
+    superuser_password: &str,
+    initdb_options: &[String],
+    pg_bin: Option<&Path>,
+) -> crate::Result<()> {
+    let mut pwfile = tempfile::NamedTempFile::new()?;
+    pwfile.write_all(superuser_password.as_bytes())?;
+
+    let mut initdb = postgres_command(pg_bin, "initdb");
+    initdb
+        .arg("-D")
+        .arg(dbpath)
+        .arg("--pwfile")
+        .arg(pwfile.path())
+        .arg("--username")
+        .arg("postgres");
+    for option in initdb_options {
+        initdb.arg(option);
+    }
+    let initdb = initdb.output()?;
+
+    if !initdb.status.success() {
+        return Err(UnableToInitDb.with_context(format!("initdb failed: {initdb:?}",)));
+    }
+    Ok(())
 }
 
 impl TempServer {
-    pub fn new(postgres_options: &str, initdb_options: &[String]) -> crate::Result<Self> {
-        let port = find_free_port_on_localhost()?;
-        check_required_postgres_commands()?;
+    /// Create and start a postgres server in a temporary directory
+    ///
+    /// `pg_bin` is the directory with `initdb`, `pg_ctl` and `postgres`, for example
+    /// `/usr/lib/postgresql/16/bin`. Uses the commands in `PATH` if it is `None`.
+    pub fn new(
+        postgres_options: &str,
+        initdb_options: &[String],
+        pg_bin: Option<&Path>,
+    ) -> crate::Result<Self> {
+        check_required_postgres_commands(pg_bin)?;
         let dbpath = Builder::new().prefix("eugene-temp-postgres").tempdir()?;
         dbpath.fsync()?;
-        let mut superuser_password = String::new();
-        while superuser_password.len() < 20 {
-            let rand_byte: u8 = rand::random();
-            if rand_byte.is_ascii_alphanumeric() {
-                superuser_password.push(rand_byte as char);
-            }
-        }
-        let mut pwfile = tempfile::NamedTempFile::new()?;
-        pwfile.write_all(superuser_password.as_bytes())?;
-
-        let mut initdb = Command::new("initdb");
-        initdb
-            .arg("-D")
-            .arg(dbpath.path())
-            .arg("--pwfile")
-            .arg(pwfile.path())
-            .arg("--username")
-            .arg("postgres");
-        for option in initdb_options {
-            initdb.arg(option);
-        }
-        let initdb = initdb.output()?;
+        let superuser_password = random_password();
+        init_db(dbpath.path(), &superuser_password, initdb_options, pg_bin)?;
+        Self::start(
+            DataDir::Temporary(dbpath),
+            superuser_password,
+            postgres_options,
+            pg_bin,
+        )
+    }
 
-        if !initdb.status.success() {
-            return Err(UnableToInitDb.with_context(format!("initdb failed: {initdb:?}",)));
-        }
+    /// Start a postgres server that keeps its data in `cache_dir` when it stops
+    ///
+    /// The database cluster is created with `initdb` the first time, later calls start the
+    /// existing cluster, with the databases that earlier servers left behind.
+    pub fn cached(
+        cache_dir: &Path,
+        postgres_options: &str,
+        initdb_options: &[String],
+        pg_bin: Option<&Path>,
+    ) -> crate::Result<Self> {
+        check_required_postgres_commands(pg_bin)?;
+        let dbpath = cache_dir.join("data");
+        let password_file = cache_dir.join("password");
+        let superuser_password = if dbpath.join("PG_VERSION").exists() {
+            std::fs::read_to_string(&password_file)
+                .with_context(format!("Unable to read {password_file:?}"))?
+        } else {
+            // Remove anything left behind by an initdb that failed
+            if dbpath.exists() {
+                std::fs::remove_dir_all(&dbpath)?;
+            }
+            std::fs::create_dir_all(cache_dir)?;
+            let superuser_password = random_password();
+            std::fs::write(&password_file, &superuser_password)?;
+            init_db(&dbpath, &superuser_password, initdb_options, pg_bin)?;
+            superuser_password
+        };
+        Self::start(
+            DataDir::Cached(dbpath),
+            superuser_password,
+            postgres_options,
+            pg_bin,
+        )
+    }
 
-        let mut pg = Command::new("pg_ctl");
+    fn start(
+        dbpath: DataDir,
+        superuser_password: String,
+        postgres_options: &str,
+        pg_bin: Option<&Path>,
+    ) -> crate::Result<Self> {
+        let port = find_free_port_on_localhost()?;
+        let mut pg = postgres_command(pg_bin, "pg_ctl");
         pg.arg("start")
             .arg("-D")
             .arg(dbpath.path())
@@ -116,16 +215,32 @@ impl TempServer {
                 "postgres".to_string(),
                 "localhost".to_string(),
                 port,
-                superuser_password.clone(),
+                superuser_password,
             ),
+            pg_bin: pg_bin.map(Path::to_path_buf),
+        })
+    }
+
+    /// Settings for connecting to `database` on this server
+    pub fn connection_to(&self, database: &str) -> ClientSource {
+        self.connection_settings.for_database(database)
+    }
+
+    /// The `server_version` setting of the running server, eg. `16.4`
+    pub fn server_version(&mut self) -> crate::Result<String> {
+        self.with_client(|client| {
+            let row = client
+                .query_one("SHOW server_version", &[])
+                .with_context("Unable to read server_version")?;
+            Ok(row.try_get(0)?)
         })
     }
 }
 
-fn check_required_postgres_commands() -> crate::Result<()> {
+fn check_required_postgres_commands(pg_bin: Option<&Path>) -> crate::Result<()> {
     let required = ["initdb", "postgres"];
     for command in required.iter() {
-        Command::new(command)
+        postgres_command(pg_bin, command)
             .arg("--help")
             .output()
             .map_err(|err| {
@@ -149,21 +264,28 @@ impl WithClient for TempServer {
     ) -> crate::Result<T> {
         self.connection_settings.with_client(f)
     }
+
+    fn new_client(&self) -> crate::Result<Client> {
+        self.connection_settings.new_client()
+    }
 }
 
 impl Drop for TempServer {
     fn drop(&mut self) {
         debug!("Dropping TempServer at {:?}", &self.dbpath);
-        let path_name = self.dbpath.as_ref().map(|d| d.path());
-
         // This matches unless drop has already run
-        if let Some(path_name) = path_name {
-            let r = Command::new("pg_ctl")
+        if let Some(dbpath) = self.dbpath.as_ref() {
+            // A cached cluster is started again later, so it should shut down cleanly
+            let mode = match dbpath {
+                DataDir::Temporary(_) => "immediate",
+                DataDir::Cached(_) => "fast",
+            };
+            let r = postgres_command(self.pg_bin.as_deref(), "pg_ctl")
                 .arg("stop")
                 .arg("-D")
-                .arg(path_name)
+                .arg(dbpath.path())
                 .arg("-m")
-                .arg("immediate")
+                .arg(mode)
                 .output();
 
             if let Err(problem) = r {
@@ -176,7 +298,7 @@ impl Drop for TempServer {
             Err(e) => info!("Failed to stop postgres: {:?}", e),
             Ok(()) => {
                 debug!("Stopped postgres, deleting {:?}", self.dbpath);
-                if let Some(dbpath) = self.dbpath.take() {
+                if let Some(DataDir::Temporary(dbpath)) = self.dbpath.take() {
                     if let Err(e) = dbpath.close() {
                         warn!("Failed to delete tempdir: {:?}", e);
                     }
@@ -208,7 +330,7 @@ mod tests {
     fn temp_server_cleans_up_when_leaving_scope() {
         env_logger::init();
         fn inner() -> String {
-            let mut s = TempServer::new("", &[]).unwrap();
+            let mut s = TempServer::new("", &[], None).unwrap();
             let rows: Vec<_> = s
                 .with_client(|client| Ok(client.query("SELECT 1 + 1", &[]).unwrap()))
                 .unwrap();
@@ -0,0 +1,254 @@
+use std::path::{Path, PathBuf};
+
+use crate::error::ContextualResult;
+use crate::{ClientSource, SqlScript, WithClient};
+
+/// The database that scripts are traced in on a cached server
+pub const TRACE_DATABASE: &str = "eugene_trace";
+
+/// Template databases are named with this prefix and their cache key
+const TEMPLATE_PREFIX: &str = "eugene_cache_";
+
+/// A hash of `parts`, for naming cached clusters and template databases
+///
+/// This is FNV-1a, which gives the same hash in every version of eugene, unlike the hashers
+/// in `std`. The length of each part is hashed too, so `["ab", "c"]` and `["a", "bc"]` differ.
+pub fn cache_key<S: AsRef<[u8]>>(parts: impl IntoIterator<Item = S>) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for part in parts {
+        let part = part.as_ref();
+        for &byte in (part.len() as u64).to_le_bytes().iter().chain(part) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    format!("{hash:016x}")
+}
+
+/// The directory in `cache_dir` for a cluster created by `initdb` in `pg_bin` with `initdb_options`
+pub fn cluster_dir(cache_dir: &Path, initdb_options: &[String], pg_bin: Option<&Path>) -> PathBuf {
+    let pg_bin = pg_bin
+        .map(|path| path.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let key = cache_key(
+        initdb_options
+            .iter()
+            .map(String::as_str)
+            .chain([pg_bin.as_str()]),
+    );
+    cache_dir.join(format!("cluster-{key}"))
+}
+
+fn template_name(key: &str) -> String {
+    format!("{TEMPLATE_PREFIX}{key}")
+}
+
+/// Create [`TRACE_DATABASE`] from the template for `key`, or empty if there is no such template
+///
+/// Returns `true` if the database was created from the template. A [`TRACE_DATABASE`] left
+/// behind by an earlier run is dropped first.
+pub fn prepare_database<T: WithClient>(server: &mut T, key: &str) -> crate::Result<bool> {
+    let template = template_name(key);
+    server.with_client(|client| {
+        let restore = !client
+            .query("select from pg_database where datname = $1", &[&template])
+            .with_context("Failed to look for template database")?
+            .is_empty();
+        client
+            .batch_execute(&format!(
+                "drop database if exists {TRACE_DATABASE} with (force)"
+            ))
+            .with_context(format!("Failed to drop {TRACE_DATABASE}"))?;
+        let create = if restore {
+            format!("create database {TRACE_DATABASE} template {template}")
+        } else {
+            format!("create database {TRACE_DATABASE}")
+        };
+        client
+            .batch_execute(&create)
+            .with_context(format!("Failed to create {TRACE_DATABASE}"))?;
+        Ok(restore)
+    })
+}
+
+/// Save [`TRACE_DATABASE`] as the template for `key`, and drop the templates for other keys
+///
+/// There must be no connections to [`TRACE_DATABASE`].
+pub fn save_template<T: WithClient>(server: &mut T, key: &str) -> crate::Result<()> {
+    let template = template_name(key);
+    server.with_client(|client| {
+        let databases = client
+            .query("select datname from pg_database", &[])
+            .with_context("Failed to list databases")?;
+        for row in databases {
+            let name: String = row.try_get(0)?;
+            if name.starts_with(TEMPLATE_PREFIX) {
+                client
+                    .batch_execute(&format!("drop database {name}"))
+                    .with_context(format!("Failed to drop {name}"))?;
+            }
+        }
+        client
+            .batch_execute(&format!(
+                "create database {template} template {TRACE_DATABASE}"
+            ))
+            .with_context(format!("Failed to create {template}"))?;
+        Ok(())
+    })
+}
+
+/// Trace `scripts` in [`TRACE_DATABASE`] on `server`, starting from the template for `key` if there is one
+///
+/// Without a template, the first `applied` scripts are traced on an empty database, and the template
+/// is saved after them. With a template, they are not traced again, so their traces are missing
+/// from the result. Callers must only pass scripts they do not report on as `applied`, or runs with and
+/// without the template would give different reports. `trace` receives `settings` for connecting to
+/// [`TRACE_DATABASE`], the scripts to trace, and whether those are the `applied` scripts.
+pub fn trace_from_template<S, T, E>(
+    server: &mut S,
+    settings: &ClientSource,
+    key: &str,
+    scripts: &[SqlScript],
+    applied: usize,
+    mut trace: impl FnMut(ClientSource, &[SqlScript], bool) -> Result<Vec<T>, E>,
+) -> Result<Vec<T>, E>
+where
+    S: WithClient,
+    E: From<crate::error::Error>,
+{
+    let mut traces = vec![];
+    if !prepare_database(server, key)? {
+        traces = trace(
+            settings.for_database(TRACE_DATABASE),
+            &scripts[..applied],
+            true,
+        )?;
+        save_template(server, key)?;
+    }
+    traces.extend(trace(
+        settings.for_database(TRACE_DATABASE),
+        &scripts[applied..],
+        false,
+    )?);
+    Ok(traces)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::{full_trace_data, Settings};
+    use crate::perform_trace;
+    use crate::tracing::TraceOptions;
+
+    fn local_server() -> ClientSource {
+        ClientSource::new(
+            "postgres".to_string(),
+            "postgres".to_string(),
+            "localhost".to_string(),
+            5432,
+            "postgres".to_string(),
+        )
+    }
+
+    #[test]
+    fn test_trace_database_is_restored_from_saved_template() {
+        let mut server = local_server();
+        let key = cache_key([uuid::Uuid::new_v4().to_string()]);
+        assert!(!prepare_database(&mut server, &key).unwrap());
+        server
+            .for_database(TRACE_DATABASE)
+            .with_client(|client| {
+                Ok(client.batch_execute("create table authors (id int primary key)")?)
+            })
+            .unwrap();
+        save_template(&mut server, &key).unwrap();
+
+        assert!(prepare_database(&mut server, &key).unwrap());
+        let tables = server
+            .for_database(TRACE_DATABASE)
+            .with_client(|client| {
+                Ok(client.query("select from pg_class where relname = 'authors'", &[])?)
+            })
+            .unwrap();
+        assert_eq!(tables.len(), 1);
+
+        // A cold run saves the template, and a warm run starts from it, with the same findings
+        let scripts = vec![
+            SqlScript {
+                name: "1.sql".to_string(),
+                sql: "create table publishers (id int primary key, name text);".to_string(),
+            },
+            SqlScript {
+                name: "2.sql".to_string(),
+                sql: "alter table publishers alter column name set not null;\n\
+                      alter table publishers add column country text not null;"
+                    .to_string(),
+            },
+        ];
+        let key = cache_key([uuid::Uuid::new_v4().to_string()]);
+        let mut run = || {
+            let mut traced = vec![];
+            let findings = trace_from_template(
+                &mut server,
+                &local_server(),
+                &key,
+                &scripts,
+                1,
+                |mut settings, scripts, applied| {
+                    traced.extend(scripts.iter().map(|script| script.name.clone()));
+                    let mut findings = vec![];
+                    for script in scripts {
+                        let options = TraceOptions::default()
+                            .with_commit(true)
+                            .with_final(!applied);
+                        let traces = perform_trace(script, &mut settings, &options)?;
+                        if !applied {
+                            let trace = full_trace_data(&traces, Settings::new(true, false));
+                            findings.push((
+                                script.name.clone(),
+                                trace.passed_all_checks,
+                                trace
+                                    .statements
+                                    .iter()
+                                    .map(|statement| {
+                                        let ids = statement
+                                            .triggered_rules
+                                            .iter()
+                                            .map(|hint| hint.id.clone())
+                                            .collect::<Vec<_>>();
+                                        (statement.sql.clone(), ids)
+                                    })
+                                    .collect::<Vec<_>>(),
+                            ));
+                        }
+                    }
+                    crate::Result::Ok(findings)
+                },
+            )
+            .unwrap();
+            (traced, findings)
+        };
+        let (cold_traced, cold) = run();
+        let (warm_traced, warm) = run();
+        assert_eq!(cold_traced, vec!["1.sql", "2.sql"]);
+        assert_eq!(warm_traced, vec!["2.sql"]);
+        assert_eq!(cold, warm);
+        assert_eq!(cold.len(), 1);
+        assert!(cold[0].2.iter().all(|(_, ids)| !ids.is_empty()));
+
+        server
+            .with_client(|client| {
+                client.batch_execute(&format!("drop database {}", template_name(&key)))?;
+                client.batch_execute(&format!("drop database {TRACE_DATABASE} with (force)"))?;
+                Ok(())
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn test_cache_key_depends_on_order_and_boundaries_of_parts() {
+        assert_eq!(cache_key(["a", "b"]), cache_key(["a", "b"]));
+        assert_ne!(cache_key(["a", "b"]), cache_key(["b", "a"]));
+        assert_ne!(cache_key(["ab", "c"]), cache_key(["a", "bc"]));
+    }
+}
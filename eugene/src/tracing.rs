@@ -1,19 +1,213 @@
+use crate::rule_set::{builtin_rules, RuleSet};
+use crate::sqltext::is_concurrently;
+use crate::tracing::monitor::LockMonitor;
+use crate::tracing::probes::Prober;
 use fxhash::FxHashSet as HashSet;
-use postgres::Transaction;
+use postgres::{Client, Transaction};
 use regex::Regex;
+use std::cell::RefCell;
 pub use tracer::{SqlStatementTrace, TxLockTracer};
+/// Sampling the locks of a statement from another connection while it runs.
+pub mod monitor;
+/// Probe queries that check which application queries a traced statement blocks.
+pub mod probes;
 pub mod queries;
 /// Implementation details of the lock tracer.
 pub mod tracer;
 
+/// Settings for tracing SQL statements with `trace_transaction`, `trace_concurrently` or `perform_trace`
+///
+/// `TraceOptions::default()` checks the built-in trace hints and does not commit, probe, monitor
+/// lock waits or track writes. Use the `with_` methods to change that.
+pub struct TraceOptions<'a> {
+    rules: &'a RuleSet,
+    ignored_hints: Vec<&'a str>,
+    skip: Vec<Regex>,
+    commit: bool,
+    is_final: bool,
+    prober: Option<RefCell<Prober>>,
+    monitor_waits: bool,
+    track_writes: bool,
+}
+
+impl Default for TraceOptions<'_> {
+    fn default() -> Self {
+        Self::new(builtin_rules())
+    }
+}
+
+impl<'a> TraceOptions<'a> {
+    /// Check statements against the trace hints in `rules`
+    pub fn new(rules: &'a RuleSet) -> Self {
+        TraceOptions {
+            rules,
+            ignored_hints: vec![],
+            skip: vec![],
+            commit: false,
+            is_final: false,
+            prober: None,
+            monitor_waits: false,
+            track_writes: false,
+        }
+    }
+
+    /// Hints that are not checked for any statement
+    pub fn with_ignored_hints(mut self, ignored_hints: &[&'a str]) -> Self {
+        self.ignored_hints = ignored_hints.to_vec();
+        self
+    }
+
+    /// Statements matching any of these patterns are not executed
+    pub fn with_skip(mut self, skip: Vec<Regex>) -> Self {
+        self.skip = skip;
+        self
+    }
+
+    /// Commit the traced transactions, instead of rolling them back
+    ///
+    /// Without commit, `CONCURRENTLY` statements are not executed, since they can not run in a transaction.
+    pub fn with_commit(mut self, commit: bool) -> Self {
+        self.commit = commit;
+        self
+    }
+
+    /// The last statement traced ends the migration, so hints about its end state are checked
+    pub fn with_final(mut self, is_final: bool) -> Self {
+        self.is_final = is_final;
+        self
+    }
+
+    /// Run probe queries against the locked tables after each statement, from another connection
+    pub fn with_prober(mut self, prober: Prober) -> Self {
+        self.prober = Some(RefCell::new(prober));
+        self
+    }
+
+    /// Record the locks each statement waited for, and which backends blocked it
+    pub fn with_monitor_waits(mut self, monitor_waits: bool) -> Self {
+        self.monitor_waits = monitor_waits;
+        self
+    }
+
+    /// Record the rows and bytes of write-ahead log each statement wrote
+    pub fn with_track_writes(mut self, track_writes: bool) -> Self {
+        self.track_writes = track_writes;
+        self
+    }
+
+    pub fn rules(&self) -> &'a RuleSet {
+        self.rules
+    }
+
+    pub fn commit(&self) -> bool {
+        self.commit
+    }
+
+    pub fn is_final(&self) -> bool {
+        self.is_final
+    }
+
+    pub fn monitor_waits(&self) -> bool {
+        self.monitor_waits
+    }
+
+    /// True if the statement should not be executed
+    fn skips(&self, sql: &str) -> bool {
+        self.skip.iter().any(|r| r.is_match(sql)) || (!self.commit && is_concurrently(sql))
+    }
+}
+
 /// Trace a transaction, executing a series of SQL statements and recording the locks taken.
+///
+/// Each statement is checked against the trace hints in `options`. If `monitor` is provided,
+/// it records the locks each statement waited for, and which backends blocked it.
 pub fn trace_transaction<'a, S: AsRef<str>>(
     name: Option<String>,
     tx: &mut Transaction,
     sql_statements: impl Iterator<Item = (usize, S)>,
-    ignored_hints: &'a [&'a str],
-    skip: &[Regex],
+    options: &'a TraceOptions<'a>,
+    monitor: Option<&mut LockMonitor>,
+) -> crate::Result<TxLockTracer<'a>> {
+    trace_transaction_block(name, tx, sql_statements, options, monitor, options.is_final)
+}
+
+/// Like `trace_transaction`, with `is_final` instead of the one in `options`
+pub(crate) fn trace_transaction_block<'a, S: AsRef<str>>(
+    name: Option<String>,
+    tx: &mut Transaction,
+    sql_statements: impl Iterator<Item = (usize, S)>,
+    options: &'a TraceOptions<'a>,
+    mut monitor: Option<&mut LockMonitor>,
     is_final: bool,
+) -> crate::Result<TxLockTracer<'a>> {
+    let mut trace = start_trace(name, tx, options)?;
+    let mut prober = options.prober.as_ref().map(RefCell::borrow_mut);
+    let mut peekable = sql_statements.peekable();
+    while let Some((line, sql)) = peekable.next() {
+        let is_empty = peekable.peek().is_none();
+        trace.trace_sql_statement(
+            tx,
+            (line, sql.as_ref().trim()),
+            options.skips(sql.as_ref()),
+            is_final && is_empty,
+            prober.as_deref_mut(),
+            monitor.as_deref_mut(),
+        )?;
+    }
+    Ok(trace)
+}
+
+/// Trace `CONCURRENTLY` statements that must run outside of a transaction, one at a time.
+///
+/// Each statement runs on `client`, while `monitor` samples the locks it holds from another connection.
+pub fn trace_concurrently<'a, S: AsRef<str>>(
+    name: Option<String>,
+    client: &mut Client,
+    monitor: &mut LockMonitor,
+    sql_statements: impl Iterator<Item = (usize, S)>,
+    options: &'a TraceOptions<'a>,
+) -> crate::Result<TxLockTracer<'a>> {
+    trace_concurrently_block(
+        name,
+        client,
+        monitor,
+        sql_statements,
+        options,
+        options.is_final,
+    )
+}
+
+/// Like `trace_concurrently`, with `is_final` instead of the one in `options`
+pub(crate) fn trace_concurrently_block<'a, S: AsRef<str>>(
+    name: Option<String>,
+    client: &mut Client,
+    monitor: &mut LockMonitor,
+    sql_statements: impl Iterator<Item = (usize, S)>,
+    options: &'a TraceOptions<'a>,
+    is_final: bool,
+) -> crate::Result<TxLockTracer<'a>> {
+    let mut trace = start_trace(name, &mut client.transaction()?, options)?;
+    trace.concurrent = true;
+    let mut peekable = sql_statements.peekable();
+    while let Some((line, sql)) = peekable.next() {
+        let is_empty = peekable.peek().is_none();
+        let skip_this = options.skip.iter().any(|r| r.is_match(sql.as_ref()));
+        trace.trace_concurrent_statement(
+            client,
+            monitor,
+            (line, sql.as_ref().trim()),
+            skip_this,
+            is_final && is_empty,
+        )?;
+    }
+    Ok(trace)
+}
+
+/// Start a trace from the objects, columns and constraints that exist in the database
+fn start_trace<'a>(
+    name: Option<String>,
+    tx: &mut Transaction,
+    options: &'a TraceOptions<'a>,
 ) -> crate::Result<TxLockTracer<'a>> {
     let initial_objects: HashSet<_> = queries::fetch_lockable_objects(tx, &[])?
         .into_iter()
@@ -22,6 +216,7 @@ pub fn trace_transaction<'a, S: AsRef<str>>(
     let oid_vec: Vec<_> = initial_objects.iter().copied().collect();
     let columns = queries::fetch_all_columns(tx, &oid_vec)?;
     let constraints = queries::fetch_constraints(tx, &oid_vec)?;
+    let schema_objects = queries::fetch_schema_objects(tx, &Default::default(), None)?;
     let relfile_ids = queries::fetch_all_rel_file_ids(tx, &oid_vec)?
         .into_iter()
         .map(|(oid, relfile_id)| (oid, relfile_id.relfilenode))
@@ -31,56 +226,46 @@ pub fn trace_transaction<'a, S: AsRef<str>>(
         initial_objects,
         columns,
         constraints,
+        schema_objects,
         relfile_ids,
-        ignored_hints,
+        &options.ignored_hints,
+        options.rules,
     );
-    let mut peekable = sql_statements.peekable();
-    while let Some((line, sql)) = peekable.next() {
-        let is_empty = peekable.peek().is_none();
-        let skip_this = skip.iter().any(|r| r.is_match(sql.as_ref()));
-        trace.trace_sql_statement(
-            tx,
-            (line, sql.as_ref().trim()),
-            skip_this,
-            is_final && is_empty,
-        )?;
-    }
+    trace.track_writes = options.track_writes;
     Ok(trace)
 }
 
 #[cfg(test)]
 mod tests {
-    use postgres::{Client, NoTls};
+    use postgres::Client;
 
-    use crate::generate_new_test_db;
     use crate::hint_data;
     use crate::pg_types::contype::Contype;
     use crate::pg_types::lock_modes::LockMode;
+    use crate::tracing::queries::SchemaObjectKind;
+    use crate::tracing::TraceOptions;
+    use crate::{connect_to_test_db, generate_new_test_db};
+    use itertools::Itertools;
     use pretty_assertions::assert_eq;
     use regex::Regex;
 
     fn get_client() -> Client {
-        let test_db = generate_new_test_db();
-        Client::connect(
-            format!("host=localhost dbname={test_db} password=postgres user=postgres").as_str(),
-            NoTls,
-        )
-        .unwrap()
+        connect_to_test_db(&generate_new_test_db())
     }
 
     #[test]
     fn test_that_we_discover_modified_nullability() {
         let mut client = get_client();
         let mut tx = client.transaction().unwrap();
+        let options = TraceOptions::default();
         let trace = super::trace_transaction(
             None,
             &mut tx,
             vec!["alter table books alter column title set not null"]
                 .into_iter()
                 .enumerate(),
-            &[],
-            &[],
-            false,
+            &options,
+            None,
         )
         .unwrap();
         let modification = &trace.statements[0].modified_columns[0].1;
@@ -91,19 +276,109 @@ mod tests {
             .any(|hint| hint.id == hint_data::MAKE_COLUMN_NOT_NULLABLE_WITH_LOCK.id));
     }
 
+    #[test]
+    fn test_that_we_discover_lock_upgrades() {
+        let mut client = get_client();
+        let upgrades = |client: &mut Client, statements: Vec<&str>| {
+            let mut tx = client.transaction().unwrap();
+            let options = TraceOptions::default();
+            let trace = super::trace_transaction(
+                None,
+                &mut tx,
+                statements.into_iter().enumerate(),
+                &options,
+                None,
+            )
+            .unwrap();
+            trace
+                .triggered_hints
+                .iter()
+                .map(|hints| {
+                    hints
+                        .iter()
+                        .find(|hint| hint.id == hint_data::LOCK_UPGRADE_HAZARD.id)
+                        .map(|hint| hint.help.clone())
+                })
+                .collect::<Vec<_>>()
+        };
+        let found = upgrades(
+            &mut client,
+            vec![
+                "update books set title = title",
+                "lock table books in share update exclusive mode",
+                "lock table books in share row exclusive mode",
+                "alter table books add column meta jsonb",
+            ],
+        );
+        assert_eq!(found[0], None);
+        // Upgrading from a lock that application traffic also takes is not a hazard
+        assert_eq!(found[1], None);
+        assert!(found[2]
+            .as_ref()
+            .unwrap()
+            .contains("took `ShareRowExclusiveLock` on the table `public.books`, which the transaction already held in `ShareUpdateExclusiveLock`"));
+        assert!(found[3]
+            .as_ref()
+            .unwrap()
+            .contains("took `AccessExclusiveLock` on the table `public.books`, which the transaction already held in `ShareRowExclusiveLock`"));
+
+        let found = upgrades(
+            &mut client,
+            vec![
+                "select count(*) from books",
+                "alter table books add column meta jsonb",
+            ],
+        );
+        assert_eq!(found, vec![None, None]);
+    }
+
+    #[test]
+    fn test_that_we_discover_changes_to_tables_locked_by_earlier_statements() {
+        let mut client = get_client();
+        let mut tx = client.transaction().unwrap();
+        let options = TraceOptions::default();
+        let trace = super::trace_transaction(
+            None,
+            &mut tx,
+            vec![
+                "lock table books in access exclusive mode",
+                "create table authors (id int primary key)",
+                "alter table books alter column title set not null",
+                "alter table books add column author_id int references authors (id)",
+            ]
+            .into_iter()
+            .enumerate(),
+            &options,
+            None,
+        )
+        .unwrap();
+        let created: Vec<_> = trace.statements[1]
+            .created_objects
+            .iter()
+            .map(|obj| obj.object_name.as_str())
+            .sorted()
+            .collect();
+        assert_eq!(created, vec!["authors", "authors_pkey"]);
+        assert!(trace.statements[2].locks_taken.is_empty());
+        assert_eq!(trace.statements[2].modified_columns.len(), 1);
+        assert!(trace.statements[3].created_objects.is_empty());
+        assert_eq!(trace.statements[3].added_columns.len(), 1);
+        assert_eq!(trace.statements[3].added_constraints.len(), 1);
+    }
+
     #[test]
     fn test_that_we_discover_new_valid_check_constraint() {
         let mut client = get_client();
         let mut tx = client.transaction().unwrap();
+        let options = TraceOptions::default();
         let trace = super::trace_transaction(
             None,
             &mut tx,
             vec!["alter table books add constraint check_title check (title <> '')"]
                 .into_iter()
                 .enumerate(),
-            &[],
-            &[],
-            false,
+            &options,
+            None,
         )
         .unwrap();
         let constraint = &trace.statements[0].added_constraints[0];
@@ -119,16 +394,12 @@ mod tests {
     fn test_that_we_discover_new_foreign_key_constraint() {
         let mut client = get_client();
         let mut tx = client.transaction().unwrap();
-        let trace = super::trace_transaction(
-            None, &mut tx, vec![
+        let options = TraceOptions::default();
+        let trace = super::trace_transaction(None, &mut tx, vec![
                 "create table authors (id serial primary key);",
                 "alter table books add column author_id integer;",
                 "alter table books add constraint fk_author foreign key (author_id) references authors(id)",
-            ].into_iter().enumerate(),
-            &[],
-            &[],
-            false
-        ).unwrap();
+            ].into_iter().enumerate(), &options, None).unwrap();
         let constraint = &trace.statements[2].added_constraints[0];
         assert_eq!(constraint.constraint_type, Contype::ForeignKey);
         assert!(constraint.valid);
@@ -148,15 +419,15 @@ mod tests {
     fn test_that_we_discover_new_not_valid_check_constraint() {
         let mut client = get_client();
         let mut tx = client.transaction().unwrap();
+        let options = TraceOptions::default();
         let trace = super::trace_transaction(
             None,
             &mut tx,
             vec!["alter table books add constraint check_title check (title <> '') not valid"]
                 .into_iter()
                 .enumerate(),
-            &[],
-            &[],
-            false,
+            &options,
+            None,
         )
         .unwrap();
         let constraint = &trace.statements[0].added_constraints[0];
@@ -171,35 +442,96 @@ mod tests {
     fn test_that_we_discover_column_renames() {
         let mut client = get_client();
         let mut tx = client.transaction().unwrap();
+        let options = TraceOptions::default();
         let trace = super::trace_transaction(
             None,
             &mut tx,
-            vec!["alter table books rename column title to book_title"]
-                .into_iter()
-                .enumerate(),
-            &[],
-            &[],
-            false,
+            vec![
+                "alter table books rename column title to book_title",
+                "alter table books drop column price",
+                "alter table books rename column book_title to price",
+            ]
+            .into_iter()
+            .enumerate(),
+            &options,
+            None,
         )
         .unwrap();
         let modification = &trace.statements[0].modified_columns[0].1;
         assert_eq!(modification.old.column_name, "title");
         assert_eq!(modification.new.column_name, "book_title");
+        assert!(trace.triggered_hints[0]
+            .iter()
+            .any(|hint| hint.id == hint_data::COLUMN_RENAMED.id));
+        // The new name belonged to a column that was dropped, so queries using it still work
+        assert!(!trace.triggered_hints[2]
+            .iter()
+            .any(|hint| hint.id == hint_data::COLUMN_RENAMED.id));
+    }
+
+    #[test]
+    fn test_that_we_discover_required_columns_without_defaults() {
+        let mut client = get_client();
+        let mut tx = client.transaction().unwrap();
+        let options = TraceOptions::default();
+        let trace = super::trace_transaction(
+            None,
+            &mut tx,
+            vec![
+                "alter table books add column isbn text not null",
+                "alter table books add column stock int not null default 0",
+                "alter table books alter column stock drop default",
+                "alter table books alter column title set not null",
+                "alter table books add constraint price_not_null check (price is not null)",
+                "alter table books alter column price set not null",
+                "create table reviews (id int not null, body text not null)",
+            ]
+            .into_iter()
+            .enumerate(),
+            &options,
+            None,
+        )
+        .unwrap();
+        let required: Vec<_> = trace
+            .triggered_hints
+            .iter()
+            .map(|hints| {
+                hints
+                    .iter()
+                    .find(|hint| hint.id == hint_data::COLUMN_REQUIRED_WITHOUT_DEFAULT.id)
+                    .map(|hint| hint.help.as_str())
+            })
+            .collect();
+        // Postgres only accepts this because `books` is empty
+        assert!(required[0].unwrap().starts_with(
+            "The `NOT NULL` column `isbn` was added to the table `public.books` without a default."
+        ));
+        assert_eq!(required[1], None);
+        assert!(required[2].unwrap().starts_with(
+            "The default `0` was dropped from the `NOT NULL` column `stock` in the table `public.books`."
+        ));
+        assert!(required[3].unwrap().starts_with(
+            "The column `title` in the table `public.books` was changed to `NOT NULL`, and has no default."
+        ));
+        // A valid check constraint already made inserts without `price` fail
+        assert_eq!(required[5], None);
+        // No application version inserts into a new table yet
+        assert_eq!(required[6], None);
     }
 
     #[test]
     fn test_that_we_discover_column_type_changes() {
         let mut client = get_client();
         let mut tx = client.transaction().unwrap();
+        let options = TraceOptions::default();
         let trace = super::trace_transaction(
             None,
             &mut tx,
             vec!["alter table books alter column title type varchar(255)"]
                 .into_iter()
                 .enumerate(),
-            &[],
-            &[],
-            false,
+            &options,
+            None,
         )
         .unwrap();
         let modification = &trace.statements[0].modified_columns[0].1;
@@ -214,26 +546,71 @@ mod tests {
             .any(|hint| hint.id == hint_data::TYPE_CHANGE_REQUIRES_TABLE_REWRITE.id));
     }
 
+    #[test]
+    fn test_that_we_discover_serial_to_identity_changes() {
+        let mut client = get_client();
+        let mut tx = client.transaction().unwrap();
+        let options = TraceOptions::default();
+        let trace = super::trace_transaction(
+            None,
+            &mut tx,
+            vec![
+                "alter table books alter column id drop default",
+                "alter table books alter column id add generated always as identity",
+            ]
+            .into_iter()
+            .enumerate(),
+            &options,
+            None,
+        )
+        .unwrap();
+        let modification = &trace.statements[0].modified_columns[0].1;
+        assert_eq!(
+            modification.old.default.as_deref(),
+            Some("nextval('books_id_seq'::regclass)")
+        );
+        assert_eq!(modification.new.default, None);
+        let modification = &trace.statements[1].modified_columns[0].1;
+        assert_eq!(modification.old.identity, None);
+        assert_eq!(modification.new.identity.as_deref(), Some("ALWAYS"));
+        let changes: Vec<_> = trace
+            .triggered_hints
+            .iter()
+            .map(|hints| {
+                hints
+                    .iter()
+                    .find(|hint| hint.id == hint_data::COLUMN_VALUE_GENERATION_CHANGED.id)
+                    .map(|hint| hint.help.as_str())
+            })
+            .collect();
+        assert!(changes[0].unwrap().starts_with(
+            "The default `nextval('books_id_seq'::regclass)` was removed from the column `id`"
+        ));
+        assert!(changes[1]
+            .unwrap()
+            .starts_with("The column `id` in the table `public.books` was changed to `GENERATED ALWAYS AS IDENTITY`"));
+    }
+
     #[test]
     fn test_that_we_see_new_access_share_lock() {
         let mut client = get_client();
         let mut tx = client.transaction().unwrap();
+        let options = TraceOptions::default();
         let trace = super::trace_transaction(
             None,
             &mut tx,
             vec!["select * from books"].into_iter().enumerate(),
-            &[],
-            &[],
-            false,
+            &options,
+            None,
         )
         .unwrap();
         let lock = &trace.statements[0].locks_taken[0];
         assert_eq!(lock.mode, LockMode::AccessShare);
-        let is_pkey = lock.target.rel_kind.is_index();
-        if is_pkey {
-            assert_eq!(lock.target.object_name, "books_pkey");
+        let target = lock.relation().unwrap();
+        if target.rel_kind.is_index() {
+            assert_eq!(target.object_name, "books_pkey");
         } else {
-            assert_eq!(lock.target.object_name, "books");
+            assert_eq!(target.object_name, "books");
         }
     }
 
@@ -241,15 +618,15 @@ mod tests {
     fn test_that_we_see_access_exclusive_lock_on_alter() {
         let mut client = get_client();
         let mut tx = client.transaction().unwrap();
+        let options = TraceOptions::default();
         let trace = super::trace_transaction(
             None,
             &mut tx,
             vec!["alter table books add column metadata text"]
                 .into_iter()
                 .enumerate(),
-            &[],
-            &[],
-            false,
+            &options,
+            None,
         )
         .unwrap();
         let lock = trace
@@ -258,22 +635,22 @@ mod tests {
             .find(|lock| lock.mode == LockMode::AccessExclusive)
             .unwrap();
 
-        assert_eq!(lock.target.object_name, "books");
+        assert_eq!(lock.relation().unwrap().object_name, "books");
     }
 
     #[test]
     fn test_creating_index_blocks_writes() {
         let mut client = get_client();
         let mut tx = client.transaction().unwrap();
+        let options = TraceOptions::default();
         let trace = super::trace_transaction(
             None,
             &mut tx,
             vec!["create index on books (title)"]
                 .into_iter()
                 .enumerate(),
-            &[],
-            &[],
-            false,
+            &options,
+            None,
         )
         .unwrap();
         let lock = trace
@@ -284,19 +661,336 @@ mod tests {
         assert!(lock.is_some());
     }
 
+    #[test]
+    fn test_concurrent_index_build_is_traced_from_another_connection() {
+        let test_db = generate_new_test_db();
+        let mut client = connect_to_test_db(&test_db);
+        let mut monitor = super::LockMonitor::new(connect_to_test_db(&test_db));
+        client
+            .execute(
+                "insert into books (title) select 'title ' || i from generate_series(1, 200000) i",
+                &[],
+            )
+            .unwrap();
+        let options = TraceOptions::default();
+        let trace = super::trace_concurrently(
+            None,
+            &mut client,
+            &mut monitor,
+            vec!["create index concurrently books_title_idx on books (title)"]
+                .into_iter()
+                .enumerate(),
+            &options,
+        )
+        .unwrap();
+        assert!(trace.concurrent);
+        let statement = &trace.statements[0];
+        assert!(statement.locks_taken.iter().any(|lock| lock.mode
+            == LockMode::ShareUpdateExclusive
+            && lock
+                .relation()
+                .is_some_and(|target| target.object_name == "books")));
+        assert!(!statement.phases.is_empty());
+        assert!(statement
+            .created_objects
+            .iter()
+            .any(|obj| obj.object_name == "books_title_idx"));
+    }
+
+    #[test]
+    fn test_lock_waits_are_recorded_with_the_blocking_backend() {
+        let test_db = generate_new_test_db();
+        let mut blocker = connect_to_test_db(&test_db);
+        let mut client = connect_to_test_db(&test_db);
+        let mut monitor = super::LockMonitor::new(connect_to_test_db(&test_db));
+        let blocker_pid: i32 = blocker
+            .query_one("select pg_backend_pid()", &[])
+            .unwrap()
+            .get(0);
+        let mut blocking_tx = blocker.transaction().unwrap();
+        blocking_tx.execute("select * from books", &[]).unwrap();
+        let mut observer = connect_to_test_db(&test_db);
+        let options = TraceOptions::default();
+        let trace = std::thread::scope(|scope| {
+            scope.spawn(|| {
+                // Release the lock only after the traced statement has waited for it for a while
+                while observer
+                    .query(
+                        "select pid from pg_stat_activity where wait_event_type = 'Lock' and datname = current_database()",
+                        &[],
+                    )
+                    .unwrap()
+                    .is_empty()
+                {
+                    std::thread::sleep(std::time::Duration::from_millis(5));
+                }
+                std::thread::sleep(std::time::Duration::from_millis(100));
+                blocking_tx.rollback().unwrap();
+            });
+            let mut tx = client.transaction().unwrap();
+            super::trace_transaction(
+                None,
+                &mut tx,
+                vec!["alter table books add column meta jsonb"]
+                    .into_iter()
+                    .enumerate(),
+                &options,
+                Some(&mut monitor),
+            )
+            .unwrap()
+        });
+        let wait = &trace.statements[0].lock_waits[0];
+        assert_eq!(wait.lock.mode, LockMode::AccessExclusive);
+        assert_eq!(wait.blocking_pids, vec![blocker_pid]);
+        assert!(wait.waited >= std::time::Duration::from_millis(50));
+    }
+
+    #[test]
+    fn test_dropped_tables_leave_the_columns_and_constraints() {
+        let mut client = get_client();
+        let mut tx = client.transaction().unwrap();
+        let options = TraceOptions::default();
+        let trace = super::trace_transaction(
+            None,
+            &mut tx,
+            vec![
+                "set lock_timeout = '2s'",
+                "drop table for_checking_modified_constraints",
+            ]
+            .into_iter()
+            .enumerate(),
+            &options,
+            None,
+        )
+        .unwrap();
+        let table = "for_checking_modified_constraints";
+        assert!(trace.columns.values().all(|col| col.table_name != table));
+        assert!(trace
+            .constraints
+            .values()
+            .all(|con| con.table_name != table));
+        assert!(trace.statements[1]
+            .dropped_columns
+            .iter()
+            .any(|(_, col)| col.table_name == table));
+    }
+
+    #[test]
+    fn test_that_we_discover_views_functions_triggers_and_sequences() {
+        let mut client = get_client();
+        let mut tx = client.transaction().unwrap();
+        let options = TraceOptions::default();
+        let trace = super::trace_transaction(None, &mut tx, vec![
+                "create view book_titles as select title from books",
+                "create or replace view book_titles as select title, id from books",
+                "create function touch() returns trigger language plpgsql as $$ begin return new; end $$",
+                "create trigger books_touch before update on books for each row execute function touch()",
+                "create sequence book_numbers",
+                "alter sequence book_numbers owned by books.id",
+                "drop view book_titles",
+                "create or replace function touch() returns trigger language plpgsql as $$ begin return old; end $$",
+                "drop trigger books_touch on books",
+                "drop function touch()",
+            ]
+            .into_iter()
+            .enumerate(), &options, None)
+        .unwrap();
+        let created = |ix: usize| -> Vec<_> {
+            trace.statements[ix]
+                .added_schema_objects
+                .iter()
+                .map(|obj| (obj.kind, obj.name.as_str()))
+                .collect()
+        };
+        assert_eq!(created(0), vec![(SchemaObjectKind::View, "book_titles")]);
+        assert_eq!(trace.statements[1].modified_schema_objects.len(), 1);
+        assert!(created(1).is_empty());
+        assert_eq!(created(2), vec![(SchemaObjectKind::Function, "touch()")]);
+        assert_eq!(
+            created(3),
+            vec![(SchemaObjectKind::Trigger, "books_touch on books")]
+        );
+        assert_eq!(
+            created(4),
+            vec![(SchemaObjectKind::Sequence, "book_numbers")]
+        );
+        let owned = &trace.statements[5].modified_schema_objects;
+        assert_eq!(owned.len(), 1);
+        assert!(owned[0].new.definition.ends_with("owned by books.id"));
+        assert_eq!(trace.statements[6].dropped_schema_objects.len(), 1);
+        assert_eq!(
+            trace.statements[6].dropped_schema_objects[0].kind,
+            SchemaObjectKind::View
+        );
+        // Objects that a statement does not lock or write are carried over, not dropped
+        assert!(trace.statements[..6]
+            .iter()
+            .all(|st| st.dropped_schema_objects.is_empty()));
+        let replaced = &trace.statements[7].modified_schema_objects;
+        assert_eq!(replaced.len(), 1);
+        assert!(replaced[0].new.definition.contains("return old"));
+        let dropped = |ix: usize| -> Vec<_> {
+            trace.statements[ix]
+                .dropped_schema_objects
+                .iter()
+                .map(|obj| (obj.kind, obj.name.as_str()))
+                .collect()
+        };
+        assert_eq!(
+            dropped(8),
+            vec![(SchemaObjectKind::Trigger, "books_touch on books")]
+        );
+        assert_eq!(dropped(9), vec![(SchemaObjectKind::Function, "touch()")]);
+    }
+
+    #[test]
+    fn test_that_we_see_locks_on_types_and_advisory_locks() {
+        let mut client = get_client();
+        client
+            .execute("create type mood as enum ('happy')", &[])
+            .unwrap();
+        let mut tx = client.transaction().unwrap();
+        let options = TraceOptions::default();
+        let trace = super::trace_transaction(
+            None,
+            &mut tx,
+            vec![
+                "alter type mood add value 'sad'",
+                "select pg_advisory_xact_lock(42)",
+                "select pg_advisory_xact_lock(1, -2)",
+                "insert into books (title) values ('a')",
+            ]
+            .into_iter()
+            .enumerate(),
+            &options,
+            None,
+        )
+        .unwrap();
+        let targets: Vec<_> = trace
+            .statements
+            .iter()
+            .flat_map(|st| st.locks_taken.iter())
+            .map(|lock| lock.lock_target().to_string())
+            .collect();
+        assert!(targets.contains(&"Type public.mood".to_string()));
+        assert!(targets.contains(&"advisory lock 42".to_string()));
+        assert!(targets.contains(&"advisory lock (1, -2)".to_string()));
+        // The transaction's own ID is locked as soon as it writes, which is not interesting
+        assert!(!targets.contains(&"transaction ID".to_string()));
+        assert!(targets.contains(&"Table public.books".to_string()));
+        assert!(trace
+            .all_locks
+            .iter()
+            .all(|lock| lock.relation().is_some() || !lock.maybe_dangerous()));
+    }
+
+    #[test]
+    fn test_probes_are_blocked_by_access_exclusive_lock() {
+        let test_db = generate_new_test_db();
+        let mut client = connect_to_test_db(&test_db);
+        let prober = super::Prober::new(connect_to_test_db(&test_db), 20);
+        let mut tx = client.transaction().unwrap();
+        let options = TraceOptions::default().with_prober(prober);
+        let trace = super::trace_transaction(
+            None,
+            &mut tx,
+            vec![
+                "select * from books",
+                "alter table books add column metadata text",
+            ]
+            .into_iter()
+            .enumerate(),
+            &options,
+            None,
+        )
+        .unwrap();
+        let reading = &trace.statements[0].probes;
+        assert_eq!(reading.len(), 4);
+        assert!(reading.iter().all(|probe| !probe.blocked));
+        let altering = &trace.statements[1].probes;
+        assert_eq!(altering.len(), 4);
+        assert!(altering
+            .iter()
+            .all(|probe| probe.blocked && probe.table_name == "books"));
+    }
+
+    #[test]
+    fn test_probes_are_inconclusive_on_errors_and_skip_ignored_statements() {
+        let test_db = generate_new_test_db();
+        let mut client = connect_to_test_db(&test_db);
+        client
+            .batch_execute("create table authors (id int generated always as identity, name text)")
+            .unwrap();
+        let prober = super::Prober::new(connect_to_test_db(&test_db), 20);
+        let mut tx = client.transaction().unwrap();
+        let options = TraceOptions::default()
+            .with_skip(vec![Regex::new("lock table authors").unwrap()])
+            .with_prober(prober);
+        let trace = super::trace_transaction(
+            None,
+            &mut tx,
+            vec![
+                "select * from authors",
+                "-- eugene: ignore\nlock table books in share mode",
+                "lock table authors in share mode",
+            ]
+            .into_iter()
+            .enumerate(),
+            &options,
+            None,
+        )
+        .unwrap();
+        let reading = &trace.statements[0].probes;
+        assert_eq!(reading.len(), 4);
+        let update = reading
+            .iter()
+            .find(|probe| probe.kind == super::probes::ProbeKind::Update)
+            .unwrap();
+        assert!(!update.blocked);
+        assert!(update.error.is_some());
+        assert!(reading
+            .iter()
+            .filter(|probe| probe.kind == super::probes::ProbeKind::Select)
+            .all(|probe| probe.error.is_none()));
+        assert!(trace.statements[1].probes.is_empty());
+        assert!(trace.statements[2].probes.is_empty());
+    }
+
+    #[test]
+    fn test_writes_are_only_tracked_when_asked_for() {
+        let mut client = get_client();
+        for track_writes in [false, true] {
+            let mut tx = client.transaction().unwrap();
+            let options = TraceOptions::default().with_track_writes(track_writes);
+            let trace = super::trace_transaction(
+                None,
+                &mut tx,
+                vec!["insert into books (title, price) values ('a', 1), ('b', 2)"]
+                    .into_iter()
+                    .enumerate(),
+                &options,
+                None,
+            )
+            .unwrap();
+            let statement = &trace.statements[0];
+            assert_eq!(statement.rows_written, if track_writes { 2 } else { 0 });
+            assert_eq!(statement.bytes_written > 0, track_writes);
+        }
+    }
+
     #[test]
     fn discovers_new_index() {
         let mut client = get_client();
         let mut tx = client.transaction().unwrap();
+        let options = TraceOptions::default();
         let trace = super::trace_transaction(
             None,
             &mut tx,
             vec!["create index on books (title)"]
                 .into_iter()
                 .enumerate(),
-            &[],
-            &[],
-            false,
+            &options,
+            None,
         )
         .unwrap();
 
@@ -316,6 +1010,7 @@ mod tests {
     fn ignores_new_index_on_new_table() {
         let mut client = get_client();
         let mut tx = client.transaction().unwrap();
+        let options = TraceOptions::default();
         let trace = super::trace_transaction(
             None,
             &mut tx,
@@ -325,9 +1020,8 @@ mod tests {
             ]
             .into_iter()
             .enumerate(),
-            &[],
-            &[],
-            false,
+            &options,
+            None,
         )
         .unwrap();
         assert!(trace.triggered_hints[0].is_empty());
@@ -342,15 +1036,15 @@ mod tests {
             .execute("create unique index books_title_uq on books(title);", &[])
             .unwrap();
         let mut tx = client.transaction().unwrap();
+        let options = TraceOptions::default();
         let trace = super::trace_transaction(
             None,
             &mut tx,
             vec!["alter table books add constraint unique_title unique using index books_title_uq"]
                 .into_iter()
                 .enumerate(),
-            &[],
-            &[],
-            false,
+            &options,
+            None,
         )
         .unwrap();
         assert!(trace.statements[0].created_objects.is_empty());
@@ -363,6 +1057,7 @@ mod tests {
     fn discovers_lock_timeout_from_set() {
         let mut client = get_client();
         let mut tx = client.transaction().unwrap();
+        let options = TraceOptions::default();
         let trace = super::trace_transaction(
             None,
             &mut tx,
@@ -372,9 +1067,8 @@ mod tests {
             ]
             .into_iter()
             .enumerate(),
-            &[],
-            &[],
-            false,
+            &options,
+            None,
         )
         .unwrap();
         assert_eq!(trace.statements[1].lock_timeout_millis, 1000);
@@ -386,15 +1080,15 @@ mod tests {
     fn test_that_we_stop_json() {
         let mut client = get_client();
         let mut tx = client.transaction().unwrap();
+        let options = TraceOptions::default();
         let trace = super::trace_transaction(
             None,
             &mut tx,
             vec!["alter table books add column metadata json"]
                 .into_iter()
                 .enumerate(),
-            &[],
-            &[],
-            false,
+            &options,
+            None,
         )
         .unwrap();
         let modification = &trace.statements[0].added_columns[0].1;
@@ -409,6 +1103,7 @@ mod tests {
         let mut client = get_client();
         let regex = Regex::new("not valid sql").unwrap();
         let mut tx = client.transaction().unwrap();
+        let options = TraceOptions::default().with_skip(vec![regex]);
         let trace = super::trace_transaction(
             None,
             &mut tx,
@@ -418,9 +1113,8 @@ mod tests {
             ]
             .into_iter()
             .enumerate(),
-            &[],
-            &[regex],
-            false,
+            &options,
+            None,
         )
         .unwrap();
         assert_eq!(trace.statements.len(), 2);
@@ -438,15 +1132,15 @@ mod tests {
             .unwrap();
 
         let mut tx = client.transaction().unwrap();
+        let options = TraceOptions::default();
         let trace = super::trace_transaction(
             None,
             &mut tx,
             vec!["alter table books alter column title set not null"]
                 .into_iter()
                 .enumerate(),
-            &[],
-            &[],
-            false,
+            &options,
+            None,
         )
         .unwrap();
         let modification = &trace.statements[0].modified_columns[0].1;
@@ -460,15 +1154,15 @@ mod tests {
     fn test_widening_type_causes_rewrite() {
         let mut client = get_client();
         let mut tx = client.transaction().unwrap();
+        let options = TraceOptions::default();
         let trace = super::trace_transaction(
             None,
             &mut tx,
             vec!["alter table books alter column price type bigint"]
                 .into_iter()
                 .enumerate(),
-            &[],
-            &[],
-            false,
+            &options,
+            None,
         )
         .unwrap();
         assert!(trace.statements[0]
@@ -484,15 +1178,15 @@ mod tests {
             .execute("insert into books (title) values ('hello')", &[])
             .unwrap();
         let mut tx = client.transaction().unwrap();
+        let options = TraceOptions::default();
         let trace = super::trace_transaction(
             None,
             &mut tx,
             vec!["alter table books drop column title"]
                 .into_iter()
                 .enumerate(),
-            &[],
-            &[],
-            false,
+            &options,
+            None,
         )
         .unwrap();
         assert!(trace.statements[0].rewritten_objects.is_empty());
@@ -502,15 +1196,15 @@ mod tests {
     fn test_ignore_all_triggers_no_hints() {
         let mut client = get_client();
         let mut tx = client.transaction().unwrap();
+        let options = TraceOptions::default();
         let trace = super::trace_transaction(
             None,
             &mut tx,
             vec!["-- eugene: ignore\nalter table books add column meta json;"]
                 .into_iter()
                 .enumerate(),
-            &[],
-            &[],
-            false,
+            &options,
+            None,
         )
         .unwrap();
         assert!(trace.triggered_hints[0].is_empty());
@@ -521,6 +1215,7 @@ mod tests {
         let mut client = get_client();
         let mut tx = client.transaction().unwrap();
         let json_id = hint_data::ADD_JSON_COLUMN.id;
+        let options = TraceOptions::default();
         let trace = super::trace_transaction(
             None,
             &mut tx,
@@ -529,9 +1224,8 @@ mod tests {
             )]
             .into_iter()
             .enumerate(),
-            &[],
-            &[],
-            false,
+            &options,
+            None,
         )
         .unwrap();
         assert!(!trace.triggered_hints[0]
@@ -539,4 +1233,39 @@ mod tests {
             .any(|hint| hint.id == hint_data::ADD_JSON_COLUMN.id));
         assert!(!trace.triggered_hints.is_empty())
     }
+
+    #[test]
+    fn test_rollback_to_savepoint_releases_locks_and_marks_statements() {
+        let mut client = get_client();
+        let mut tx = client.transaction().unwrap();
+        let options = TraceOptions::default();
+        let trace = super::trace_transaction(
+            None,
+            &mut tx,
+            vec![
+                "savepoint before_alter",
+                "alter table books add column meta jsonb",
+                "rollback to savepoint before_alter",
+                "select * from books",
+            ]
+            .into_iter()
+            .enumerate(),
+            &options,
+            None,
+        )
+        .unwrap();
+        let rolled_back: Vec<_> = trace.statements.iter().map(|st| st.rolled_back).collect();
+        assert_eq!(rolled_back, vec![false, true, false, false]);
+        assert!(trace.statements[2]
+            .released_locks
+            .iter()
+            .any(|lock| lock.mode == LockMode::AccessExclusive));
+        assert!(trace.triggered_hints[2].is_empty());
+        assert!(trace.statements[2].added_columns.is_empty());
+        assert!(trace.statements[2].modified_columns.is_empty());
+        assert!(!trace
+            .all_locks
+            .iter()
+            .any(|lock| lock.mode == LockMode::AccessExclusive));
+    }
 }
@@ -0,0 +1,208 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use fxhash::{FxHashMap as HashMap, FxHashSet as HashSet};
+use itertools::Itertools;
+use postgres::{Client, GenericClient};
+
+use crate::pg_types::locks::Lock;
+use crate::tracing::queries;
+
+/// What a monitored backend was seen doing while a statement ran
+#[derive(Debug, Default, Eq, PartialEq, Clone)]
+pub struct Samples {
+    /// Every lock the backend was seen holding
+    pub locks: HashSet<Lock>,
+    /// Phases of `CREATE INDEX` or `REINDEX`, in the order they were seen
+    pub phases: Vec<String>,
+    /// Locks the backend waited for, in the order the waits started
+    pub waits: Vec<LockWait>,
+}
+
+/// A lock that a backend was seen waiting for
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct LockWait {
+    pub lock: Lock,
+    /// Time from the first to the last sample that saw the backend waiting, plus one interval
+    pub waited: Duration,
+    /// Backends that held or queued for a conflicting lock while the backend waited
+    pub blocking_pids: Vec<i32>,
+}
+
+/// When a wait was first and last seen, and who blocked it
+struct WaitSightings {
+    first: Instant,
+    last: Instant,
+    blocking_pids: Vec<i32>,
+}
+
+/// Samples the locks of another backend from a separate connection, while a statement runs
+///
+/// Each sample is a single short query outside of any transaction, so the monitor does not hold a
+/// snapshot that `CREATE INDEX CONCURRENTLY` would need to wait for. Samples are taken every 10ms,
+/// so locks that are held for a shorter time may be missed.
+pub struct LockMonitor {
+    client: Client,
+    interval: Duration,
+    /// The backend pid of the traced connection, once it is known
+    traced_pid: Option<i32>,
+}
+
+impl LockMonitor {
+    pub fn new(client: Client) -> Self {
+        LockMonitor {
+            client,
+            interval: Duration::from_millis(10),
+            traced_pid: None,
+        }
+    }
+
+    /// The backend pid of `traced`, which is looked up once, since a monitor only watches one connection
+    pub fn traced_pid(&mut self, traced: &mut impl GenericClient) -> crate::Result<i32> {
+        match self.traced_pid {
+            Some(pid) => Ok(pid),
+            None => {
+                let pid: i32 = traced.query_one("SELECT pg_backend_pid()", &[])?.get(0);
+                self.traced_pid = Some(pid);
+                Ok(pid)
+            }
+        }
+    }
+
+    /// Run `f`, sampling the locks held by the backend with `pid` until it returns
+    pub fn sample_while<T>(
+        &mut self,
+        pid: i32,
+        f: impl FnOnce() -> crate::Result<T>,
+    ) -> crate::Result<(T, Samples)> {
+        let done = AtomicBool::new(false);
+        let client = &mut self.client;
+        let interval = self.interval;
+        std::thread::scope(|scope| {
+            let sampler = scope.spawn(|| -> crate::Result<Samples> {
+                let mut samples = Samples::default();
+                let mut waits: HashMap<Lock, WaitSightings> = HashMap::default();
+                loop {
+                    let finished = done.load(Ordering::Acquire);
+                    let sample = queries::sample_backend(client, pid)?;
+                    samples.locks.extend(sample.held);
+                    if let Some(phase) = sample.phase {
+                        if samples.phases.last() != Some(&phase) {
+                            samples.phases.push(phase);
+                        }
+                    }
+                    let now = Instant::now();
+                    for lock in sample.waiting_for {
+                        let seen = waits.entry(lock).or_insert_with(|| WaitSightings {
+                            first: now,
+                            last: now,
+                            blocking_pids: vec![],
+                        });
+                        seen.last = now;
+                        seen.blocking_pids
+                            .extend(sample.blocking_pids.iter().copied());
+                    }
+                    if finished {
+                        samples.waits = waits
+                            .into_iter()
+                            .sorted_by_key(|(_, seen)| seen.first)
+                            .map(|(lock, seen)| LockWait {
+                                lock,
+                                waited: seen.last - seen.first + interval,
+                                blocking_pids: seen
+                                    .blocking_pids
+                                    .into_iter()
+                                    .sorted()
+                                    .dedup()
+                                    .collect(),
+                            })
+                            .collect();
+                        return Ok(samples);
+                    }
+                    sleep(interval);
+                }
+            });
+            let result = f();
+            done.store(true, Ordering::Release);
+            let samples = sampler
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+            Ok((result?, samples?))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::pg_types::lock_modes::LockMode;
+    use crate::{connect_to_test_db, generate_new_test_db};
+
+    #[test]
+    fn test_samples_locks_held_by_another_backend() {
+        let test_db = generate_new_test_db();
+        let mut client = connect_to_test_db(&test_db);
+        let mut monitor = LockMonitor::new(connect_to_test_db(&test_db));
+        let pid: i32 = client
+            .query_one("SELECT pg_backend_pid()", &[])
+            .unwrap()
+            .get(0);
+        assert_eq!(monitor.traced_pid(&mut client).unwrap(), pid);
+        let (_, samples) = monitor
+            .sample_while(pid, || {
+                let mut tx = client.transaction()?;
+                tx.execute("LOCK TABLE books IN SHARE MODE", &[])?;
+                tx.execute("SELECT pg_sleep(0.05)", &[])?;
+                tx.commit()?;
+                Ok(())
+            })
+            .unwrap();
+        assert!(samples.locks.iter().any(|lock| lock.mode == LockMode::Share
+            && lock
+                .relation()
+                .is_some_and(|target| target.object_name == "books")));
+    }
+
+    #[test]
+    fn test_records_waits_and_blocking_pids() {
+        let test_db = generate_new_test_db();
+        let mut blocker = connect_to_test_db(&test_db);
+        let mut client = connect_to_test_db(&test_db);
+        let mut monitor = LockMonitor::new(connect_to_test_db(&test_db));
+        let blocker_pid: i32 = blocker
+            .query_one("SELECT pg_backend_pid()", &[])
+            .unwrap()
+            .get(0);
+        let pid: i32 = client
+            .query_one("SELECT pg_backend_pid()", &[])
+            .unwrap()
+            .get(0);
+        let mut blocking_tx = blocker.transaction().unwrap();
+        blocking_tx
+            .execute("LOCK TABLE books IN ACCESS SHARE MODE", &[])
+            .unwrap();
+        let (_, samples) = monitor
+            .sample_while(pid, || {
+                std::thread::scope(|scope| {
+                    scope.spawn(|| {
+                        sleep(Duration::from_millis(100));
+                        blocking_tx.rollback().unwrap();
+                    });
+                    client.batch_execute(
+                        "BEGIN; LOCK TABLE books IN ACCESS EXCLUSIVE MODE; COMMIT;",
+                    )?;
+                    Ok(())
+                })
+            })
+            .unwrap();
+        let wait = samples
+            .waits
+            .iter()
+            .find(|wait| wait.lock.mode == LockMode::AccessExclusive)
+            .unwrap();
+        assert!(wait.waited >= Duration::from_millis(50));
+        assert_eq!(wait.blocking_pids, vec![blocker_pid]);
+    }
+}
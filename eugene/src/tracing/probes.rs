@@ -0,0 +1,169 @@
+use postgres::error::SqlState;
+use postgres::Client;
+
+use crate::error::ContextualResult;
+use crate::pg_types::locks::LockableTarget;
+use crate::sqltext::quote_ident;
+
+/// A query that application code typically runs against a table, used to check if a traced
+/// statement would block it.
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash, Ord, PartialOrd)]
+pub enum ProbeKind {
+    Select,
+    Insert,
+    Update,
+    SelectForUpdate,
+}
+
+/// All the probes that run against each locked table
+pub const PROBE_KINDS: [ProbeKind; 4] = [
+    ProbeKind::Select,
+    ProbeKind::Insert,
+    ProbeKind::Update,
+    ProbeKind::SelectForUpdate,
+];
+
+impl ProbeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProbeKind::Select => "SELECT",
+            ProbeKind::Insert => "INSERT",
+            ProbeKind::Update => "UPDATE",
+            ProbeKind::SelectForUpdate => "SELECT FOR UPDATE",
+        }
+    }
+
+    /// The SQL for the probe, or `None` if it can not run against a table without columns
+    fn sql(&self, table: &str, column: Option<&str>) -> Option<String> {
+        match self {
+            ProbeKind::Select => Some(format!("SELECT * FROM {table} LIMIT 1")),
+            ProbeKind::Insert => Some(format!(
+                "INSERT INTO {table} SELECT * FROM {table} WHERE false"
+            )),
+            ProbeKind::Update => column.map(|column| {
+                format!(
+                    "UPDATE {table} SET {column} = {column} \
+                     WHERE ctid = (SELECT ctid FROM {table} LIMIT 1)"
+                )
+            }),
+            ProbeKind::SelectForUpdate => Some(format!("SELECT * FROM {table} LIMIT 1 FOR UPDATE")),
+        }
+    }
+}
+
+/// The outcome of running a probe against a table while the traced transaction held its locks.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct ProbeResult {
+    pub(crate) kind: ProbeKind,
+    pub(crate) schema: String,
+    pub(crate) table_name: String,
+    /// True if the probe failed to get its locks within the `lock_timeout`
+    pub(crate) blocked: bool,
+    /// The error the probe failed with, if it is not known whether it got its locks
+    pub(crate) error: Option<String>,
+}
+
+/// How a probe query ended
+#[derive(Debug, Eq, PartialEq, Clone)]
+enum ProbeOutcome {
+    /// The probe got its locks, even if it then failed on a constraint or bad data
+    Passed,
+    /// The probe did not get its locks within the `lock_timeout`
+    Blocked,
+    /// The probe failed for another reason, so it is not known whether it would be blocked
+    Inconclusive(String),
+}
+
+/// Runs probe queries from a second connection to the same database, to observe which queries
+/// from application code would be blocked by the locks held by a traced transaction.
+///
+/// Each probe runs in its own transaction that is rolled back, with a short `lock_timeout`.
+pub struct Prober {
+    client: Client,
+    lock_timeout_millis: u64,
+}
+
+impl Prober {
+    pub fn new(client: Client, lock_timeout_millis: u64) -> Self {
+        Prober {
+            client,
+            lock_timeout_millis,
+        }
+    }
+
+    fn first_column(&mut self, table: &LockableTarget) -> crate::Result<Option<String>> {
+        let row = self
+            .client
+            .query_opt(
+                "SELECT attname::text FROM pg_catalog.pg_attribute
+                 WHERE attrelid = $1 AND attnum > 0 AND NOT attisdropped
+                 ORDER BY attnum LIMIT 1",
+                &[&table.oid],
+            )
+            .with_context("failed to find column for probe")?;
+        Ok(row.map(|row| row.get(0)))
+    }
+
+    /// Run `sql` and check whether it had to wait longer than `lock_timeout` for a lock
+    ///
+    /// Errors from the connection are returned, errors from the database are an outcome.
+    fn run_probe(&mut self, sql: &str) -> crate::Result<ProbeOutcome> {
+        let mut tx = self.client.transaction()?;
+        tx.batch_execute(&format!(
+            "SET LOCAL lock_timeout = '{}ms'",
+            self.lock_timeout_millis
+        ))?;
+        let outcome = match tx.batch_execute(sql) {
+            Ok(()) => ProbeOutcome::Passed,
+            Err(err) if err.code() == Some(&SqlState::LOCK_NOT_AVAILABLE) => ProbeOutcome::Blocked,
+            Err(err) => match err.as_db_error() {
+                // Constraints and data are checked after the locks are taken
+                Some(db_error)
+                    if ["22", "23"]
+                        .iter()
+                        .any(|class| db_error.code().code().starts_with(class)) =>
+                {
+                    ProbeOutcome::Passed
+                }
+                Some(db_error) => ProbeOutcome::Inconclusive(db_error.message().to_string()),
+                None => return Err(err.into()),
+            },
+        };
+        tx.rollback()?;
+        Ok(outcome)
+    }
+
+    /// Run all probes against each of the tables
+    pub fn probe<'t>(
+        &mut self,
+        tables: impl IntoIterator<Item = &'t LockableTarget>,
+    ) -> crate::Result<Vec<ProbeResult>> {
+        let mut results = vec![];
+        for table in tables {
+            let name = format!(
+                "{}.{}",
+                quote_ident(&table.schema),
+                quote_ident(&table.object_name)
+            );
+            let column = self.first_column(table)?.map(|c| quote_ident(&c));
+            for kind in PROBE_KINDS {
+                if let Some(sql) = kind.sql(&name, column.as_deref()) {
+                    let outcome = self
+                        .run_probe(&sql)
+                        .with_context(format!("failed to run probe {sql}"))?;
+                    results.push(ProbeResult {
+                        kind,
+                        schema: table.schema.clone(),
+                        table_name: table.object_name.clone(),
+                        blocked: outcome == ProbeOutcome::Blocked,
+                        error: match outcome {
+                            ProbeOutcome::Inconclusive(message) => Some(message),
+                            _ => None,
+                        },
+                    });
+                }
+            }
+        }
+        Ok(results)
+    }
+}
@@ -1,10 +1,10 @@
 use crate::error::{ContextualResult, InnerError};
 use fxhash::{FxHashMap as HashMap, FxHashSet as HashSet};
 use postgres::types::Oid;
-use postgres::Transaction;
+use postgres::{GenericClient, Row, Transaction};
 
 use crate::pg_types::contype::Contype;
-use crate::pg_types::locks::{InvalidLockError, Lock, LockableTarget};
+use crate::pg_types::locks::{object_kind, InvalidLockError, Lock, LockTarget, LockableTarget};
 use crate::pg_types::relkinds::RelKind;
 
 #[derive(Eq, PartialEq, Debug, Clone, Copy, Hash)]
@@ -21,6 +21,12 @@ pub struct ColumnMetadata {
     pub(crate) nullable: bool,
     pub(crate) typename: String,
     pub(crate) max_len: Option<u32>,
+    /// `ALWAYS` or `BY DEFAULT` for identity columns
+    pub(crate) identity: Option<String>,
+    /// The expression of a generated column
+    pub(crate) generated: Option<String>,
+    /// The default expression, eg. `nextval('books_id_seq'::regclass)` for a `serial` column
+    pub(crate) default: Option<String>,
 }
 
 #[derive(Eq, PartialEq, Debug, Clone)]
@@ -44,6 +50,102 @@ pub struct RelfileId {
     pub(crate) oid: Oid,
 }
 
+impl ColumnIdentifier {
+    /// The oid of the table the column belongs to
+    pub fn oid(&self) -> Oid {
+        self.oid
+    }
+    /// The position of the column in the table, `pg_attribute.attnum`
+    pub fn attnum(&self) -> i32 {
+        self.attnum
+    }
+}
+
+impl ColumnMetadata {
+    pub fn schema_name(&self) -> &str {
+        &self.schema_name
+    }
+    pub fn table_name(&self) -> &str {
+        &self.table_name
+    }
+    pub fn column_name(&self) -> &str {
+        &self.column_name
+    }
+    pub fn nullable(&self) -> bool {
+        self.nullable
+    }
+    /// The type of the column, eg. `varchar` or `int4`
+    pub fn typename(&self) -> &str {
+        &self.typename
+    }
+    /// The maximum length of the column, for types like `varchar(255)`
+    pub fn max_len(&self) -> Option<u32> {
+        self.max_len
+    }
+    /// `ALWAYS` or `BY DEFAULT` for identity columns
+    pub fn identity(&self) -> Option<&str> {
+        self.identity.as_deref()
+    }
+    /// The expression of a generated column
+    pub fn generated(&self) -> Option<&str> {
+        self.generated.as_deref()
+    }
+    /// The default expression of the column
+    pub fn default(&self) -> Option<&str> {
+        self.default.as_deref()
+    }
+}
+
+impl Constraint {
+    pub fn schema_name(&self) -> &str {
+        &self.schema_name
+    }
+    pub fn table_name(&self) -> &str {
+        &self.table_name
+    }
+    pub fn constraint_type(&self) -> Contype {
+        self.constraint_type
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    /// The definition of the constraint, from `pg_get_constraintdef`
+    pub fn expression(&self) -> Option<&str> {
+        self.expression.as_deref()
+    }
+    /// False if the constraint was added as `NOT VALID` and has not been validated yet
+    pub fn valid(&self) -> bool {
+        self.valid
+    }
+    /// The oid of the table the constraint is on
+    pub fn target(&self) -> Oid {
+        self.target
+    }
+    /// The oid of the table a foreign key references
+    pub fn fk_target(&self) -> Option<Oid> {
+        self.fk_target
+    }
+}
+
+impl RelfileId {
+    pub fn schema_name(&self) -> &str {
+        &self.schema_name
+    }
+    pub fn object_name(&self) -> &str {
+        &self.object_name
+    }
+    /// The file node of the relation, it changes when the relation is rewritten
+    pub fn relfilenode(&self) -> u32 {
+        self.relfilenode
+    }
+    pub fn rel_kind(&self) -> RelKind {
+        self.rel_kind
+    }
+    pub fn oid(&self) -> Oid {
+        self.oid
+    }
+}
+
 #[derive(Eq, PartialEq, Debug, Clone)]
 pub struct ForeignKeyReference {
     pub(crate) constraint_name: String,
@@ -52,54 +154,299 @@ pub struct ForeignKeyReference {
     pub(crate) columns: Vec<String>,
 }
 
+/// The kind of a [`SchemaObject`]
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Hash, PartialOrd, Ord)]
+pub enum SchemaObjectKind {
+    /// A view or materialized view, from `pg_rewrite`
+    View,
+    /// A function or procedure, from `pg_proc`
+    Function,
+    /// A trigger on a table, from `pg_trigger`
+    Trigger,
+    /// A sequence, from `pg_sequence`
+    Sequence,
+}
+
+impl SchemaObjectKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SchemaObjectKind::View => "view",
+            SchemaObjectKind::Function => "function",
+            SchemaObjectKind::Trigger => "trigger",
+            SchemaObjectKind::Sequence => "sequence",
+        }
+    }
+
+    fn from_str(kind: &str) -> crate::Result<Self> {
+        match kind {
+            "view" => Ok(SchemaObjectKind::View),
+            "function" => Ok(SchemaObjectKind::Function),
+            "trigger" => Ok(SchemaObjectKind::Trigger),
+            "sequence" => Ok(SchemaObjectKind::Sequence),
+            _ => Err(InnerError::UnknownSchemaObjectKind(kind.to_string()).into()),
+        }
+    }
+}
+
+/// A schema object that other objects depend on, or that depends on tables, with its definition
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct SchemaObject {
+    pub(crate) kind: SchemaObjectKind,
+    pub(crate) oid: Oid,
+    pub(crate) schema_name: String,
+    /// The name, with argument types for functions and the table for triggers
+    pub(crate) name: String,
+    pub(crate) definition: String,
+    /// Changes when the catalog rows of the object change, so unchanged definitions are not rendered again
+    pub(crate) version: String,
+    /// The oid that locks show up on when the object changes, the table for triggers, otherwise `oid`
+    pub(crate) lock_oid: Oid,
+}
+
+/// Identifies a [`SchemaObject`] by kind, schema and name, so replacing it is a change, not a new object
+pub type SchemaObjectId = (SchemaObjectKind, String, String);
+
+/// An index with `indisvalid = false`, eg. left behind by a failed `CREATE INDEX CONCURRENTLY`
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct InvalidIndex {
+    pub(crate) schema_name: String,
+    pub(crate) index_name: String,
+    pub(crate) table_name: String,
+    /// True if this is an index on a partitioned table that is missing indexes on partitions
+    pub(crate) partitioned: bool,
+}
+
 /// Enumerate all locks owned by the current transaction.
-fn query_pg_locks_in_current_transaction(tx: &mut Transaction) -> crate::Result<HashSet<Lock>> {
-    let query = "SELECT n.nspname::text AS schema_name,
+pub fn query_pg_locks_in_current_transaction(tx: &mut Transaction) -> crate::Result<HashSet<Lock>> {
+    query_pg_locks(tx, None, true)
+}
+
+/// Columns for reading a lock from `pg_locks l`, joined with `pg_class c` and `pg_namespace n`,
+/// in the order that `lock_from_row` reads them
+const LOCK_COLUMNS: &str = "l.locktype::text AS locktype,
+                l.mode::text AS mode,
+                n.nspname::text AS schema_name,
                 c.relname::text AS object_name,
                 c.relkind AS relkind,
-                l.mode::text AS mode,
-                c.oid AS oid
-         FROM pg_locks l JOIN pg_class c ON c.oid = l.relation
-           JOIN pg_namespace n ON n.oid = c.relnamespace
-         WHERE l.locktype = 'relation' AND l.pid = pg_backend_pid();";
-    let rows = tx
-        .query(query, &[])
-        .with_context("failed to query pg_locks_in_current_transaction")?;
-    let locks = rows
-        .into_iter()
-        .map(|row| {
-            let schema: String = row.try_get(0)?;
-            let object_name: String = row.try_get(1)?;
-            let relkind: i8 = row.try_get(2)?;
-            let mode: String = row.try_get(3)?;
-            let oid: Oid = row.try_get(4)?;
-            Lock::new(schema, object_name, mode, (relkind as u8) as char, oid).map_err(|e| e.into())
-        })
-        .collect::<crate::Result<HashSet<Lock>>>()?;
+                c.oid AS oid,
+                l.page AS page,
+                l.tuple AS tuple,
+                CASE WHEN l.locktype = 'object'
+                  THEN (pg_identify_object(l.classid, l.objid, l.objsubid)).type END AS object_type,
+                CASE WHEN l.locktype = 'object'
+                  THEN (pg_identify_object(l.classid, l.objid, l.objsubid)).schema END AS object_schema,
+                CASE WHEN l.locktype = 'object'
+                  THEN coalesce(
+                    (pg_identify_object(l.classid, l.objid, l.objsubid)).name,
+                    (pg_identify_object(l.classid, l.objid, l.objsubid)).identity
+                  ) END AS object_identity,
+                l.objid AS objid,
+                CASE WHEN l.locktype = 'advisory' AND l.objsubid = 1
+                  THEN ((l.classid::int8 << 32) | l.objid::int8)::text
+                  WHEN l.locktype = 'advisory'
+                  THEN '(' || l.classid::int8::bit(32)::int4 || ', '
+                    || l.objid::int8::bit(32)::int4 || ')' END AS advisory_key";
+
+/// The lock types that `lock_from_row` reads, skipping those that only postgres itself uses
+const LOCK_TYPES: &str =
+    "l.locktype IN ('relation', 'object', 'advisory', 'transactionid', 'tuple')";
+
+/// Enumerate all locks granted to the backend with `pid`, or the current backend if `pid` is `None`.
+///
+/// This finds locks on relations, other database objects, advisory locks, rows and transaction
+/// IDs, skipping locks that only postgres itself uses, like `virtualxid`.
+pub fn query_pg_locks(
+    client: &mut impl GenericClient,
+    pid: Option<i32>,
+    granted: bool,
+) -> crate::Result<HashSet<Lock>> {
+    let query = format!(
+        "SELECT {LOCK_COLUMNS}
+         FROM pg_locks l
+           LEFT JOIN pg_class c ON c.oid = l.relation
+           LEFT JOIN pg_namespace n ON n.oid = c.relnamespace
+         WHERE {LOCK_TYPES}
+           AND l.granted = $2
+           AND l.pid = coalesce($1::int4, pg_backend_pid());"
+    );
+    let rows = client
+        .query(&query, &[&pid, &granted])
+        .with_context("failed to query pg_locks")?;
+    let mut locks = HashSet::default();
+    for row in rows {
+        if let Some(lock) = lock_from_row(&row)? {
+            locks.insert(lock);
+        }
+    }
     Ok(locks)
 }
 
+/// Read a lock from the first columns of `row`, which are [`LOCK_COLUMNS`]
+///
+/// Returns `None` for rows without a lock, or locks on targets that can not be identified.
+fn lock_from_row(row: &Row) -> crate::Result<Option<Lock>> {
+    let Some(locktype) = row.try_get::<_, Option<String>>(0)? else {
+        return Ok(None);
+    };
+    let mode: String = row.try_get(1)?;
+    let relation = || -> crate::Result<Option<LockableTarget>> {
+        // The relation is missing if it is in another database, or was dropped
+        let Some(oid) = row.try_get::<_, Option<Oid>>(5)? else {
+            return Ok(None);
+        };
+        let schema: String = row.try_get(2)?;
+        let object_name: String = row.try_get(3)?;
+        let relkind: i8 = row.try_get(4)?;
+        let rel_kind = (relkind as u8) as char;
+        LockableTarget::new(schema, object_name, rel_kind, oid)
+            .map(Some)
+            .ok_or_else(|| InvalidLockError::InvalidRelKind(rel_kind).into())
+    };
+    let target = match locktype.as_str() {
+        "relation" => relation()?.map(LockTarget::Relation),
+        "tuple" => match relation()? {
+            Some(relation) => Some(LockTarget::Tuple {
+                relation,
+                page: row.try_get(6)?,
+                tuple: row.try_get(7)?,
+            }),
+            None => None,
+        },
+        "object" => {
+            let object_type: Option<String> = row.try_get(8)?;
+            let schema: Option<String> = row.try_get(9)?;
+            let identity: Option<String> = row.try_get(10)?;
+            let oid: Oid = row.try_get(11)?;
+            match (object_type, identity) {
+                (Some(object_type), Some(object_name))
+                    if !schema
+                        .as_deref()
+                        .is_some_and(|s| ["pg_catalog", "information_schema"].contains(&s)) =>
+                {
+                    Some(LockTarget::Object {
+                        kind: object_kind(&object_type),
+                        schema: schema.unwrap_or_default(),
+                        object_name,
+                        oid,
+                    })
+                }
+                _ => None,
+            }
+        }
+        "advisory" => Some(LockTarget::Advisory {
+            key: row.try_get(12)?,
+        }),
+        "transactionid" => Some(LockTarget::TransactionId),
+        _ => None,
+    };
+    match target {
+        Some(target) => Ok(Some(Lock::on(mode, target)?)),
+        None => Ok(None),
+    }
+}
+
 /// Find all locks in the current transaction that are relevant to the given set of objects.
+///
+/// Locks on relations and rows are only relevant if the relation is in `relevant_objects`,
+/// locks on other targets are always relevant.
 pub fn find_relevant_locks_in_current_transaction(
     tx: &mut Transaction,
     relevant_objects: &HashSet<Oid>,
 ) -> crate::Result<HashSet<Lock>> {
     let current_locks = query_pg_locks_in_current_transaction(tx)?;
-    Ok(current_locks
+    Ok(relevant_locks(current_locks, relevant_objects))
+}
+
+/// The oids of all relations that the current transaction holds locks on
+///
+/// This reads `pg_locks.relation`, so it includes relations that were created or dropped in the
+/// transaction, which locks from [`query_pg_locks_in_current_transaction`] can not identify.
+pub fn locked_relations(tx: &mut Transaction) -> crate::Result<HashSet<Oid>> {
+    let sql = "select distinct relation from pg_locks
+where pid = pg_backend_pid() and relation is not null
+  and database = (select oid from pg_database where datname = current_database());";
+    let rows = tx
+        .query(sql, &[])
+        .with_context("Failed to fetch locked relations")?;
+    rows.into_iter().map(|row| Ok(row.try_get(0)?)).collect()
+}
+
+/// Keep the locks on relations and rows in `relevant_objects`, and locks on other targets
+///
+/// Every transaction that writes holds a lock on its own transaction ID, so granted locks on
+/// transaction IDs are left out.
+pub fn relevant_locks(locks: HashSet<Lock>, relevant_objects: &HashSet<Oid>) -> HashSet<Lock> {
+    locks
         .into_iter()
-        .filter(|lock| relevant_objects.contains(&lock.target_oid()))
-        .collect())
+        .filter(|lock| lock.lock_target() != &LockTarget::TransactionId)
+        .filter(|lock| {
+            lock.lock_target()
+                .relation_oid()
+                .is_none_or(|oid| relevant_objects.contains(&oid))
+        })
+        .collect()
+}
+
+/// What the backend with `pid` held, waited for and was doing at one point in time
+#[derive(Debug, Default, Eq, PartialEq, Clone)]
+pub struct BackendSample {
+    /// Locks granted to the backend
+    pub held: HashSet<Lock>,
+    /// Locks the backend is waiting for
+    pub waiting_for: HashSet<Lock>,
+    /// Backends that block the backend from getting the locks it waits for
+    pub blocking_pids: Vec<i32>,
+    /// The phase of `CREATE INDEX` or `REINDEX`, if the backend is building an index
+    pub phase: Option<String>,
+}
+
+/// Sample the locks and index build phase of the backend with `pid`, in a single query
+pub fn sample_backend(client: &mut impl GenericClient, pid: i32) -> crate::Result<BackendSample> {
+    let query = format!(
+        "SELECT {LOCK_COLUMNS},
+                l.granted AS granted,
+                CASE WHEN NOT l.granted THEN pg_blocking_pids(l.pid) END AS blocking_pids,
+                p.phase::text AS phase
+         FROM (SELECT $1::int4 AS pid) b
+           LEFT JOIN pg_stat_progress_create_index p ON p.pid = b.pid
+           LEFT JOIN pg_locks l ON l.pid = b.pid AND {LOCK_TYPES}
+           LEFT JOIN pg_class c ON c.oid = l.relation
+           LEFT JOIN pg_namespace n ON n.oid = c.relnamespace;"
+    );
+    let rows = client
+        .query(&query, &[&pid])
+        .with_context("failed to sample pg_locks")?;
+    let mut sample = BackendSample::default();
+    for row in rows {
+        sample.phase = row.try_get(15)?;
+        let blocking_pids: Option<Vec<i32>> = row.try_get(14)?;
+        sample
+            .blocking_pids
+            .extend(blocking_pids.unwrap_or_default());
+        let granted: Option<bool> = row.try_get(13)?;
+        match (lock_from_row(&row)?, granted) {
+            (Some(lock), Some(true)) => sample.held.insert(lock),
+            (Some(lock), Some(false)) => sample.waiting_for.insert(lock),
+            _ => false,
+        };
+    }
+    Ok(sample)
 }
 
 /// Return the locks that are new in the new set of locks compared to the old set.
+///
+/// Relation locks are compared by `oid`, so renaming a relation does not make its locks new.
 pub fn find_new_locks(old_locks: &HashSet<Lock>, new_locks: &HashSet<Lock>) -> HashSet<Lock> {
-    let old = old_locks
-        .iter()
-        .map(|lock| (lock.target_oid(), lock.mode))
-        .collect::<HashSet<_>>();
+    let same_lock = |old: &Lock, new: &Lock| {
+        old.mode == new.mode
+            && match (old.relation(), new.relation()) {
+                (Some(old), Some(new)) => old.oid == new.oid,
+                _ => old.target == new.target,
+            }
+    };
     new_locks
         .iter()
-        .filter(|lock| !old.contains(&(lock.target_oid(), lock.mode)))
+        .filter(|new| !old_locks.iter().any(|old| same_lock(old, new)))
         .cloned()
         .collect()
 }
@@ -117,11 +464,15 @@ pub fn fetch_all_columns(
            t.typname as type_name,
            a.atttypmod as typmod,
            n.nspname as schema_name,
-           c.relname as table_name
+           c.relname as table_name,
+           CASE a.attidentity WHEN 'a' THEN 'ALWAYS' WHEN 'd' THEN 'BY DEFAULT' END as identity,
+           a.attgenerated <> '' as generated,
+           pg_catalog.pg_get_expr(d.adbin, d.adrelid) as default_expr
          FROM pg_catalog.pg_attribute a
            JOIN pg_catalog.pg_type t ON a.atttypid = t.oid
            JOIN pg_catalog.pg_class c ON a.attrelid = c.oid
            JOIN pg_catalog.pg_namespace n ON c.relnamespace = n.oid
+           LEFT JOIN pg_catalog.pg_attrdef d ON d.adrelid = a.attrelid AND d.adnum = a.attnum
          WHERE n.nspname NOT IN ('pg_catalog', 'information_schema') AND c.oid = ANY($1)
          ";
     let rows = tx
@@ -142,6 +493,15 @@ pub fn fetch_all_columns(
             };
             let schema_name: String = row.try_get(6)?;
             let table_name: String = row.try_get(7)?;
+            let identity: Option<String> = row.try_get(8)?;
+            let generated: bool = row.try_get(9)?;
+            let expression: Option<String> = row.try_get(10)?;
+            // Generated columns keep their expression in `pg_attrdef`, like defaults
+            let (generated, default) = if generated {
+                (expression, None)
+            } else {
+                (None, expression)
+            };
             let identifier = ColumnIdentifier {
                 oid: table_oid,
                 attnum: attnum as i32,
@@ -153,6 +513,9 @@ pub fn fetch_all_columns(
                 max_len,
                 schema_name,
                 table_name,
+                identity,
+                generated,
+                default,
             };
             Ok((identifier, metadata))
         })
@@ -229,18 +592,38 @@ pub fn fetch_lockable_objects(
     let rows = tx
         .query(sql, &[&skip_list])
         .with_context("failed to fetch lockable objects")?;
+    rows.into_iter().map(lockable_object).collect()
+}
 
-    rows.into_iter()
-        .map(|row| {
-            let schema: String = row.try_get(0)?;
-            let object_name: String = row.try_get(1)?;
-            let rk_byte: i8 = row.try_get(2)?;
-            let rel_kind: char = (rk_byte as u8) as char;
-            let oid: Oid = row.try_get(3)?;
-            LockableTarget::new(schema.as_str(), object_name.as_str(), rel_kind, oid)
-                .ok_or_else(|| InvalidLockError::InvalidRelKind(rel_kind).into())
-        })
-        .collect()
+/// Fetch the lockable objects with the given `oids`, eg. relations that a statement created
+pub fn fetch_lockable_objects_by_oid(
+    tx: &mut Transaction,
+    oids: &[Oid],
+) -> crate::Result<HashSet<LockableTarget>> {
+    let sql = "SELECT
+           n.nspname as schema_name,
+           c.relname as table_name,
+           c.relkind as relkind,
+           c.oid as oid
+         FROM pg_catalog.pg_class c
+           JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+         WHERE
+           n.nspname NOT IN ('pg_catalog', 'information_schema') AND c.oid = ANY($1)
+         ";
+    let rows = tx
+        .query(sql, &[&oids])
+        .with_context("failed to fetch lockable objects by oid")?;
+    rows.into_iter().map(lockable_object).collect()
+}
+
+fn lockable_object(row: postgres::Row) -> crate::Result<LockableTarget> {
+    let schema: String = row.try_get(0)?;
+    let object_name: String = row.try_get(1)?;
+    let rk_byte: i8 = row.try_get(2)?;
+    let rel_kind: char = (rk_byte as u8) as char;
+    let oid: Oid = row.try_get(3)?;
+    LockableTarget::new(schema.as_str(), object_name.as_str(), rel_kind, oid)
+        .ok_or_else(|| InvalidLockError::InvalidRelKind(rel_kind).into())
 }
 
 /// Fetch all non-system relation file ids in the database
@@ -278,6 +661,45 @@ pub fn fetch_all_rel_file_ids(
         .collect()
 }
 
+/// Counters for what the current transaction has written so far
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct WritePosition {
+    pub(crate) rows: i64,
+    pub(crate) wal_lsn: String,
+}
+
+const ROWS_WRITTEN: &str = "SELECT coalesce(sum(n_tup_ins + n_tup_upd + n_tup_del), 0)::int8
+         FROM pg_catalog.pg_stat_xact_user_tables";
+
+/// Fetch the rows written by the current transaction and the current write-ahead log position
+pub fn fetch_write_position(tx: &mut Transaction) -> crate::Result<WritePosition> {
+    let query = format!("SELECT ({ROWS_WRITTEN}), pg_current_wal_insert_lsn()::text");
+    let row = tx
+        .query_one(&query, &[])
+        .with_context("failed to fetch write position")?;
+    Ok(WritePosition {
+        rows: row.try_get(0)?,
+        wal_lsn: row.try_get(1)?,
+    })
+}
+
+/// Rows and bytes of write-ahead log written by the current transaction since `position`
+pub fn written_since(tx: &mut Transaction, position: &WritePosition) -> crate::Result<(u64, u64)> {
+    let query = format!(
+        "SELECT ({ROWS_WRITTEN}),
+           pg_wal_lsn_diff(pg_current_wal_insert_lsn(), $1::text::pg_lsn)::int8"
+    );
+    let row = tx
+        .query_one(&query, &[&position.wal_lsn])
+        .with_context("failed to fetch rows and bytes written")?;
+    let rows: i64 = row.try_get(0)?;
+    let bytes: i64 = row.try_get(1)?;
+    Ok((
+        rows.saturating_sub(position.rows).max(0) as u64,
+        bytes.max(0) as u64,
+    ))
+}
+
 /// Retrieve the current `lock_timeout` for the active transaction
 pub fn get_lock_timeout(tx: &mut Transaction) -> crate::Result<u64> {
     let query = "select current_setting('lock_timeout')";
@@ -360,20 +782,234 @@ group by schema_name, table_name, fk;
         .collect()
 }
 
+/// The oids of relations and functions that the current transaction holds locks on
+///
+/// Unlike [`query_pg_locks_in_current_transaction`], this includes relations and functions that
+/// were dropped by the transaction, for finding the schema objects that changed.
+pub fn locked_schema_object_oids(tx: &mut Transaction) -> crate::Result<Vec<Oid>> {
+    let sql = "select relation from pg_locks
+where pid = pg_backend_pid() and locktype = 'relation'
+  and database = (select oid from pg_database where datname = current_database())
+union
+select objid from pg_locks
+where pid = pg_backend_pid() and locktype = 'object' and classid = 'pg_proc'::regclass;
+";
+    let rows = tx
+        .query(sql, &[])
+        .with_context("Failed to fetch locked relations and functions")?;
+    rows.into_iter().map(|row| Ok(row.try_get(0)?)).collect()
+}
+
+/// Fetch views, functions, triggers and sequences outside of system schemas, with their definitions
+///
+/// Objects that belong to extensions and sequences of identity columns are left out, since they
+/// change along with the extension or column. With a `scope`, only views and sequences in it,
+/// triggers on tables in it, functions in it and functions written by the current transaction
+/// are fetched, and the other objects are copied from `known`. Objects are created, changed and
+/// dropped with locks on them or their tables, except for functions that are created or replaced,
+/// which postgres does not lock. Definitions are only rendered for objects that are not in `known`
+/// with the same version.
+pub fn fetch_schema_objects(
+    tx: &mut Transaction,
+    known: &HashMap<SchemaObjectId, SchemaObject>,
+    scope: Option<&[Oid]>,
+) -> crate::Result<HashMap<SchemaObjectId, SchemaObject>> {
+    let sql = "with user_namespaces as (
+    select oid, nspname
+    from pg_namespace
+    where nspname not in ('pg_catalog', 'information_schema')
+      and nspname not like 'pg_toast%'
+      and nspname not like 'pg_temp%'
+), extension_members as (
+    select classid, objid
+    from pg_depend
+    where deptype = 'e'
+), sequence_definitions as (
+    select s.seqrelid,
+        format('%s start %s increment %s minvalue %s maxvalue %s%s', format_type(s.seqtypid, null),
+            s.seqstart, s.seqincrement, s.seqmin, s.seqmax,
+            case when s.seqcycle then ' cycle' else '' end)
+        || coalesce((
+            select ' owned by ' || quote_ident(owner.relname) || '.' || quote_ident(a.attname)
+            from pg_depend d
+                join pg_class owner on d.refobjid = owner.oid
+                join pg_attribute a on a.attrelid = owner.oid and a.attnum = d.refobjsubid
+            where d.classid = 'pg_class'::regclass
+              and d.objid = s.seqrelid
+              and d.refclassid = 'pg_class'::regclass
+              and d.deptype = 'a'
+        ), '') as definition
+    from pg_sequence s
+    where $1::oid[] is null or s.seqrelid = any($1)
+)
+select 'view'::text as kind, c.oid, n.nspname::text as schema_name, c.relname::text as name,
+    c.ctid::text || r.ctid::text as version, c.oid as lock_oid
+from pg_class c
+    join user_namespaces n on c.relnamespace = n.oid
+    join pg_rewrite r on r.ev_class = c.oid and r.rulename = '_RETURN'
+where c.relkind in ('v', 'm')
+  and ($1::oid[] is null or c.oid = any($1))
+  and (c.oid, 'pg_class'::regclass) not in (select objid, classid from extension_members)
+union all
+select 'function', p.oid, n.nspname::text,
+    p.proname || '(' || pg_get_function_identity_arguments(p.oid) || ')', p.ctid::text, p.oid
+from pg_proc p
+    join user_namespaces n on p.pronamespace = n.oid
+where ($1::oid[] is null or p.oid = any($1)
+       or age(p.xmin) <= age((txid_current_if_assigned() % 4294967296)::text::xid))
+  and (p.oid, 'pg_proc'::regclass) not in (select objid, classid from extension_members)
+union all
+select 'trigger', t.oid, n.nspname::text, t.tgname || ' on ' || c.relname,
+    t.ctid::text || c.ctid::text, c.oid
+from pg_trigger t
+    join pg_class c on t.tgrelid = c.oid
+    join user_namespaces n on c.relnamespace = n.oid
+where not t.tgisinternal
+  and ($1::oid[] is null or c.oid = any($1))
+union all
+select 'sequence', c.oid, n.nspname::text, c.relname::text, s.definition, c.oid
+from sequence_definitions s
+    join pg_class c on s.seqrelid = c.oid
+    join user_namespaces n on c.relnamespace = n.oid
+where not exists (
+    select from pg_depend d
+    where d.classid = 'pg_class'::regclass and d.objid = c.oid and d.deptype = 'i'
+) and (c.oid, 'pg_class'::regclass) not in (select objid, classid from extension_members);
+";
+    let known_by_oid: HashMap<_, _> = known
+        .values()
+        .map(|obj| ((obj.kind, obj.oid), obj))
+        .collect();
+    let rows = tx
+        .query(sql, &[&scope])
+        .with_context("Failed to fetch views, functions, triggers and sequences")?;
+    let mut fetched = rows
+        .into_iter()
+        .map(|row| {
+            let kind: String = row.try_get(0)?;
+            let kind = SchemaObjectKind::from_str(&kind)?;
+            let version: String = row.try_get(4)?;
+            Ok(SchemaObject {
+                kind,
+                oid: row.try_get(1)?,
+                schema_name: row.try_get(2)?,
+                name: row.try_get(3)?,
+                // The version of a sequence is its definition
+                definition: match kind {
+                    SchemaObjectKind::Sequence => version.clone(),
+                    _ => String::new(),
+                },
+                version,
+                lock_oid: row.try_get(5)?,
+            })
+        })
+        .collect::<crate::Result<Vec<_>>>()?;
+
+    let mut render = vec![];
+    for obj in fetched.iter_mut() {
+        match known_by_oid.get(&(obj.kind, obj.oid)) {
+            Some(known) if known.version == obj.version => {
+                obj.definition = known.definition.clone()
+            }
+            _ if obj.kind != SchemaObjectKind::Sequence => render.push((obj.kind, obj.oid)),
+            _ => {}
+        }
+    }
+    if !render.is_empty() {
+        let definitions = fetch_schema_object_definitions(tx, &render)?;
+        for obj in fetched.iter_mut() {
+            if let Some(definition) = definitions.get(&(obj.kind, obj.oid)) {
+                obj.definition = definition.clone();
+            }
+        }
+    }
+
+    let fetched_oids: HashSet<_> = fetched.iter().map(|obj| (obj.kind, obj.oid)).collect();
+    let unchanged = known.values().filter(|obj| {
+        scope.is_some_and(|scope| !scope.contains(&obj.lock_oid))
+            && !fetched_oids.contains(&(obj.kind, obj.oid))
+    });
+    Ok(unchanged
+        .cloned()
+        .chain(fetched)
+        .map(|obj| ((obj.kind, obj.schema_name.clone(), obj.name.clone()), obj))
+        .collect())
+}
+
+/// Render the definitions of views, functions and triggers in `objects`
+fn fetch_schema_object_definitions(
+    tx: &mut Transaction,
+    objects: &[(SchemaObjectKind, Oid)],
+) -> crate::Result<HashMap<(SchemaObjectKind, Oid), String>> {
+    let sql = "select k.kind, k.oid,
+    case
+        when k.kind = 'view' then pg_get_viewdef(k.oid)
+        when k.kind = 'function' then (
+            select case when p.prokind in ('f', 'p') then pg_get_functiondef(p.oid) else p.prosrc end
+            from pg_proc p where p.oid = k.oid
+        )
+        when k.kind = 'trigger' then (
+            select pg_get_triggerdef(t.oid) || case t.tgenabled when 'D' then ' (disabled)' else '' end
+            from pg_trigger t where t.oid = k.oid
+        )
+    end as definition
+from unnest($1::text[], $2::oid[]) as k(kind, oid);
+";
+    let kinds: Vec<_> = objects.iter().map(|(kind, _)| kind.as_str()).collect();
+    let oids: Vec<_> = objects.iter().map(|(_, oid)| *oid).collect();
+    let rows = tx
+        .query(sql, &[&kinds, &oids])
+        .with_context("Failed to render definitions of views, functions and triggers")?;
+    rows.into_iter()
+        .map(|row| {
+            let kind: String = row.try_get(0)?;
+            let definition: Option<String> = row.try_get(2)?;
+            Ok((
+                (SchemaObjectKind::from_str(&kind)?, row.try_get(1)?),
+                definition.unwrap_or_default(),
+            ))
+        })
+        .collect()
+}
+
+/// Fetch all indexes that are not valid, so they are maintained on writes but not used by queries
+pub fn invalid_indexes(client: &mut impl GenericClient) -> crate::Result<Vec<InvalidIndex>> {
+    let sql = "select
+    pg_namespace.nspname as schema_name,
+    index_class.relname as index_name,
+    table_class.relname as table_name,
+    index_class.relkind = 'I' as partitioned
+from pg_index
+    join pg_class index_class on pg_index.indexrelid = index_class.oid
+    join pg_class table_class on pg_index.indrelid = table_class.oid
+    join pg_namespace on index_class.relnamespace = pg_namespace.oid
+where not pg_index.indisvalid
+    and pg_namespace.nspname not in ('pg_catalog', 'information_schema')
+order by schema_name, index_name;
+";
+    let rows = client
+        .query(sql, &[])
+        .with_context("Failed to fetch invalid indexes")?;
+    rows.into_iter()
+        .map(|row| {
+            Ok(InvalidIndex {
+                schema_name: row.try_get(0)?,
+                index_name: row.try_get(1)?,
+                table_name: row.try_get(2)?,
+                partitioned: row.try_get(3)?,
+            })
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::generate_new_test_db;
-    use postgres::{Client, NoTls};
+    use crate::{connect_to_test_db, generate_new_test_db};
 
     #[test]
     fn test_fks_missing_index() {
-        let test_db = generate_new_test_db();
-        let mut client = Client::connect(
-            format!("host=localhost dbname={test_db} password=postgres user=postgres").as_str(),
-            NoTls,
-        )
-        .unwrap();
+        let mut client = connect_to_test_db(&generate_new_test_db());
         let script = include_str!("../../examples/E15/bad.sql");
         client.batch_execute(script).unwrap();
         let fks = fks_missing_index(&mut client.transaction().unwrap()).unwrap();
@@ -384,4 +1020,25 @@ mod tests {
                 && fk.columns == vec!["item".to_string()]
                 && fk.table_name.as_str() == "purchase"))
     }
+
+    #[test]
+    fn test_invalid_index_after_failed_concurrent_build() {
+        let mut client = connect_to_test_db(&generate_new_test_db());
+        client
+            .batch_execute("insert into books (title) values ('dup'), ('dup');")
+            .unwrap();
+        assert!(client
+            .batch_execute("create unique index concurrently books_title_key on books (title);")
+            .is_err());
+        let invalid = invalid_indexes(&mut client).unwrap();
+        assert_eq!(
+            invalid,
+            vec![InvalidIndex {
+                schema_name: "public".to_string(),
+                index_name: "books_title_key".to_string(),
+                table_name: "books".to_string(),
+                partitioned: false,
+            }]
+        );
+    }
 }
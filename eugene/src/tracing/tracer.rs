@@ -1,20 +1,24 @@
 use fxhash::{FxHashMap as HashMap, FxHashSet as HashSet};
 use std::time::{Duration, Instant};
 
-use crate::comments::{filter_rules, find_comment_action};
+use crate::comments::{find_comment_action, LintAction};
 use crate::error::ContextualError;
 use chrono::{DateTime, Utc};
 use itertools::Itertools;
 use postgres::types::Oid;
-use postgres::Transaction;
+use postgres::{Client, GenericClient, Transaction};
 
-use crate::hint_data::HintId;
-use crate::hints;
 use crate::output::output_format::Hint;
 use crate::pg_types::locks::{Lock, LockableTarget};
+use crate::pg_types::relkinds::RelKind;
+use crate::rule_set::RuleSet;
+use crate::sqltext::{transaction_control, TransactionControl};
+use crate::tracing::monitor::{LockMonitor, LockWait, Samples};
+use crate::tracing::probes::{ProbeResult, Prober};
 use crate::tracing::queries;
 use crate::tracing::queries::{
-    ColumnIdentifier, ColumnMetadata, Constraint, ForeignKeyReference, RelfileId,
+    ColumnIdentifier, ColumnMetadata, Constraint, ForeignKeyReference, InvalidIndex, RelfileId,
+    SchemaObject, SchemaObjectId,
 };
 
 /// A trace of a single SQL statement, including the locks taken and the duration of the statement.
@@ -32,14 +36,26 @@ pub struct SqlStatementTrace {
     pub(crate) added_columns: Vec<(ColumnIdentifier, ColumnMetadata)>,
     /// Columns that were modified
     pub(crate) modified_columns: Vec<(ColumnIdentifier, ModifiedColumn)>,
+    /// Columns that were dropped, along with their metadata before the statement
+    pub(crate) dropped_columns: Vec<(ColumnIdentifier, ColumnMetadata)>,
     /// Constraints that were added
     pub(crate) added_constraints: Vec<Constraint>,
     /// Constraints that were modified
     pub(crate) modified_constraints: Vec<(Oid, ModifiedConstraint)>,
     /// Database objects that were created by this statement
     pub(crate) created_objects: Vec<LockableTarget>,
+    /// Views, functions, triggers and sequences that were created by this statement
+    pub(crate) added_schema_objects: Vec<SchemaObject>,
+    /// Views, functions, triggers and sequences that were replaced or altered by this statement
+    pub(crate) modified_schema_objects: Vec<ModifiedSchemaObject>,
+    /// Views, functions, triggers and sequences that were dropped by this statement
+    pub(crate) dropped_schema_objects: Vec<SchemaObject>,
     /// The `lock_timeout` that was active in postgres when `sql` started to execute
     pub(crate) lock_timeout_millis: u64,
+    /// Rows inserted, updated or deleted by the statement
+    pub(crate) rows_written: u64,
+    /// Bytes of write-ahead log written by the statement
+    pub(crate) bytes_written: u64,
 
     /// Rewritten database objects
     pub(crate) rewritten_objects: Vec<RelfileId>,
@@ -48,6 +64,18 @@ pub struct SqlStatementTrace {
 
     /// Foreign keys that had no index at the end of the statement
     pub(crate) fks_missing_index: Vec<ForeignKeyReference>,
+    /// Indexes that were not valid at the end of the statement
+    pub(crate) invalid_indexes: Vec<InvalidIndex>,
+    /// Probe queries that ran against locked tables from another connection after the statement
+    pub(crate) probes: Vec<ProbeResult>,
+    /// Locks that were released by this statement, by `ROLLBACK TO SAVEPOINT`
+    pub(crate) released_locks: Vec<Lock>,
+    /// True if a later `ROLLBACK TO SAVEPOINT` undid this statement
+    pub(crate) rolled_back: bool,
+    /// Phases of a concurrent index build that were seen while the statement ran
+    pub(crate) phases: Vec<String>,
+    /// Locks the statement waited for, seen from a monitoring connection
+    pub(crate) lock_waits: Vec<LockWait>,
 }
 
 #[derive(Eq, PartialEq, Debug, Clone)]
@@ -62,6 +90,34 @@ pub struct ModifiedConstraint {
     pub(crate) new: Constraint,
 }
 
+impl ModifiedColumn {
+    /// The column as it was before the statement
+    pub fn before(&self) -> &ColumnMetadata {
+        &self.old
+    }
+    /// The column as it was after the statement
+    pub fn after(&self) -> &ColumnMetadata {
+        &self.new
+    }
+}
+
+impl ModifiedConstraint {
+    /// The constraint as it was before the statement
+    pub fn before(&self) -> &Constraint {
+        &self.old
+    }
+    /// The constraint as it was after the statement
+    pub fn after(&self) -> &Constraint {
+        &self.new
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct ModifiedSchemaObject {
+    pub(crate) old: SchemaObject,
+    pub(crate) new: SchemaObject,
+}
+
 /// A trace of a transaction, including all SQL statements executed and the locks taken by each one.
 #[derive(Eq, PartialEq, Debug, Clone)]
 pub struct TxLockTracer<'a> {
@@ -82,8 +138,12 @@ pub struct TxLockTracer<'a> {
     pub(crate) columns: HashMap<ColumnIdentifier, ColumnMetadata>,
     /// All constraints in the database
     pub(crate) constraints: HashMap<Oid, Constraint>,
+    /// All views, functions, triggers and sequences in the database
+    pub(crate) schema_objects: HashMap<SchemaObjectId, SchemaObject>,
     /// Is the trace from one or more `CONCURRENTLY` statements that must run outside transactions?
     pub(crate) concurrent: bool,
+    /// Should statements record the rows and bytes of write-ahead log they wrote?
+    pub(crate) track_writes: bool,
 
     /// Database objects that have been created in the transaction
     pub(crate) created_objects: HashSet<Oid>,
@@ -92,6 +152,38 @@ pub struct TxLockTracer<'a> {
     pub(crate) relfile_ids: HashMap<Oid, u32>,
     /// Hint ids to ignore across all statements
     pub(crate) ignored_hints: &'a [&'a str],
+    /// The hints to check each statement against
+    pub(crate) rules: &'a RuleSet,
+    /// Open savepoints, with the index of the `SAVEPOINT` statement in `statements`
+    pub(crate) savepoints: Vec<(String, usize)>,
+    /// True if the script ended the transaction with `ROLLBACK`
+    pub(crate) rolled_back: bool,
+}
+
+/// How a statement ran, before its effects on the catalog are recorded
+struct Execution {
+    executed: bool,
+    start_time: Instant,
+    duration: Duration,
+    lock_timeout_millis: u64,
+    /// All relevant locks held after the statement, or while it ran if it was concurrent
+    locks_held: HashSet<Lock>,
+    /// All relations locked by the transaction, including new ones, or `None` if not known
+    /// for certain, in which case the whole catalog is checked for changes
+    locked_relations: Option<HashSet<Oid>>,
+    rows_written: u64,
+    bytes_written: u64,
+    probes: Vec<ProbeResult>,
+    phases: Vec<String>,
+    lock_waits: Vec<LockWait>,
+}
+
+fn execute(client: &mut impl GenericClient, sql: &str) -> crate::Result<()> {
+    client.execute(sql, &[]).map_err(|err| {
+        let context = format!("Error while executing SQL statement: {err:?}: {sql}");
+        err.with_context(context)
+    })?;
+    Ok(())
 }
 
 pub struct StatementCtx<'a> {
@@ -100,23 +192,60 @@ pub struct StatementCtx<'a> {
 }
 
 impl StatementCtx<'_> {
+    /// The SQL text of the statement
+    pub fn sql(&self) -> &str {
+        &self.sql_statement_trace.sql
+    }
+    /// The line number of the statement in the script
+    pub fn line_number(&self) -> usize {
+        self.sql_statement_trace.line_no
+    }
+    /// True if the statement was traced with `CONCURRENTLY` outside of a transaction
+    pub fn is_concurrent(&self) -> bool {
+        self.transaction.concurrent
+    }
     pub fn new_constraints(&self) -> impl Iterator<Item = &Constraint> {
         self.sql_statement_trace.added_constraints.iter()
     }
     pub fn altered_columns(&self) -> impl Iterator<Item = &(ColumnIdentifier, ModifiedColumn)> {
         self.sql_statement_trace.modified_columns.iter()
     }
+    /// Columns added by this statement, including the columns of new tables
+    pub fn added_columns(&self) -> impl Iterator<Item = &(ColumnIdentifier, ColumnMetadata)> {
+        self.sql_statement_trace.added_columns.iter()
+    }
+    /// True if the relation existed before the transaction started, so other transactions use it
+    pub fn existed_before_transaction(&self, oid: Oid) -> bool {
+        self.transaction.initial_objects.contains(&oid)
+    }
     pub fn new_columns(&self) -> impl Iterator<Item = &ColumnMetadata> {
         self.sql_statement_trace
             .added_columns
             .iter()
             .map(|(_, col)| col)
     }
+    /// Columns dropped by this statement or earlier statements in the same transaction
+    pub fn dropped_columns_in_transaction(&self) -> impl Iterator<Item = &ColumnMetadata> {
+        self.transaction
+            .statements
+            .iter()
+            .filter(|st| !st.rolled_back)
+            .chain([self.sql_statement_trace])
+            .flat_map(|st| st.dropped_columns.iter().map(|(_, col)| col))
+    }
+    /// Locks on relations and rows held at the start of the statement
     pub fn locks_at_start(&self) -> impl Iterator<Item = &Lock> {
-        self.transaction.all_locks.iter()
+        self.transaction
+            .all_locks
+            .iter()
+            .filter(|lock| lock.lock_target().relation_oid().is_some())
     }
+    /// Locks on relations and rows taken by the statement
     pub fn new_locks_taken(&self) -> impl Iterator<Item = &Lock> {
-        self.sql_statement_trace.locks_taken.iter()
+        self.sql_statement_trace
+            .locks_taken
+            .iter()
+            .filter(|lock| lock.lock_target().relation_oid().is_some())
     }
     pub fn new_objects(&self) -> impl Iterator<Item = &LockableTarget> {
         self.sql_statement_trace.created_objects.iter()
@@ -124,6 +253,25 @@ impl StatementCtx<'_> {
     pub fn lock_timeout_millis(&self) -> u64 {
         self.sql_statement_trace.lock_timeout_millis
     }
+    /// How long the statement ran
+    pub fn duration(&self) -> Duration {
+        self.sql_statement_trace.duration
+    }
+    /// How long `lock` has been held at the end of the statement, from the start of the statement
+    /// that took it. Time spent by eugene between statements is not counted.
+    pub fn held_for(&self, lock: &Lock) -> Duration {
+        let statements = &self.transaction.statements;
+        let held_before = match statements
+            .iter()
+            .rposition(|st| st.locks_taken.contains(lock))
+        {
+            Some(ix) if self.transaction.all_locks.contains(lock) => {
+                statements[ix..].iter().map(|st| st.duration).sum()
+            }
+            _ => Duration::ZERO,
+        };
+        held_before + self.sql_statement_trace.duration
+    }
     pub fn constraints_on(&self, oid: Oid) -> impl Iterator<Item = &Constraint> {
         self.transaction
             .constraints
@@ -141,30 +289,194 @@ impl<'a> TxLockTracer<'a> {
         self.triggered_hints.iter().all(|hints| hints.is_empty())
     }
     /// Trace a single SQL statement, recording the locks taken and the duration of the statement.
+    ///
+    /// If `monitor` is provided, it records the locks the statement waited for while it ran.
     pub fn trace_sql_statement(
         &mut self,
         tx: &mut Transaction,
         sql: (usize, &str),
         skip_this: bool,
         final_checks: bool,
+        prober: Option<&mut Prober>,
+        monitor: Option<&mut LockMonitor>,
     ) -> crate::Result<()> {
-        // TODO: This is too big and should be refactored into more manageable pieces
+        let write_position = if self.track_writes {
+            Some(queries::fetch_write_position(tx)?)
+        } else {
+            None
+        };
+        let monitor = match monitor {
+            Some(monitor) if !skip_this => {
+                let pid = monitor.traced_pid(tx)?;
+                Some((monitor, pid))
+            }
+            _ => None,
+        };
         let start_time = Instant::now();
-        let oid_vec = self.initial_objects.iter().copied().collect_vec();
-        let lock_timeout = queries::get_lock_timeout(tx)?;
-        if !skip_this {
-            tx.execute(sql.1, &[]).map_err(|err| {
-                let context = format!("Error while executing SQL statement: {err:?}: {}", sql.1);
-                err.with_context(context)
-            })?;
-        }
+        let lock_timeout_millis = queries::get_lock_timeout(tx)?;
+        let lock_waits = match monitor {
+            Some((monitor, pid)) => monitor.sample_while(pid, || execute(tx, sql.1))?.1.waits,
+            None => {
+                if !skip_this {
+                    execute(tx, sql.1)?;
+                }
+                vec![]
+            }
+        };
         let duration = start_time.elapsed();
-        let locks_taken =
-            queries::find_relevant_locks_in_current_transaction(tx, &self.initial_objects)?;
-        let new_locks = queries::find_new_locks(&self.all_locks, &locks_taken);
+        let (rows_written, bytes_written) = match &write_position {
+            Some(position) => queries::written_since(tx, position)?,
+            None => (0, 0),
+        };
+        let current_locks = queries::query_pg_locks_in_current_transaction(tx)?;
+        let locked_relations = queries::locked_relations(tx)?;
+        let locks_held = queries::relevant_locks(current_locks, &self.initial_objects);
+        // Statements that are skipped or ignored with `-- eugene: ignore` are not probed
+        let ignored = find_comment_action(sql.1)? == LintAction::SkipAll;
+        let probes = match prober {
+            Some(prober) if !skip_this && !ignored => {
+                let locked_tables = locks_held
+                    .iter()
+                    .filter_map(|lock| lock.relation())
+                    .filter(|target| {
+                        matches!(target.rel_kind, RelKind::Table | RelKind::PartitionedTable)
+                    })
+                    .unique_by(|target| target.oid)
+                    .sorted_by_key(|target| (&target.schema, &target.object_name));
+                prober.probe(locked_tables)?
+            }
+            _ => vec![],
+        };
+        let execution = Execution {
+            executed: !skip_this,
+            start_time,
+            duration,
+            lock_timeout_millis,
+            locks_held,
+            locked_relations: Some(locked_relations),
+            rows_written,
+            bytes_written,
+            probes,
+            phases: vec![],
+            lock_waits,
+        };
+        self.record_statement(tx, sql, final_checks, execution)
+    }
+
+    /// Trace a `CONCURRENTLY` statement, that must run outside of a transaction.
+    ///
+    /// The statement runs on `client`, while `monitor` samples the locks it holds from another
+    /// connection. Locks taken by a concurrent statement are released when it is done.
+    pub fn trace_concurrent_statement(
+        &mut self,
+        client: &mut Client,
+        monitor: &mut LockMonitor,
+        sql: (usize, &str),
+        skip_this: bool,
+        final_checks: bool,
+    ) -> crate::Result<()> {
+        let (write_position, lock_timeout_millis) = {
+            let mut tx = client.transaction()?;
+            let write_position = if self.track_writes {
+                Some(queries::fetch_write_position(&mut tx)?)
+            } else {
+                None
+            };
+            (write_position, queries::get_lock_timeout(&mut tx)?)
+        };
+        let pid = monitor.traced_pid(client)?;
+        let start_time = Instant::now();
+        let samples = if skip_this {
+            Samples::default()
+        } else {
+            match monitor.sample_while(pid, || execute(client, sql.1)) {
+                Ok((_, samples)) => samples,
+                Err(err) => {
+                    let invalid = queries::invalid_indexes(client)?
+                        .into_iter()
+                        .map(|index| format!("{}.{}", index.schema_name, index.index_name))
+                        .join(", ");
+                    return Err(if invalid.is_empty() {
+                        err
+                    } else {
+                        err.with_context(format!(
+                            "Invalid indexes are left behind, drop them with DROP INDEX CONCURRENTLY before retrying: {invalid}"
+                        ))
+                    });
+                }
+            }
+        };
+        let duration = start_time.elapsed();
+        let mut tx = client.transaction()?;
+        let bytes_written = match &write_position {
+            Some(position) => queries::written_since(&mut tx, position)?.1,
+            None => 0,
+        };
+        let execution = Execution {
+            executed: !skip_this,
+            start_time,
+            duration,
+            lock_timeout_millis,
+            locks_held: queries::relevant_locks(samples.locks, &self.initial_objects),
+            // Samples can miss short-lived locks, so the whole catalog must be checked
+            locked_relations: None,
+            // Row counts are per transaction, and the statement ran in its own
+            rows_written: 0,
+            bytes_written,
+            probes: vec![],
+            phases: samples.phases,
+            lock_waits: samples.waits,
+        };
+        self.record_statement(&mut tx, sql, final_checks, execution)?;
+        self.all_locks.clear();
+        Ok(())
+    }
+
+    /// Record the effects of a statement that ran, by comparing the catalog in `tx` to the catalog
+    /// before the statement, and check it against the hints.
+    fn record_statement(
+        &mut self,
+        tx: &mut Transaction,
+        sql: (usize, &str),
+        final_checks: bool,
+        execution: Execution,
+    ) -> crate::Result<()> {
+        let locks_held = execution.locks_held;
+        let new_locks = queries::find_new_locks(&self.all_locks, &locks_held);
+        let savepoint_action = if execution.executed {
+            transaction_control(sql.1)
+        } else {
+            None
+        };
+        let rolled_back_to = matches!(savepoint_action, Some(TransactionControl::RollbackTo(_)));
+        let released_locks: Vec<_> = if rolled_back_to {
+            self.all_locks
+                .iter()
+                .filter(|lock| !locks_held.contains(lock))
+                .cloned()
+                .collect()
+        } else {
+            vec![]
+        };
+        // Statements can only change relations that the transaction holds a lock on, or did before
+        // a `ROLLBACK TO SAVEPOINT`, so only those need to be checked.
+        let changed_scope: HashSet<Oid> = match &execution.locked_relations {
+            Some(locked) => locked
+                .iter()
+                .copied()
+                .chain(
+                    self.all_locks
+                        .iter()
+                        .filter_map(|lock| lock.lock_target().relation_oid()),
+                )
+                .filter(|oid| self.initial_objects.contains(oid))
+                .collect(),
+            None => self.initial_objects.clone(),
+        };
+        let oid_vec = changed_scope.iter().copied().collect_vec();
         let relfile_ids = queries::fetch_all_rel_file_ids(tx, &oid_vec)?;
 
-        let changed_ids: Vec<_> = relfile_ids
+        let mut changed_ids: Vec<_> = relfile_ids
             .into_iter()
             .filter(|(oid, id)| self.relfile_ids.get(oid) != Some(&id.relfilenode))
             .map(|(_, id)| id)
@@ -190,7 +502,17 @@ impl<'a> TxLockTracer<'a> {
                 added_columns.push((*col_id, col.clone()));
             }
         }
-        self.columns = columns;
+        let mut dropped_columns: Vec<_> = self
+            .columns
+            .iter()
+            .filter(|(col_id, _)| {
+                changed_scope.contains(&col_id.oid) && !columns.contains_key(col_id)
+            })
+            .map(|(col_id, col)| (*col_id, col.clone()))
+            .collect();
+        self.columns
+            .retain(|col_id, _| !changed_scope.contains(&col_id.oid));
+        self.columns.extend(columns);
 
         let constraints = queries::fetch_constraints(tx, &oid_vec)?;
         let mut added_constraints = Vec::new();
@@ -211,25 +533,98 @@ impl<'a> TxLockTracer<'a> {
                 added_constraints.push(con.clone());
             }
         }
-        self.constraints = constraints;
-        let new_objects: Vec<_> = queries::fetch_lockable_objects(tx, &oid_vec)?
+        self.constraints.retain(|_, con| {
+            !changed_scope.contains(&con.target)
+                && !con
+                    .fk_target
+                    .is_some_and(|oid| changed_scope.contains(&oid))
+        });
+        self.constraints.extend(constraints);
+
+        // Views, triggers and sequences change along with locks on their relations, and functions
+        // are locked when they are altered or dropped. Without locks, or after undoing changes
+        // with `ROLLBACK TO`, check all objects.
+        let schema_scope = match &execution.locked_relations {
+            Some(_) if !rolled_back_to => Some(queries::locked_schema_object_oids(tx)?),
+            _ => None,
+        };
+        let schema_objects =
+            queries::fetch_schema_objects(tx, &self.schema_objects, schema_scope.as_deref())?;
+        let mut added_schema_objects = Vec::new();
+        let mut modified_schema_objects = Vec::new();
+        for (id, obj) in schema_objects.iter().sorted_by_key(|(id, _)| *id) {
+            match self.schema_objects.get(id) {
+                Some(pre_existing) if pre_existing.definition != obj.definition => {
+                    modified_schema_objects.push(ModifiedSchemaObject {
+                        old: pre_existing.clone(),
+                        new: obj.clone(),
+                    })
+                }
+                Some(_) => {}
+                None => added_schema_objects.push(obj.clone()),
+            }
+        }
+        let mut dropped_schema_objects: Vec<_> = self
+            .schema_objects
+            .iter()
+            .filter(|(id, _)| !schema_objects.contains_key(*id))
+            .sorted_by_key(|(id, _)| *id)
+            .map(|(_, obj)| obj.clone())
+            .collect();
+        self.schema_objects = schema_objects;
+        // New relations are locked by the transaction that creates them
+        let new_objects = match &execution.locked_relations {
+            Some(locked) => {
+                let candidates = locked
+                    .iter()
+                    .filter(|oid| {
+                        !self.initial_objects.contains(oid) && !self.created_objects.contains(oid)
+                    })
+                    .copied()
+                    .collect_vec();
+                queries::fetch_lockable_objects_by_oid(tx, &candidates)?
+            }
+            None => {
+                let initial = self.initial_objects.iter().copied().collect_vec();
+                queries::fetch_lockable_objects(tx, &initial)?
+            }
+        };
+        let new_objects: Vec<_> = new_objects
             .into_iter()
             .filter(|target| !self.created_objects.contains(&target.oid))
             .collect();
         self.created_objects
             .extend(new_objects.iter().map(|obj| obj.oid));
+        if rolled_back_to {
+            // Changes undone by the rollback are not new changes made by this statement
+            added_columns.clear();
+            modified_columns.clear();
+            dropped_columns.clear();
+            added_constraints.clear();
+            modified_constraints.clear();
+            added_schema_objects.clear();
+            modified_schema_objects.clear();
+            dropped_schema_objects.clear();
+            changed_ids.clear();
+        }
 
         let statement = SqlStatementTrace {
             sql: sql.1.to_string(),
             locks_taken: new_locks.into_iter().collect(),
-            start_time,
-            duration,
+            start_time: execution.start_time,
+            duration: execution.duration,
             added_columns,
             modified_columns,
+            dropped_columns,
             added_constraints,
             modified_constraints,
             created_objects: new_objects,
-            lock_timeout_millis: lock_timeout,
+            added_schema_objects,
+            modified_schema_objects,
+            dropped_schema_objects,
+            lock_timeout_millis: execution.lock_timeout_millis,
+            rows_written: execution.rows_written,
+            bytes_written: execution.bytes_written,
             rewritten_objects: changed_ids,
             line_no: sql.0,
             fks_missing_index: if final_checks {
@@ -237,26 +632,67 @@ impl<'a> TxLockTracer<'a> {
             } else {
                 Vec::new()
             },
+            invalid_indexes: if final_checks {
+                queries::invalid_indexes(tx)?
+            } else {
+                Vec::new()
+            },
+            probes: execution.probes,
+            released_locks,
+            rolled_back: false,
+            phases: execution.phases,
+            lock_waits: execution.lock_waits,
         };
         let ctx = StatementCtx {
             sql_statement_trace: &statement,
             transaction: self,
         };
         let hint_action = find_comment_action(sql.1)?;
-        let hints: Vec<_> = filter_rules(
-            &hint_action,
-            hints::all_hints()
-                .iter()
-                .filter(|hint| !self.ignored_hints.contains(&hint.id())),
-        )
-        .filter_map(|hint| hint.check(&ctx))
-        .collect();
+        // `ROLLBACK TO` only releases locks, so hints about the locks it holds do not apply
+        let hints = if rolled_back_to {
+            vec![]
+        } else {
+            self.rules
+                .trace_statement(&hint_action, self.ignored_hints, &ctx)
+        };
 
         self.triggered_hints.push(hints);
         self.statements.push(statement);
-        self.all_locks.extend(locks_taken.iter().cloned());
+        if rolled_back_to {
+            self.all_locks = locks_held;
+        } else {
+            self.all_locks.extend(locks_held);
+        }
+        if let Some(action) = savepoint_action {
+            self.track_savepoint(action);
+        }
         Ok(())
     }
+
+    /// Update open savepoints after the last statement, marking statements undone by `ROLLBACK TO`
+    fn track_savepoint(&mut self, action: TransactionControl) {
+        let current = self.statements.len() - 1;
+        match action {
+            TransactionControl::Savepoint(name) => self.savepoints.push((name, current)),
+            TransactionControl::Release(name) => {
+                if let Some(pos) = self.savepoints.iter().rposition(|(n, _)| *n == name) {
+                    self.savepoints.truncate(pos);
+                }
+            }
+            TransactionControl::RollbackTo(name) => {
+                if let Some(pos) = self.savepoints.iter().rposition(|(n, _)| *n == name) {
+                    let start = self.savepoints[pos].1 + 1;
+                    for statement in &mut self.statements[start..current] {
+                        statement.rolled_back = true;
+                    }
+                    self.savepoints.truncate(pos + 1);
+                }
+            }
+            TransactionControl::Begin
+            | TransactionControl::Commit
+            | TransactionControl::Rollback => {}
+        }
+    }
     /// Start a new lock tracing session.
     ///
     /// # Parameters
@@ -264,15 +700,20 @@ impl<'a> TxLockTracer<'a> {
     /// * `trace_targets` - The typically `Oid` of relations visible to other transactions.
     /// * `columns` - Initial columns in the database, to track changes.
     /// * `constraints` - Initial constraints in the database, to track changes.
+    /// * `schema_objects` - Initial views, functions, triggers and sequences, to track changes.
     /// * `relfile_ids` - Initial relation file IDs in the database, to track changes.
     /// * `ignored_hints` - Hints to ignore across all statements.
+    /// * `rules` - Hints to check each statement against.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         name: Option<String>,
         trace_targets: HashSet<Oid>,
         columns: HashMap<ColumnIdentifier, ColumnMetadata>,
         constraints: HashMap<Oid, Constraint>,
+        schema_objects: HashMap<SchemaObjectId, SchemaObject>,
         relfile_ids: HashMap<Oid, u32>,
         ignored_hints: &'a [&'a str],
+        rules: &'a RuleSet,
     ) -> Self {
         Self {
             name,
@@ -282,58 +723,16 @@ impl<'a> TxLockTracer<'a> {
             trace_start: Utc::now(),
             columns,
             constraints,
+            schema_objects,
             concurrent: false,
+            track_writes: false,
             created_objects: Default::default(),
             triggered_hints: vec![],
             relfile_ids,
             ignored_hints,
+            rules,
+            savepoints: vec![],
+            rolled_back: false,
         }
     }
-
-    /// Start a new lock tracing session for a `CONCURRENTLY` statement.
-    ///
-    /// # Parameters
-    /// * `name` - The name of the transaction, typically the file name.
-    /// * `statements` - The SQL statements to trace.
-    /// * `ignored_hints` - Hints to ignore across all statements.
-    ///
-    /// This can not really do any tracing, as `CONCURRENTLY` statements must run outside transactions.
-    pub fn tracer_for_concurrently<S: AsRef<str>>(
-        name: Option<String>,
-        statements: impl Iterator<Item = (usize, S)>,
-        ignored_hints: &'a [&'a str],
-    ) -> Self {
-        let mut out = Self {
-            name,
-            initial_objects: HashSet::default(),
-            statements: statements
-                .map(|(line, s)| SqlStatementTrace {
-                    sql: s.as_ref().to_string(),
-                    locks_taken: vec![],
-                    start_time: Instant::now(),
-                    duration: Duration::from_secs(0),
-                    added_columns: vec![],
-                    modified_columns: vec![],
-                    added_constraints: vec![],
-                    modified_constraints: vec![],
-                    created_objects: vec![],
-                    lock_timeout_millis: 0,
-                    rewritten_objects: vec![],
-                    line_no: line,
-                    fks_missing_index: Vec::new(),
-                })
-                .collect(),
-            all_locks: HashSet::default(),
-            trace_start: Utc::now(),
-            columns: HashMap::default(),
-            constraints: HashMap::default(),
-            concurrent: true,
-            created_objects: Default::default(),
-            triggered_hints: vec![],
-            relfile_ids: Default::default(),
-            ignored_hints,
-        };
-        out.triggered_hints = vec![vec![]; out.statements.len()];
-        out
-    }
 }